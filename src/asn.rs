@@ -0,0 +1,182 @@
+//! Origin AS and announced prefix lookups through Team Cymru's IP to ASN mapping, which answers
+//! over plain DNS:
+//!
+//! - `14.215.184.93.origin.asn.cymru.com TXT` gives `"15133 | 93.184.215.0/24 | EU | ripencc | 2008-06-02"`
+//! - `AS15133.asn.cymru.com TXT` gives `"15133 | US | arin | 2007-03-19 | EDGECAST, US"`
+//!
+//! IPv6 addresses are asked about nibble by nibble under `origin6.asn.cymru.com`.
+
+use std::{collections::HashMap, io, net::IpAddr, sync::Mutex};
+
+use serde::Serialize;
+
+use crate::dns_client;
+use crate::dns_components::{dns_message::DnsMessage, dns_rdata::DnsRdata};
+use crate::rate_limit::RateLimiter;
+
+const TXT: u16 = 16;
+
+/// Who announces an address, according to the routing table.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OriginInfo {
+    pub asn: u32,
+    /// the most specific announced prefix covering the address, e.g. `93.184.215.0/24`
+    pub prefix: String,
+    pub country_code: String,
+    /// the regional registry the prefix was allocated by, e.g. `ripencc`
+    pub registry: String,
+    /// the AS's name, e.g. `EDGECAST, US`
+    pub name: String,
+}
+impl std::fmt::Display for OriginInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AS{}", self.asn)?;
+        if !self.name.is_empty() {
+            write!(f, " {}", self.name)?;
+        }
+        write!(f, ", announced as {}", self.prefix)
+    }
+}
+
+/// the name to ask about `ip`: its reversed octets (or nibbles for IPv6) under the origin zone
+fn origin_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let octets: Vec<String> = v4.octets().iter().rev().map(u8::to_string).collect();
+            format!("{}.origin.asn.cymru.com", octets.join("."))
+        },
+        IpAddr::V6(v6) => {
+            let nibbles: Vec<String> = v6.octets().iter().rev()
+                .flat_map(|byte| [byte & 0xf, byte >> 4])
+                .map(|nibble| format!("{nibble:x}"))
+                .collect();
+            format!("{}.origin6.asn.cymru.com", nibbles.join("."))
+        },
+    }
+}
+
+/// the `|` separated fields of the first TXT record in `msg`
+fn txt_fields(msg: &DnsMessage) -> Option<Vec<String>> {
+    msg.answers().iter().find_map(|answer| match &answer.rdata {
+        DnsRdata::TxtRecord(strings) => {
+            let text: String = strings.iter().map(|string| String::from_utf8_lossy(string)).collect();
+            Some(text.split('|').map(|field| field.trim().to_string()).collect())
+        },
+        _ => None,
+    })
+}
+
+/// `15133 | 93.184.215.0/24 | EU | ripencc | 2008-06-02`. addresses originated by several
+/// ASes list them all in the first field; the first one is used.
+fn parse_origin(fields: &[String]) -> Option<OriginInfo> {
+    let asn = fields.first()?.split_whitespace().next()?.parse().ok()?;
+    Some(OriginInfo {
+        asn,
+        prefix: fields.get(1)?.clone(),
+        country_code: fields.get(2).cloned().unwrap_or_default(),
+        registry: fields.get(3).cloned().unwrap_or_default(),
+        name: String::new(),
+    })
+}
+
+/// Looks up origins through a DNS server, remembering AS names so each is only asked for once.
+pub struct Cymru {
+    server: String,
+    names: Mutex<HashMap<u32, String>>,
+}
+impl Cymru {
+    pub fn new(server: &str) -> Self {
+        Self {
+            server: server.to_string(),
+            names: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// who announces `ip`; `Ok(None)` when it isn't routed at all
+    pub fn origin(&self, ip: IpAddr, limiter: &RateLimiter) -> Result<Option<OriginInfo>, io::Error> {
+        let msg = match dns_client::query(&self.server, &origin_name(ip), TXT, limiter) {
+            Ok(msg) => msg,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let Some(mut origin) = txt_fields(&msg).as_deref().and_then(parse_origin) else {
+            return Ok(None);
+        };
+        origin.name = self.as_name(origin.asn, limiter)?;
+        Ok(Some(origin))
+    }
+
+    /// the name of AS `asn`, from the last field of `15133 | US | arin | 2007-03-19 | EDGECAST, US`
+    fn as_name(&self, asn: u32, limiter: &RateLimiter) -> Result<String, io::Error> {
+        let names = || self.names.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(name) = names().get(&asn) {
+            return Ok(name.clone());
+        }
+        let name = match dns_client::query(&self.server, &format!("AS{asn}.asn.cymru.com"), TXT, limiter) {
+            Ok(msg) => txt_fields(&msg).and_then(|fields| fields.get(4).cloned()).unwrap_or_default(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        names().insert(asn, name.clone());
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dns;
+
+    #[test]
+    fn origin_names() {
+        assert_eq!(origin_name("93.184.215.14".parse().unwrap()), "14.215.184.93.origin.asn.cymru.com");
+        assert_eq!(
+            origin_name("2001:db8::1".parse().unwrap()),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.origin6.asn.cymru.com",
+        );
+    }
+
+    #[test]
+    fn origin_and_as_name() {
+        let (server, handle) = test_dns::serve(3, |question| {
+            let text = match question.qname.to_string().as_str() {
+                "14.215.184.93.origin.asn.cymru.com" => "15133 | 93.184.215.0/24 | EU | ripencc | 2008-06-02",
+                "15.215.184.93.origin.asn.cymru.com" => "15133 16000 | 93.184.215.0/24 | EU | ripencc | 2008-06-02",
+                "AS15133.asn.cymru.com" => "15133 | US | arin | 2007-03-19 | EDGECAST, US",
+                _ => return None,
+            };
+            Some(vec![(TXT, test_dns::txt(text))])
+        });
+        let cymru = Cymru::new(&server);
+        let limiter = RateLimiter::new(None, 1);
+
+        let origin = cymru.origin("93.184.215.14".parse().unwrap(), &limiter).unwrap().unwrap();
+        assert_eq!(origin, OriginInfo {
+            asn: 15133,
+            prefix: "93.184.215.0/24".to_string(),
+            country_code: "EU".to_string(),
+            registry: "ripencc".to_string(),
+            name: "EDGECAST, US".to_string(),
+        });
+        assert_eq!(origin.to_string(), "AS15133 EDGECAST, US, announced as 93.184.215.0/24");
+        // the AS name is only asked for once
+        let multi_origin = cymru.origin("93.184.215.15".parse().unwrap(), &limiter).unwrap().unwrap();
+        assert_eq!(multi_origin.asn, 15133);
+
+        let asked = handle.join().unwrap();
+        assert_eq!(asked, vec![
+            "14.215.184.93.origin.asn.cymru.com/16",
+            "AS15133.asn.cymru.com/16",
+            "15.215.184.93.origin.asn.cymru.com/16",
+        ]);
+    }
+
+    #[test]
+    fn unrouted_address() {
+        let (server, handle) = test_dns::serve(1, |_| None);
+        let origin = Cymru::new(&server).origin("203.0.113.9".parse().unwrap(), &RateLimiter::new(None, 1)).unwrap();
+        assert_eq!(origin, None);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+use std::{collections::BTreeMap, sync::{atomic::{AtomicUsize, Ordering}, mpsc}, thread};
+
+/// Runs `job` over every item on up to `jobs` worker threads.
+/// Results are handed to `emit` in the same order as `items`, regardless of which job finishes first.
+pub fn run_ordered<T, R, J, E>(items: &[T], jobs: usize, progress: bool, job: J, mut emit: E)
+where
+    T: Sync,
+    R: Send,
+    J: Fn(&T) -> R + Sync,
+    E: FnMut(R),
+{
+    let workers = jobs.clamp(1, items.len().max(1));
+    let next_item = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let sender = sender.clone();
+            let next_item = &next_item;
+            let job = &job;
+            scope.spawn(move || loop {
+                let index = next_item.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                if sender.send((index, job(item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender); // the receiver finishes once every worker is done
+
+        let mut pending = BTreeMap::new();
+        let mut next_to_emit = 0;
+        let mut finished = 0;
+        for (index, result) in receiver {
+            finished += 1;
+            if progress {
+                eprint!("\r[{finished}/{}]", items.len());
+            }
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&next_to_emit) {
+                emit(result);
+                next_to_emit += 1;
+            }
+        }
+        if progress {
+            eprintln!();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn results_keep_input_order() {
+        let items: Vec<u64> = (0..20).collect();
+        let mut seen = Vec::new();
+        // later items finish first, so completion order is roughly reversed
+        run_ordered(&items, 8, false, |n| {
+            thread::sleep(Duration::from_millis(20 - n));
+            n * 2
+        }, |r| seen.push(r));
+        assert_eq!(seen, items.iter().map(|n| n * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn empty_input_emits_nothing() {
+        let items: Vec<u8> = Vec::new();
+        let mut count = 0;
+        run_ordered(&items, 4, false, |n| *n, |_| count += 1);
+        assert_eq!(count, 0);
+    }
+}
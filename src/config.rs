@@ -0,0 +1,83 @@
+//! The optional configuration file at `$XDG_CONFIG_HOME/inloc/config` (or `~/.config/inloc/config`),
+//! for settings that shouldn't have to be repeated on every run, like API keys:
+//!
+//! ```text
+//! # ip-api Pro
+//! geo-key = 0123456789abcdef
+//! geo-url = https://pro.ip-api.com
+//! geo-header = X-Client: inloc
+//! ```
+//!
+//! Each line is `key = value`, and lines starting with `#` are comments. Command line flags win over the file.
+
+use std::{fs, io, path::{Path, PathBuf}};
+
+use crate::ip_locator::{self, WebConfig};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    pub web: WebConfig,
+}
+
+/// `$<variable>/inloc`, or `~/<fallback>/inloc` when the variable isn't set, following the XDG base directory spec
+pub fn xdg_dir(variable: &str, fallback: &str) -> Option<PathBuf> {
+    let base = std::env::var_os(variable)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(fallback)))?;
+    Some(base.join("inloc"))
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    Some(xdg_dir("XDG_CONFIG_HOME", ".config")?.join("config"))
+}
+
+/// reads the file at `path`; there being no file is the same as an empty one
+pub fn load(path: &Path) -> Result<Config, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse(&text).map_err(|e| format!("{}: {e}", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(format!("can't read {}: {e}", path.display())),
+    }
+}
+
+fn parse(text: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| format!("line {}: expected key = value", number + 1))?;
+        let value = value.trim().to_string();
+        match key.trim() {
+            "geo-url" => config.web.base_url = Some(value),
+            "geo-key" => config.web.api_key = Some(value),
+            "geo-header" => config.web.headers.push(ip_locator::parse_header(&value).map_err(|e| format!("line {}: {e}", number + 1))?),
+            other => return Err(format!("line {}: unknown setting {other}", number + 1)),
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_settings() {
+        let config = parse("# paid tier\n\ngeo-key = abc=123\ngeo-url=https://pro.ip-api.com\ngeo-header = X-Client: inloc\n").unwrap();
+        assert_eq!(config.web, WebConfig {
+            base_url: Some("https://pro.ip-api.com".to_string()),
+            api_key: Some("abc=123".to_string()),
+            headers: vec![("X-Client".to_string(), "inloc".to_string())],
+        });
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse("geo-key").unwrap_err(), "line 1: expected key = value");
+        assert_eq!(parse("\ngeo-colour = red").unwrap_err(), "line 2: unknown setting geo-colour");
+        assert!(parse("geo-header = no colon").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! Sends queries to a DNS server over UDP, using the codec in [`crate::dns_components`].
+//! Answers too large for UDP are asked for again over TCP.
+
+use std::{io::{self, Read, Write}, net::{TcpStream, ToSocketAddrs, UdpSocket}, time::Duration};
+
+use crate::dns_components::dns_message::{DnsMessage, EDNS_UDP_SIZE};
+use crate::rate_limit::RateLimiter;
+
+pub const DNS_SERVER: &str = "8.8.8.8:53";
+
+/// how long to wait for an answer before giving up
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// asks `server` for the records of type `qtype` (1 for A, 16 for TXT, ...) at `name`
+pub fn query(server: &str, name: &str, qtype: u16, limiter: &RateLimiter) -> Result<DnsMessage, io::Error> {
+    let request = DnsMessage::query(name, qtype);
+
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+
+    socket.connect(server)?;
+
+    limiter.wait(server);
+    socket.send(&request.to_bytes())?;
+
+    let mut buffer = [0_u8; 512];
+    let length = socket.recv(&mut buffer)?;
+
+    DnsMessage::parse(&buffer[..length])
+}
+
+/// like [`query`], but with the DNSSEC records (RRSIG, NSEC, ...) included. the response is returned
+/// whatever its RCODE, since an NXDOMAIN response holds the proof that the name doesn't exist.
+pub fn query_dnssec(server: &str, name: &str, qtype: u16, limiter: &RateLimiter) -> Result<DnsMessage, io::Error> {
+    DnsMessage::parse_any(&exchange(server, &DnsMessage::query_dnssec(name, qtype).to_bytes(), limiter)?)
+}
+
+/// sends `request` over UDP and returns the response, asking again over TCP if it's truncated
+pub fn exchange(server: &str, request: &[u8], limiter: &RateLimiter) -> Result<Vec<u8>, io::Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.connect(server)?;
+
+    limiter.wait(server);
+    socket.send(request)?;
+
+    let mut buffer = vec![0_u8; usize::from(EDNS_UDP_SIZE)];
+    let length = socket.recv(&mut buffer)?;
+    buffer.truncate(length);
+    if !DnsMessage::parse_any(&buffer)?.truncated() {
+        return Ok(buffer);
+    }
+
+    limiter.wait(server);
+    exchange_tcp(server, request)
+}
+
+/// sends `request` over TCP and returns the one response
+fn exchange_tcp(server: &str, request: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut connection = TcpConnection::open(server)?;
+    connection.send(request)?;
+    connection.receive()
+}
+
+/// A TCP connection to a DNS server, over which every message is preceded by its length
+/// (RFC 1035 section 4.2.2). Zone transfers answer a single request with a stream of messages.
+pub struct TcpConnection {
+    stream: TcpStream,
+}
+impl TcpConnection {
+    pub fn open(server: &str) -> Result<Self, io::Error> {
+        let address = server.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("can't resolve {server}")))?;
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        Ok(Self { stream })
+    }
+
+    pub fn send(&mut self, message: &[u8]) -> Result<(), io::Error> {
+        let length = u16::try_from(message.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "message too long"))?;
+        let mut framed = length.to_be_bytes().to_vec();
+        framed.extend(message);
+        self.stream.write_all(&framed)
+    }
+
+    pub fn receive(&mut self) -> Result<Vec<u8>, io::Error> {
+        let mut length = [0_u8; 2];
+        self.stream.read_exact(&mut length)?;
+        let mut message = vec![0_u8; usize::from(u16::from_be_bytes(length))];
+        self.stream.read_exact(&mut message)?;
+        Ok(message)
+    }
+}
@@ -1,5 +1,13 @@
 use std::io::{Cursor, ErrorKind, Read, self};
 
+/// the bit of Z that's still reserved
+const Z_RESERVED: u8 = 0b100;
+/// checking disabled: the client does its own validation and wants the data even if it doesn't validate
+pub const Z_CD: u8 = 0b001;
+
+/// the opcode of dynamic updates (RFC 2136)
+pub const OPCODE_UPDATE: u8 = 5;
+
 /// The header of a DNS message. Can be serialized into and out of DNS message form.
 #[derive(PartialEq, Eq, Debug)]
 pub struct DnsHeader {
@@ -41,8 +49,8 @@ impl DnsHeader {
         let ra: bool = header[3] & 1<<7 != 0;
         // Z is made of 3 bytes, directly following RA
         let z: u8 = (header[3] & !(1<<7)) >> 4;
-        // by, the way, Z should always be zero.
-        if z != 0 {
+        // by, the way, the first Z bit should always be zero. the other two are AD and CD since DNSSEC (RFC 4035).
+        if z & Z_RESERVED != 0 {
             let msg = format!("Z bits were not all zero: Z=0{z:#03b}");
             return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, msg));
         }
@@ -158,14 +166,20 @@ impl DnsHeader {
 pub fn get_error(rcode: u8) -> io::Error {
     match rcode {
         1 => io::Error::new(ErrorKind::InvalidInput, "the name server was unable to interpret the query"),
-        2 => io::Error::new(ErrorKind::Other, "the server failed to process the request"),
+        2 => io::Error::other("the server failed to process the request"),
         3 => io::Error::new(ErrorKind::NotFound, "no corresponding DNS record found"),
         4 => io::Error::new(ErrorKind::Unsupported, "the server does not support the request"),
-        5 => io::Error::new(ErrorKind::Other, "the server refused to perform the request"),
+        5 => io::Error::other("the server refused to perform the request"),
         _ => io::Error::new(ErrorKind::InvalidData, format!("invalid RCODE received in response: {rcode}"))
     }
 }
 
+/// the mnemonic of an RCODE, including those of dynamic updates (RFC 2136 section 2.2)
+pub fn rcode_name(rcode: u8) -> String {
+    const NAMES: [&str; 11] = ["NOERROR", "FORMERR", "SERVFAIL", "NXDOMAIN", "NOTIMP", "REFUSED", "YXDOMAIN", "YXRRSET", "NXRRSET", "NOTAUTH", "NOTZONE"];
+    NAMES.get(usize::from(rcode)).map_or_else(|| format!("RCODE{rcode}"), |name| (*name).to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -236,6 +250,15 @@ mod tests {
         assert!(res.is_err());
     }
 
+    #[test]
+    fn parse_header_dnssec_bits() {
+        let mut data = util::header0_bytes();
+        data[3] |= 0b0011_0000;
+        let header = DnsHeader::parse(&mut Cursor::new(&data[..])).unwrap();
+        assert_eq!(header.z, 0b010 | Z_CD); // AD and CD
+        assert_eq!(Vec::from(header.to_bytes()), data);
+    }
+
     #[test]
     fn parse_header_bad_z_not_zero() {
         let data = util::header2_bytes();
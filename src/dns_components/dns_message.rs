@@ -1,12 +1,13 @@
-use std::collections::HashMap;
 use std::io::Cursor;
 
 use crate::dns_components::dns_header::DnsHeader;
 use crate::dns_components::dns_rr::DnsResourceRecord;
 use crate::dns_components::dns_question::DnsQuestion;
 
-use super::dns_header;
-use super::dns_rdata::DnsRdata;
+use super::{dns_header, dns_name::DnsName, dns_rdata::DnsRdata};
+
+/// the largest UDP response we ask for, small enough to avoid fragmentation (DNS flag day 2020)
+pub const EDNS_UDP_SIZE: u16 = 1232;
 
 #[derive(PartialEq, Eq, Debug)]
 pub struct DnsMessage {
@@ -17,16 +18,22 @@ pub struct DnsMessage {
     additionals: Vec<DnsResourceRecord>,
 }
 impl DnsMessage {
+    /// parses a response, turning an error RCODE into an error
     pub fn parse(data: &[u8]) -> Result<Self, std::io::Error> {
+        let msg = Self::parse_any(data)?;
+        if msg.header.rcode != 0 {
+            return Err(dns_header::get_error(msg.header.rcode));
+        }
+        Ok(msg)
+    }
+
+    /// parses a message whatever its RCODE, e.g. to look at the proof of nonexistence in an NXDOMAIN response
+    pub fn parse_any(data: &[u8]) -> Result<Self, std::io::Error> {
         let mut cursor = Cursor::new(data);
         let cursor_ptr = &mut cursor;
 
         let header = DnsHeader::parse(cursor_ptr)?;
 
-        if header.rcode != 0 {
-            return Err(dns_header::get_error(header.rcode));
-        }
-
         let mut questions = Vec::new();
         for _ in 0..header.qdcount {
             let question = DnsQuestion::parse(cursor_ptr)?;
@@ -84,9 +91,10 @@ impl DnsMessage {
         msg
     }
 
-    pub fn single_query(url: &str) -> Self {
+    /// a query with a single question for records of type `qtype`
+    pub fn query(name: &str, qtype: u16) -> Self {
         let header = DnsHeader::query(0xABBA, 1, false);
-        let questions: Vec<DnsQuestion> = vec![DnsQuestion::query(url)];
+        let questions: Vec<DnsQuestion> = vec![DnsQuestion::new(name, qtype)];
 
         Self {
             header,
@@ -97,16 +105,77 @@ impl DnsMessage {
         }
     }
 
-    pub fn get_ip_table(&self) -> HashMap<DnsRdata, Vec<DnsRdata>> {
-        let mut ip_table = HashMap::new();
-        for answer in &self.answers {
-            let key = DnsRdata::CnameRecord(answer.name.clone());
-            let mut new_val = ip_table.get(&key).unwrap_or(&Vec::new()).clone();
-            new_val.push(answer.rdata.clone());
+    /// a query asking for DNSSEC records too: an EDNS OPT record (RFC 6891) with the DO bit set,
+    /// and checking disabled so that the server hands over data even if it doesn't validate
+    pub fn query_dnssec(name: &str, qtype: u16) -> Self {
+        let mut msg = Self::query(name, qtype);
+        msg.header.z |= dns_header::Z_CD;
+        msg.header.arcount = 1;
+        msg.additionals.push(DnsResourceRecord {
+            name: DnsName { labels: Vec::new() },
+            rtype: 41,
+            class: EDNS_UDP_SIZE,
+            ttl: 0x8000, // extended RCODE 0, version 0, DO
+            rdlength: 0,
+            rdata: DnsRdata::UnknownRecord(Vec::new()),
+            rdata_raw: Vec::new(),
+        });
+        msg
+    }
 
-            ip_table.insert(key, new_val);
-        }
-        ip_table
+    /// an incremental zone transfer query (RFC 1995 section 3), with the SOA record of the version
+    /// the client has in the authority section
+    pub fn query_ixfr(zone: &str, soa: DnsResourceRecord) -> Self {
+        let mut msg = Self::query(zone, 251);
+        msg.header.nscount = 1;
+        msg.authorities.push(soa);
+        msg
+    }
+
+    /// a dynamic update of `zone` (RFC 2136): the question section holds the zone, and the answer and
+    /// authority sections the prerequisites and the changes
+    pub fn update(zone: &str) -> Self {
+        let mut msg = Self::query(zone, 6);
+        msg.header.opcode = dns_header::OPCODE_UPDATE;
+        msg.header.rd = false;
+        msg
+    }
+
+    pub fn push_prerequisite(&mut self, record: DnsResourceRecord) {
+        self.header.ancount += 1;
+        self.answers.push(record);
+    }
+
+    pub fn push_update(&mut self, record: DnsResourceRecord) {
+        self.header.nscount += 1;
+        self.authorities.push(record);
+    }
+
+    pub fn id(&self) -> u16 {
+        self.header.id
+    }
+
+    /// adds a record to the additional section, e.g. the TSIG record signing the message, which must come last
+    pub fn push_additional(&mut self, record: DnsResourceRecord) {
+        self.header.arcount += 1;
+        self.additionals.push(record);
+    }
+
+    pub fn answers(&self) -> &[DnsResourceRecord] {
+        &self.answers
+    }
+
+    pub fn authorities(&self) -> &[DnsResourceRecord] {
+        &self.authorities
+    }
+
+    pub fn rcode(&self) -> u8 {
+        self.header.rcode
+    }
+
+    /// whether the answer didn't fit and should be asked for again over TCP
+    pub fn truncated(&self) -> bool {
+        self.header.tc
     }
 }
 
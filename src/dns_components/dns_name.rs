@@ -1,4 +1,4 @@
-use std::io::{Cursor, Read};
+use std::{cmp::Ordering, io::{Cursor, Read}};
 
 /// simply for ease-of-use, no real functionality
 #[derive(PartialEq, Eq, Debug, Hash, Clone)]
@@ -6,8 +6,17 @@ pub struct DnsLabel {
     pub label: Vec<u8>,
 }
 impl std::fmt::Display for DnsLabel {
+    /// the label in presentation format (RFC 1035 section 5.1): dots and the characters zone files give a meaning
+    /// are escaped with a backslash, and bytes outside printable ASCII are written as `\DDD`
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", std::str::from_utf8(&self.label).unwrap_or("<invalid>"))
+        for &byte in &self.label {
+            match byte {
+                b'.' | b'\\' | b'"' | b';' | b'(' | b')' | b'@' | b'$' => write!(f, "\\{}", char::from(byte))?,
+                b'!'..=b'~' => write!(f, "{}", char::from(byte))?,
+                _ => write!(f, "\\{byte:03}")?,
+            }
+        }
+        Ok(())
     }
 }
 impl DnsLabel {
@@ -28,7 +37,8 @@ impl DnsLabel {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, serde::Serialize)]
+#[serde(into = "String")]
 pub struct DnsName {
     pub labels: Vec<DnsLabel>,
 }
@@ -37,17 +47,114 @@ impl std::fmt::Display for DnsName {
         write!(f, "{}", self.labels.iter().map(DnsLabel::to_string).collect::<Vec<String>>().join("."))
     }
 }
+impl From<DnsName> for String {
+    fn from(name: DnsName) -> Self {
+        name.to_string()
+    }
+}
 impl DnsName {
+    /// the name written as `www.example.com`, with or without the trailing dot. `.` and `` are the root.
+    /// escapes are read as in [`Self::from_text`], so that names read back the way they're displayed;
+    /// text that isn't a valid name is just split on its dots.
     pub fn from_string(name: &str) -> Self {
+        Self::from_text(name).unwrap_or_else(|_| Self {
+            labels: name.split('.').filter(|label| !label.is_empty()).map(|label| DnsLabel::new(label.as_bytes().to_vec())).collect(),
+        })
+    }
+
+    /// the name in presentation format (RFC 1035 section 5.1), where `\.` is a dot within a label, `\X` stands for X
+    /// and `\DDD` for the byte with that decimal value. a trailing dot is optional, and `.` is the root.
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        if text == "." {
+            return Ok(Self { labels: Vec::new() });
+        }
         let mut labels = Vec::new();
-        for label in name.split('.') {
-            labels.push(DnsLabel::new(Vec::from(label.as_bytes())));
+        let mut label = Vec::new();
+        let mut rest = text.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            rest = tail;
+            match (byte, rest) {
+                (b'.', _) => {
+                    if label.is_empty() {
+                        return Err(format!("empty label in {text}"));
+                    }
+                    labels.push(DnsLabel::new(std::mem::take(&mut label)));
+                },
+                (b'\\', [a, b, c, tail @ ..]) if [a, b, c].iter().all(|digit| digit.is_ascii_digit()) => {
+                    let value = (u16::from(a - b'0') * 100) + (u16::from(b - b'0') * 10) + u16::from(c - b'0');
+                    label.push(u8::try_from(value).map_err(|_| format!("invalid escape in {text}"))?);
+                    rest = tail;
+                },
+                (b'\\', [escaped, tail @ ..]) => {
+                    label.push(*escaped);
+                    rest = tail;
+                },
+                (b'\\', []) => return Err(format!("{text} ends in the middle of an escape")),
+                (byte, _) => label.push(byte),
+            }
+        }
+        if !label.is_empty() {
+            labels.push(DnsLabel::new(label));
+        }
+        let name = Self { labels };
+        if name.labels.iter().any(|label| label.label.len() > 63) {
+            return Err(format!("label longer than 63 bytes in {text}"));
+        }
+        if name.to_bytes().len() > 255 {
+            return Err(format!("{text} is longer than 255 bytes"));
         }
+        Ok(name)
+    }
+
+    /// the name with ASCII letters lowercased, as in the canonical form of DNSSEC (RFC 4034 section 6.2)
+    pub fn to_lowercase(&self) -> Self {
         Self {
-            labels
+            labels: self.labels.iter().map(|label| DnsLabel::new(label.label.to_ascii_lowercase())).collect(),
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// the name without its leftmost label; the root has no parent
+    pub fn parent(&self) -> Option<Self> {
+        let (_, rest) = self.labels.split_first()?;
+        Some(Self {
+            labels: rest.to_vec(),
+        })
+    }
+
+    /// the rightmost `count` labels, e.g. `example.com` for `www.example.com` and 2
+    pub fn ancestor(&self, count: usize) -> Self {
+        Self {
+            labels: self.labels[self.labels.len().saturating_sub(count)..].to_vec(),
         }
     }
 
+    /// `label` followed by this name, e.g. `*.example.com`
+    pub fn child(&self, label: &[u8]) -> Self {
+        let mut labels = vec![DnsLabel::new(label.to_vec())];
+        labels.extend(self.labels.iter().cloned());
+        Self {
+            labels,
+        }
+    }
+
+    /// whether this name is `other` or below it, ignoring case
+    pub fn is_subdomain_of(&self, other: &Self) -> bool {
+        self.labels.len() >= other.labels.len()
+            && self.labels.iter().rev().zip(other.labels.iter().rev()).all(|(a, b)| a.label.eq_ignore_ascii_case(&b.label))
+    }
+
+    /// the canonical DNS name order (RFC 4034 section 6.1): label by label from the right,
+    /// each compared as lowercase bytes, with an ancestor sorting before its descendants
+    pub fn canonical_cmp(&self, other: &Self) -> Ordering {
+        let ours = self.labels.iter().rev().map(|label| label.label.to_ascii_lowercase());
+        let theirs = other.labels.iter().rev().map(|label| label.label.to_ascii_lowercase());
+        ours.cmp(theirs)
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut msg = Vec::new();
 
@@ -151,6 +258,35 @@ mod tests {
         assert!(res.is_err());
     }
 
+    #[test]
+    fn canonical_order() {
+        // RFC 4034 section 6.1
+        let names = ["example", "a.example", "yljkjljk.a.example", "Z.a.example", "zABC.a.EXAMPLE", "z.example", "*.z.example"];
+        let names: Vec<DnsName> = names.iter().map(|name| DnsName::from_string(name)).collect();
+        for pair in names.windows(2) {
+            assert_eq!(pair[0].canonical_cmp(&pair[1]), Ordering::Less, "{} < {}", pair[0], pair[1]);
+        }
+        assert!(DnsName::from_string("WWW.example.com.").is_subdomain_of(&DnsName::from_string("Example.COM")));
+        assert!(!DnsName::from_string("example.com").is_subdomain_of(&DnsName::from_string("www.example.com")));
+        assert!(DnsName::from_string(".").is_root());
+        assert_eq!(DnsName::from_string("a.b.c").ancestor(2), DnsName::from_string("b.c"));
+    }
+
+    #[test]
+    fn presentation_format() {
+        let name = DnsName::from_text(r"a\.b.c\\d\032e\255.example.").unwrap();
+        assert_eq!(name.labels.len(), 3);
+        assert_eq!(name.labels[0].label, b"a.b");
+        assert_eq!(name.labels[1].label, b"c\\d e\xff");
+        assert_eq!(name.to_string(), r"a\.b.c\\d\032e\255.example");
+        assert_eq!(DnsName::from_text(&name.to_string()).unwrap(), name);
+        assert!(DnsName::from_text(".").unwrap().is_root());
+        assert!(DnsName::from_text("a..b").is_err());
+        assert!(DnsName::from_text(r"a\256").is_err());
+        assert!(DnsName::from_text(r"a\").is_err());
+        assert!(DnsName::from_text(&"x".repeat(64)).is_err());
+    }
+
     #[test]
     fn parse_pointer_good() {
         let msg = util::msg2_bytes();
@@ -1,7 +1,7 @@
 use std::io::{Cursor, Read};
 use crate::dns_components::dns_name::DnsName;
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, serde::Serialize)]
 pub struct DnsQuestion {
     pub qname: DnsName,
     pub qtype: u16,
@@ -37,10 +37,14 @@ impl DnsQuestion {
         })
     }
     pub fn query(url: &str) -> Self {
-        let qname = DnsName::from_string(url);
+        Self::new(url, 1)
+    }
+    /// a question for records of type `qtype` (e.g. 16 for TXT) in the IN class
+    pub fn new(name: &str, qtype: u16) -> Self {
+        let qname = DnsName::from_string(name);
         Self {
             qname,
-            qtype: 1,
+            qtype,
             qclass: 1,
         }
     }
@@ -1,17 +1,277 @@
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 use crate::dns_components::dns_name::DnsName;
+use crate::dns_components::dnssec::{self, Dnskey, Ds, Nsec, Nsec3, Nsec3Param, Rrsig};
+use crate::dns_components::encoding;
+use crate::dns_components::tsig::Tsig;
 
 #[derive(PartialEq, Eq, Debug, Hash, Clone)]
+#[allow(clippy::enum_variant_names)] // every variant is a kind of record, named after its type
 pub enum DnsRdata {
     ARecord(Ipv4Addr),
+    AaaaRecord(Ipv6Addr),
+    NsRecord(DnsName),
     CnameRecord(DnsName),
+    SoaRecord(Soa),
+    PtrRecord(DnsName),
+    /// preference and exchange
+    MxRecord(u16, DnsName),
+    TxtRecord(Vec<CharacterString>),
+    SrvRecord(Srv),
+    DnskeyRecord(Dnskey),
+    DsRecord(Ds),
+    RrsigRecord(Rrsig),
+    NsecRecord(Nsec),
+    Nsec3Record(Nsec3),
+    Nsec3ParamRecord(Nsec3Param),
+    TsigRecord(Tsig),
+    CaaRecord(Caa),
+    /// the rdata of a type we don't decode, kept as it came (RFC 3597)
+    UnknownRecord(Vec<u8>),
+}
+
+/// A string of at most 255 bytes, written on the wire after its length (RFC 1035 section 3.3).
+/// it can't be made any longer, so that the length always fits.
+#[derive(PartialEq, Eq, Debug, Hash, Clone, Default)]
+pub struct CharacterString(Vec<u8>);
+impl CharacterString {
+    pub const MAX_LEN: usize = 255;
+
+    pub fn new(bytes: Vec<u8>) -> Result<Self, String> {
+        if bytes.len() > Self::MAX_LEN {
+            return Err(format!("character-string is {} bytes long, more than {}", bytes.len(), Self::MAX_LEN));
+        }
+        Ok(Self(bytes))
+    }
+
+    /// the length byte followed by the string
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![u8::try_from(self.0.len()).expect("character-strings are at most 255 bytes long")];
+        bytes.extend(&self.0);
+        bytes
+    }
+}
+impl std::ops::Deref for CharacterString {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// The start of authority of a zone (RFC 1035 section 3.3.13).
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+pub struct Soa {
+    /// the primary name server
+    pub mname: DnsName,
+    /// the administrator's mailbox, with the first `.` standing for `@`
+    pub rname: DnsName,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    /// the TTL of negative answers (RFC 2308)
+    pub minimum: u32,
+}
+impl Soa {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut rdata = self.mname.to_bytes();
+        rdata.extend(self.rname.to_bytes());
+        for value in [self.serial, self.refresh, self.retry, self.expire, self.minimum] {
+            rdata.extend(value.to_be_bytes());
+        }
+        rdata
+    }
+}
+impl std::fmt::Display for Soa {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {}",
+            dnssec::absolute(&self.mname),
+            dnssec::absolute(&self.rname),
+            self.serial,
+            self.refresh,
+            self.retry,
+            self.expire,
+            self.minimum,
+        )
+    }
+}
+/// The location of a service (RFC 2782).
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+pub struct Srv {
+    /// lower is tried first
+    pub priority: u16,
+    /// how often this target is picked among those with the same priority
+    pub weight: u16,
+    pub port: u16,
+    /// the host providing the service, or `.` if there isn't one
+    pub target: DnsName,
+}
+impl Srv {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut rdata = Vec::new();
+        for value in [self.priority, self.weight, self.port] {
+            rdata.extend(value.to_be_bytes());
+        }
+        rdata.extend(self.target.to_bytes());
+        rdata
+    }
+}
+impl std::fmt::Display for Srv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {} {}", self.priority, self.weight, self.port, dnssec::absolute(&self.target))
+    }
+}
+
+/// Which certification authorities may issue certificates for a domain (RFC 8659).
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+pub struct Caa {
+    /// 128 is the issuer critical flag
+    pub flags: u8,
+    /// the property, like `issue` or `iodef`: up to 15 ASCII letters and digits
+    pub tag: String,
+    pub value: Vec<u8>,
+}
+impl Caa {
+    /// whether `tag` can be a property tag
+    pub fn valid_tag(tag: &str) -> bool {
+        (1..=15).contains(&tag.len()) && tag.bytes().all(|byte| byte.is_ascii_alphanumeric())
+    }
+
+    pub fn parse(rdata: &[u8]) -> Result<Self, std::io::Error> {
+        let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string());
+        let [flags, length, rest @ ..] = rdata else {
+            return Err(invalid("CAA record too short"));
+        };
+        let length = usize::from(*length);
+        if rest.len() < length {
+            return Err(invalid("CAA tag runs past the end of the record"));
+        }
+        let tag = String::from_utf8(rest[..length].to_vec()).map_err(|_| invalid("invalid CAA tag"))?;
+        if !Self::valid_tag(&tag) {
+            return Err(invalid("invalid CAA tag"));
+        }
+        Ok(Self { flags: *flags, tag, value: rest[length..].to_vec() })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // valid tags are at most 15 bytes long
+        let mut rdata = vec![self.flags, u8::try_from(self.tag.len()).unwrap_or(u8::MAX)];
+        rdata.extend(self.tag.as_bytes());
+        rdata.extend(&self.value);
+        rdata
+    }
+}
+impl std::fmt::Display for Caa {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.flags, self.tag, quote(&self.value))
+    }
+}
+
+impl DnsRdata {
+    /// parses the character-strings making up TXT rdata
+    pub fn parse_txt(rdata: &[u8]) -> Result<Self, std::io::Error> {
+        let mut strings = Vec::new();
+        let mut rest = rdata;
+        while let Some((&length, tail)) = rest.split_first() {
+            let length = usize::from(length);
+            if tail.len() < length {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "TXT string runs past the end of the record"));
+            }
+            strings.push(CharacterString(tail[..length].to_vec()));
+            rest = &tail[length..];
+        }
+        Ok(Self::TxtRecord(strings))
+    }
+
+    /// the rdata in wire format, with any names written out in full
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::ARecord(addr) => addr.octets().to_vec(),
+            Self::AaaaRecord(addr) => addr.octets().to_vec(),
+            Self::NsRecord(name) | Self::CnameRecord(name) | Self::PtrRecord(name) => name.to_bytes(),
+            Self::SoaRecord(soa) => soa.to_bytes(),
+            Self::MxRecord(preference, exchange) => {
+                let mut rdata = preference.to_be_bytes().to_vec();
+                rdata.extend(exchange.to_bytes());
+                rdata
+            },
+            Self::TxtRecord(strings) => strings.iter().flat_map(CharacterString::to_bytes).collect(),
+            Self::SrvRecord(srv) => srv.to_bytes(),
+            Self::DnskeyRecord(dnskey) => dnskey.to_bytes(),
+            Self::DsRecord(ds) => ds.to_bytes(),
+            Self::RrsigRecord(rrsig) => rrsig.to_bytes(),
+            Self::NsecRecord(nsec) => nsec.to_bytes(),
+            Self::Nsec3Record(nsec3) => nsec3.to_bytes(),
+            Self::Nsec3ParamRecord(param) => param.to_bytes(),
+            Self::TsigRecord(tsig) => tsig.to_bytes().expect("only Tsig::parse and tsig::Key make TSIGs, and both check the lengths"),
+            Self::CaaRecord(caa) => caa.to_bytes(),
+            Self::UnknownRecord(rdata) => rdata.clone(),
+        }
+    }
+}
+/// a character string as written in a zone file (RFC 1035 section 5.1): quoted, with `"` and `\` escaped,
+/// and bytes outside printable ASCII written as `\DDD`
+fn quote(string: &[u8]) -> String {
+    let mut quoted = String::with_capacity(string.len() + 2);
+    quoted.push('"');
+    for &byte in string {
+        match byte {
+            b'"' | b'\\' => {
+                quoted.push('\\');
+                quoted.push(char::from(byte));
+            },
+            b' '..=b'~' => quoted.push(char::from(byte)),
+            _ => quoted.push_str(&format!("\\{byte:03}")),
+        }
+    }
+    quoted.push('"');
+    quoted
 }
 impl std::fmt::Display for DnsRdata {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::ARecord(addr) => write!(f, "{addr}"),
-            Self::CnameRecord(name) => write!(f, "{name}")
+            Self::AaaaRecord(addr) => write!(f, "{addr}"),
+            Self::NsRecord(name) | Self::CnameRecord(name) | Self::PtrRecord(name) => write!(f, "{}", dnssec::absolute(name)),
+            Self::SoaRecord(soa) => write!(f, "{soa}"),
+            Self::MxRecord(preference, exchange) => write!(f, "{preference} {}", dnssec::absolute(exchange)),
+            Self::TxtRecord(strings) => {
+                let quoted: Vec<String> = strings.iter().map(|string| quote(string)).collect();
+                write!(f, "{}", quoted.join(" "))
+            },
+            Self::SrvRecord(srv) => write!(f, "{srv}"),
+            Self::DnskeyRecord(dnskey) => write!(f, "{dnskey}"),
+            Self::DsRecord(ds) => write!(f, "{ds}"),
+            Self::RrsigRecord(rrsig) => write!(f, "{rrsig}"),
+            Self::NsecRecord(nsec) => write!(f, "{nsec}"),
+            Self::Nsec3Record(nsec3) => write!(f, "{nsec3}"),
+            Self::Nsec3ParamRecord(param) => write!(f, "{param}"),
+            Self::TsigRecord(tsig) => write!(f, "{tsig}"),
+            Self::CaaRecord(caa) => write!(f, "{caa}"),
+            Self::UnknownRecord(rdata) if rdata.is_empty() => write!(f, "\\# 0"),
+            Self::UnknownRecord(rdata) => write!(f, "\\# {} {}", rdata.len(), encoding::hex(rdata)),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_txt_strings() {
+        let rdata = DnsRdata::parse_txt(b"\x05hello\x00\x03abc").unwrap();
+        let string = |bytes: &[u8]| CharacterString::new(bytes.to_vec()).unwrap();
+        assert_eq!(rdata, DnsRdata::TxtRecord(vec![string(b"hello"), string(b""), string(b"abc")]));
+        assert_eq!(rdata.to_string(), r#""hello" "" "abc""#);
+        assert!(DnsRdata::parse_txt(b"\x05hell").is_err());
+        assert_eq!(rdata.to_bytes(), b"\x05hello\x00\x03abc");
+        let rdata = DnsRdata::TxtRecord(vec![string(b"tab\there \"\\ \xff")]);
+        assert_eq!(rdata.to_string(), r#""tab\009here \"\\ \255""#);
+        assert_eq!(CharacterString::new(vec![b'x'; 255]).unwrap().to_bytes().len(), 256);
+        assert!(CharacterString::new(vec![b'x'; 256]).is_err());
+        assert_eq!(DnsRdata::UnknownRecord(vec![0x0a, 0x00, 0x00, 0x01]).to_string(), r"\# 4 0A000001");
+    }
+}
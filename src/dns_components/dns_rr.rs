@@ -1,8 +1,49 @@
-use std::{io::{Cursor, Read}, net::Ipv4Addr};
+use std::{io::{Cursor, Read}, net::{Ipv4Addr, Ipv6Addr}};
 
-use super::{dns_name::DnsName, dns_rdata::DnsRdata};
+use super::{dns_name::DnsName, dns_rdata::{Caa, DnsRdata, Soa, Srv}, dnssec, tsig};
 
-#[derive(PartialEq, Eq, Debug)]
+/// the mnemonics of the record types we know by name, see [`type_name`] and [`type_code`]
+const TYPE_NAMES: [(u16, &str); 21] = [
+    (1, "A"),
+    (2, "NS"),
+    (5, "CNAME"),
+    (6, "SOA"),
+    (12, "PTR"),
+    (15, "MX"),
+    (16, "TXT"),
+    (28, "AAAA"),
+    (33, "SRV"),
+    (41, "OPT"),
+    (43, "DS"),
+    (46, "RRSIG"),
+    (47, "NSEC"),
+    (48, "DNSKEY"),
+    (50, "NSEC3"),
+    (51, "NSEC3PARAM"),
+    (250, "TSIG"),
+    (251, "IXFR"),
+    (252, "AXFR"),
+    (255, "ANY"),
+    (257, "CAA"),
+];
+
+/// the mnemonic of a record type, or `TYPE1234` for types without one (RFC 3597)
+pub fn type_name(rtype: u16) -> String {
+    TYPE_NAMES.iter()
+        .find(|(code, _)| *code == rtype)
+        .map_or_else(|| format!("TYPE{rtype}"), |(_, name)| (*name).to_string())
+}
+
+/// the record type a mnemonic (in any case) or `TYPE1234` stands for
+pub fn type_code(name: &str) -> Option<u16> {
+    let name = name.to_ascii_uppercase();
+    TYPE_NAMES.iter()
+        .find(|(_, mnemonic)| *mnemonic == name)
+        .map(|(code, _)| *code)
+        .or_else(|| name.strip_prefix("TYPE")?.parse().ok())
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct DnsResourceRecord {
     pub name: DnsName,
     pub rtype: u16, // i cannot call it type, even though the field is called TYPE.
@@ -37,25 +78,60 @@ impl DnsResourceRecord {
 
         cursor.read_exact(&mut rdata_raw)?;
 
+        let end_pos = cursor.position();
+        let start_pos = end_pos - u64::from(rdlength);
+        // names in rdata may be compressed, pointing anywhere in the message, so they're read through the message's cursor
+        let mut with_names = |parse: fn(&mut Cursor<&[u8]>) -> Result<DnsRdata, std::io::Error>| {
+            cursor.set_position(start_pos);
+            let rdata = parse(cursor);
+            cursor.set_position(end_pos); // we should have ended here anyways
+            rdata
+        };
+
         let rdata = match rtype {
+            // prerequisites and deletions in dynamic updates, of class NONE or ANY, have no rdata whatever their type
+            // (RFC 2136 section 2.4)
+            _ if rdlength == 0 && matches!(class, 254 | 255) => DnsRdata::UnknownRecord(Vec::new()),
             1 => {
                 if rdlength != 4 {
                     return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("expected 4 bytes for an IP, got {rdlength}")));
                 }
                 DnsRdata::ARecord(Ipv4Addr::new(rdata_raw[0], rdata_raw[1], rdata_raw[2], rdata_raw[3]))
             },
-            5 => {
-                let end_pos = cursor.position();
-                let start_pos = end_pos - u64::from(rdlength);
-                cursor.set_position(start_pos);
-
-                let name = DnsName::parse(cursor)?;
-                if cursor.position() != end_pos {
-                    cursor.set_position(end_pos); // we should have ended here anyways
-                }
-                DnsRdata::CnameRecord(name)
+            2 => with_names(|cursor| Ok(DnsRdata::NsRecord(DnsName::parse(cursor)?)))?,
+            5 => with_names(|cursor| Ok(DnsRdata::CnameRecord(DnsName::parse(cursor)?)))?,
+            6 => with_names(|cursor| Ok(DnsRdata::SoaRecord(Soa {
+                mname: DnsName::parse(cursor)?,
+                rname: DnsName::parse(cursor)?,
+                serial: dnssec::read_u32(cursor)?,
+                refresh: dnssec::read_u32(cursor)?,
+                retry: dnssec::read_u32(cursor)?,
+                expire: dnssec::read_u32(cursor)?,
+                minimum: dnssec::read_u32(cursor)?,
+            })))?,
+            12 => with_names(|cursor| Ok(DnsRdata::PtrRecord(DnsName::parse(cursor)?)))?,
+            15 => with_names(|cursor| Ok(DnsRdata::MxRecord(dnssec::read_u16(cursor)?, DnsName::parse(cursor)?)))?,
+            16 => DnsRdata::parse_txt(&rdata_raw)?,
+            28 => {
+                let octets: [u8; 16] = rdata_raw.as_slice().try_into()
+                    .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("expected 16 bytes for an IPv6 address, got {rdlength}")))?;
+                DnsRdata::AaaaRecord(Ipv6Addr::from(octets))
             },
-            _ => return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, format!("invalid type: {rtype}")))
+            33 => with_names(|cursor| Ok(DnsRdata::SrvRecord(Srv {
+                priority: dnssec::read_u16(cursor)?,
+                weight: dnssec::read_u16(cursor)?,
+                port: dnssec::read_u16(cursor)?,
+                target: DnsName::parse(cursor)?,
+            })))?,
+            43 => DnsRdata::DsRecord(dnssec::Ds::parse(&rdata_raw)?),
+            46 => DnsRdata::RrsigRecord(dnssec::Rrsig::parse(&rdata_raw)?),
+            47 => DnsRdata::NsecRecord(dnssec::Nsec::parse(&rdata_raw)?),
+            48 => DnsRdata::DnskeyRecord(dnssec::Dnskey::parse(&rdata_raw)?),
+            50 => DnsRdata::Nsec3Record(dnssec::Nsec3::parse(&rdata_raw)?),
+            51 => DnsRdata::Nsec3ParamRecord(dnssec::Nsec3Param::parse(&rdata_raw)?),
+            tsig::TSIG => DnsRdata::TsigRecord(tsig::Tsig::parse(&rdata_raw)?),
+            257 => DnsRdata::CaaRecord(Caa::parse(&rdata_raw)?),
+            _ => DnsRdata::UnknownRecord(rdata_raw.clone()),
         };
 
         Ok(Self {
@@ -69,6 +145,8 @@ impl DnsResourceRecord {
         })
     }
 
+    /// the record in wire format. the rdata is encoded afresh rather than copied from `rdata_raw`,
+    /// since names in there may be compression pointers into the message the record came from.
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut msg = Vec::new();
 
@@ -80,13 +158,21 @@ impl DnsResourceRecord {
 
         msg.extend(self.ttl.to_be_bytes());
 
-        msg.extend(self.rdlength.to_be_bytes());
+        let rdata = self.rdata.to_bytes();
+        msg.extend(u16::try_from(rdata.len()).unwrap_or(u16::MAX).to_be_bytes());
 
-        msg.extend(&self.rdata_raw);
+        msg.extend(rdata);
 
         msg
     }
 }
+impl std::fmt::Display for DnsResourceRecord {
+    /// the record in presentation (zone file) format
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let class = if self.class == 1 { "IN".to_string() } else { format!("CLASS{}", self.class) };
+        write!(f, "{} {} {class} {} {}", dnssec::absolute(&self.name), self.ttl, type_name(self.rtype), self.rdata)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -122,7 +208,7 @@ mod tests {
         pub fn msg0_answer() -> DnsResourceRecord {
             DnsResourceRecord {
                 name: DnsName { labels: vec![
-                    DnsLabel::new(vec![0xAA, 0xBb, 0xCC, 0xDD, 0xEE]),
+                    DnsLabel::new(vec![0xAA, 0xBB, 0xCC, 0xDD, 0xEE]),
                 ] },
                 rtype: 1,
                 class: 1,
@@ -154,6 +240,34 @@ mod tests {
         assert_eq!(answer, util::msg0_answer());
     }
 
+    #[test]
+    fn dnskey_record() {
+        let mut data = b"\x07example\x00\x00\x30\x00\x01\x00\x00\x0e\x10\x00\x08".to_vec();
+        data.extend([0x01, 0x00, 0x03, 0x0d, 0xde, 0xad, 0xbe, 0xef]);
+        let record = DnsResourceRecord::parse(&mut Cursor::new(&data[..])).unwrap();
+        assert_eq!(record.to_string(), "example. 3600 IN DNSKEY 256 3 13 3q2+7w==");
+        assert_eq!(record.to_bytes(), data);
+    }
+
+    #[test]
+    fn empty_rdata() {
+        // an A record without an address is only fine as an update prerequisite or deletion
+        let data = b"\x07example\x00\x00\x01\x00\x01\x00\x00\x0e\x10\x00\x00";
+        assert!(DnsResourceRecord::parse(&mut Cursor::new(&data[..])).is_err());
+        let data = b"\x07example\x00\x00\x01\x00\xff\x00\x00\x00\x00\x00\x00";
+        let record = DnsResourceRecord::parse(&mut Cursor::new(&data[..])).unwrap();
+        assert_eq!(record.rdata, DnsRdata::UnknownRecord(Vec::new()));
+    }
+
+    #[test]
+    fn type_names() {
+        assert_eq!(type_name(48), "DNSKEY");
+        assert_eq!(type_name(1234), "TYPE1234");
+        assert_eq!(type_code("nsec3param"), Some(51));
+        assert_eq!(type_code("TYPE1234"), Some(1234));
+        assert_eq!(type_code("BOGUS"), None);
+    }
+
     #[test]
     fn parse_answer_bad_short() {
         let data = util::msg2_bytes();
@@ -0,0 +1,495 @@
+//! The rdata of the DNSSEC record types (RFC 4034 and RFC 5155). Names inside these records are
+//! never compressed, so each record can be decoded from its rdata alone.
+
+use std::io::{self, Cursor, Read};
+
+use super::{dns_name::DnsName, dns_rr, encoding};
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u8(cursor: &mut Cursor<&[u8]>) -> Result<u8, io::Error> {
+    let mut bytes = [0_u8; 1];
+    cursor.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+pub(super) fn read_u16(cursor: &mut Cursor<&[u8]>) -> Result<u16, io::Error> {
+    let mut bytes = [0_u8; 2];
+    cursor.read_exact(&mut bytes)?;
+    Ok(u16::from_be_bytes(bytes))
+}
+
+pub(super) fn read_u32(cursor: &mut Cursor<&[u8]>) -> Result<u32, io::Error> {
+    let mut bytes = [0_u8; 4];
+    cursor.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+/// a byte string preceded by its length, like the salt of NSEC3
+fn read_sized(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>, io::Error> {
+    let mut data = vec![0_u8; usize::from(read_u8(cursor)?)];
+    cursor.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn rest(cursor: &Cursor<&[u8]>) -> Vec<u8> {
+    let position = usize::try_from(cursor.position()).unwrap_or(usize::MAX);
+    cursor.get_ref().get(position..).unwrap_or_default().to_vec()
+}
+
+/// `name` with its trailing dot, the way names are written inside rdata
+pub fn absolute(name: &DnsName) -> String {
+    format!("{name}.")
+}
+
+/// decodes a type bitmap (RFC 4034 section 4.1.2): windows of up to 256 types, each a window number,
+/// a bitmap length and a bitmap with the most significant bit standing for the lowest type
+pub fn parse_type_bitmap(mut data: &[u8]) -> Result<Vec<u16>, io::Error> {
+    let mut types = Vec::new();
+    while let [window, length, tail @ ..] = data {
+        let length = usize::from(*length);
+        if length == 0 || length > 32 || tail.len() < length {
+            return Err(invalid("invalid type bitmap"));
+        }
+        for (index, byte) in tail[..length].iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    types.push((u16::from(*window) << 8) | u16::try_from(index * 8 + bit).unwrap_or_default());
+                }
+            }
+        }
+        data = &tail[length..];
+    }
+    if !data.is_empty() {
+        return Err(invalid("type bitmap ends in the middle of a window"));
+    }
+    Ok(types)
+}
+
+pub fn type_bitmap(types: &[u16]) -> Vec<u8> {
+    let mut sorted = types.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut bitmap = Vec::new();
+    for window in sorted.chunk_by(|a, b| a >> 8 == b >> 8) {
+        let mut bits = [0_u8; 32];
+        for rtype in window {
+            let low = usize::from(rtype & 0xff);
+            bits[low / 8] |= 0x80 >> (low % 8);
+        }
+        let length = window.last().map_or(0, |last| usize::from(last & 0xff) / 8 + 1);
+        bitmap.push(u8::try_from(window[0] >> 8).unwrap_or_default());
+        bitmap.push(u8::try_from(length).unwrap_or_default());
+        bitmap.extend(&bits[..length]);
+    }
+    bitmap
+}
+
+fn type_list(types: &[u16]) -> String {
+    types.iter().map(|rtype| dns_rr::type_name(*rtype)).collect::<Vec<String>>().join(" ")
+}
+
+/// an RRSIG timestamp as `YYYYMMDDHHmmSS` in UTC (RFC 4034 section 3.2)
+pub fn format_time(seconds: u32) -> String {
+    let days = i64::from(seconds / 86400);
+    let time = seconds % 86400;
+    // converts days since 1970-01-01 to a civil date, after Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}{month:02}{day:02}{:02}{:02}{:02}", time / 3600, time / 60 % 60, time % 60)
+}
+
+/// reads an RRSIG timestamp written either as `YYYYMMDDHHmmSS` or as seconds since the epoch
+pub fn parse_time(text: &str) -> Option<u32> {
+    if text.len() != 14 {
+        return text.parse().ok();
+    }
+    let field = |range: std::ops::Range<usize>| text.get(range)?.parse::<i64>().ok();
+    let (year, month, day) = (field(0..4)?, field(4..6)?, field(6..8)?);
+    let (hour, minute, second) = (field(8..10)?, field(10..12)?, field(12..14)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    // the inverse of `format_time`, after Howard Hinnant's `days_from_civil`
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    let seconds = days * 86400 + hour * 3600 + minute * 60 + second;
+    // wraps around like the timestamps themselves (RFC 4034 section 3.1.5)
+    u32::try_from(seconds.rem_euclid(1 << 32)).ok()
+}
+
+/// A public key of a zone (RFC 4034 section 2).
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+pub struct Dnskey {
+    /// 256 for a zone signing key, 257 for a key signing key (the secure entry point bit)
+    pub flags: u16,
+    /// always 3
+    pub protocol: u8,
+    pub algorithm: u8,
+    pub public_key: Vec<u8>,
+}
+impl Dnskey {
+    pub fn parse(rdata: &[u8]) -> Result<Self, io::Error> {
+        let mut cursor = Cursor::new(rdata);
+        Ok(Self {
+            flags: read_u16(&mut cursor)?,
+            protocol: read_u8(&mut cursor)?,
+            algorithm: read_u8(&mut cursor)?,
+            public_key: rest(&cursor),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut rdata = Vec::new();
+        rdata.extend(self.flags.to_be_bytes());
+        rdata.push(self.protocol);
+        rdata.push(self.algorithm);
+        rdata.extend(&self.public_key);
+        rdata
+    }
+
+    /// the key tag DS and RRSIG records refer to this key by (RFC 4034 appendix B)
+    pub fn key_tag(&self) -> u16 {
+        let rdata = self.to_bytes();
+        if self.algorithm == 1 {
+            // RSA/MD5 keys use the last but one two bytes of the modulus instead
+            let length = rdata.len();
+            return if length >= 4 { u16::from_be_bytes([rdata[length - 3], rdata[length - 2]]) } else { 0 };
+        }
+        let mut sum: u32 = rdata.iter().enumerate()
+            .map(|(index, byte)| if index % 2 == 0 { u32::from(*byte) << 8 } else { u32::from(*byte) })
+            .sum();
+        sum += (sum >> 16) & 0xffff;
+        u16::try_from(sum & 0xffff).unwrap_or_default()
+    }
+
+    /// whether this is a key signing key, the one a DS record in the parent zone points to
+    pub fn is_secure_entry_point(&self) -> bool {
+        self.flags & 1 == 1
+    }
+}
+impl std::fmt::Display for Dnskey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {} {}", self.flags, self.protocol, self.algorithm, encoding::base64(&self.public_key))
+    }
+}
+
+/// The digest of a child zone's key, published in the parent zone (RFC 4034 section 5).
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+pub struct Ds {
+    pub key_tag: u16,
+    pub algorithm: u8,
+    /// 1 for SHA-1, 2 for SHA-256, 4 for SHA-384
+    pub digest_type: u8,
+    pub digest: Vec<u8>,
+}
+impl Ds {
+    pub fn parse(rdata: &[u8]) -> Result<Self, io::Error> {
+        let mut cursor = Cursor::new(rdata);
+        Ok(Self {
+            key_tag: read_u16(&mut cursor)?,
+            algorithm: read_u8(&mut cursor)?,
+            digest_type: read_u8(&mut cursor)?,
+            digest: rest(&cursor),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut rdata = Vec::new();
+        rdata.extend(self.key_tag.to_be_bytes());
+        rdata.push(self.algorithm);
+        rdata.push(self.digest_type);
+        rdata.extend(&self.digest);
+        rdata
+    }
+}
+impl std::fmt::Display for Ds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {} {}", self.key_tag, self.algorithm, self.digest_type, encoding::hex(&self.digest))
+    }
+}
+
+/// A signature over the records of one name and type (RFC 4034 section 3).
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+pub struct Rrsig {
+    pub type_covered: u16,
+    pub algorithm: u8,
+    /// how many labels the owner name had when signed, less for wildcards
+    pub labels: u8,
+    pub original_ttl: u32,
+    /// seconds since the epoch, in serial number arithmetic
+    pub expiration: u32,
+    pub inception: u32,
+    pub key_tag: u16,
+    pub signer: DnsName,
+    pub signature: Vec<u8>,
+}
+impl Rrsig {
+    pub fn parse(rdata: &[u8]) -> Result<Self, io::Error> {
+        let mut cursor = Cursor::new(rdata);
+        Ok(Self {
+            type_covered: read_u16(&mut cursor)?,
+            algorithm: read_u8(&mut cursor)?,
+            labels: read_u8(&mut cursor)?,
+            original_ttl: read_u32(&mut cursor)?,
+            expiration: read_u32(&mut cursor)?,
+            inception: read_u32(&mut cursor)?,
+            key_tag: read_u16(&mut cursor)?,
+            signer: DnsName::parse(&mut cursor)?,
+            signature: rest(&cursor),
+        })
+    }
+
+    /// everything but the signature, which is what gets signed along with the records
+    pub fn fields_to_bytes(&self) -> Vec<u8> {
+        let mut rdata = Vec::new();
+        rdata.extend(self.type_covered.to_be_bytes());
+        rdata.push(self.algorithm);
+        rdata.push(self.labels);
+        rdata.extend(self.original_ttl.to_be_bytes());
+        rdata.extend(self.expiration.to_be_bytes());
+        rdata.extend(self.inception.to_be_bytes());
+        rdata.extend(self.key_tag.to_be_bytes());
+        rdata.extend(self.signer.to_bytes());
+        rdata
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut rdata = self.fields_to_bytes();
+        rdata.extend(&self.signature);
+        rdata
+    }
+}
+impl std::fmt::Display for Rrsig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {} {}",
+            dns_rr::type_name(self.type_covered),
+            self.algorithm,
+            self.labels,
+            self.original_ttl,
+            format_time(self.expiration),
+            format_time(self.inception),
+            self.key_tag,
+            absolute(&self.signer),
+            encoding::base64(&self.signature),
+        )
+    }
+}
+
+/// The next name in the zone and the types present at this one, proving what doesn't exist (RFC 4034 section 4).
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+pub struct Nsec {
+    pub next: DnsName,
+    pub types: Vec<u16>,
+}
+impl Nsec {
+    pub fn parse(rdata: &[u8]) -> Result<Self, io::Error> {
+        let mut cursor = Cursor::new(rdata);
+        let next = DnsName::parse(&mut cursor)?;
+        Ok(Self {
+            next,
+            types: parse_type_bitmap(&rest(&cursor))?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut rdata = self.next.to_bytes();
+        rdata.extend(type_bitmap(&self.types));
+        rdata
+    }
+}
+impl std::fmt::Display for Nsec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", absolute(&self.next), type_list(&self.types))
+    }
+}
+
+/// Like NSEC, but between hashed names so that the zone can't be walked (RFC 5155 section 3).
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+pub struct Nsec3 {
+    /// 1 for SHA-1, the only one defined
+    pub hash_algorithm: u8,
+    /// bit 0 is opt-out
+    pub flags: u8,
+    pub iterations: u16,
+    pub salt: Vec<u8>,
+    pub next_hashed: Vec<u8>,
+    pub types: Vec<u16>,
+}
+impl Nsec3 {
+    pub fn parse(rdata: &[u8]) -> Result<Self, io::Error> {
+        let mut cursor = Cursor::new(rdata);
+        Ok(Self {
+            hash_algorithm: read_u8(&mut cursor)?,
+            flags: read_u8(&mut cursor)?,
+            iterations: read_u16(&mut cursor)?,
+            salt: read_sized(&mut cursor)?,
+            next_hashed: read_sized(&mut cursor)?,
+            types: parse_type_bitmap(&rest(&cursor))?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut rdata = vec![self.hash_algorithm, self.flags];
+        rdata.extend(self.iterations.to_be_bytes());
+        rdata.push(u8::try_from(self.salt.len()).unwrap_or(u8::MAX));
+        rdata.extend(&self.salt);
+        rdata.push(u8::try_from(self.next_hashed.len()).unwrap_or(u8::MAX));
+        rdata.extend(&self.next_hashed);
+        rdata.extend(type_bitmap(&self.types));
+        rdata
+    }
+}
+impl std::fmt::Display for Nsec3 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.hash_algorithm,
+            self.flags,
+            self.iterations,
+            salt(&self.salt),
+            encoding::base32hex(&self.next_hashed),
+        )?;
+        if !self.types.is_empty() {
+            write!(f, " {}", type_list(&self.types))?;
+        }
+        Ok(())
+    }
+}
+
+/// The parameters a zone's NSEC3 records were made with (RFC 5155 section 4).
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+pub struct Nsec3Param {
+    pub hash_algorithm: u8,
+    pub flags: u8,
+    pub iterations: u16,
+    pub salt: Vec<u8>,
+}
+impl Nsec3Param {
+    pub fn parse(rdata: &[u8]) -> Result<Self, io::Error> {
+        let mut cursor = Cursor::new(rdata);
+        Ok(Self {
+            hash_algorithm: read_u8(&mut cursor)?,
+            flags: read_u8(&mut cursor)?,
+            iterations: read_u16(&mut cursor)?,
+            salt: read_sized(&mut cursor)?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut rdata = vec![self.hash_algorithm, self.flags];
+        rdata.extend(self.iterations.to_be_bytes());
+        rdata.push(u8::try_from(self.salt.len()).unwrap_or(u8::MAX));
+        rdata.extend(&self.salt);
+        rdata
+    }
+}
+impl std::fmt::Display for Nsec3Param {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {} {}", self.hash_algorithm, self.flags, self.iterations, salt(&self.salt))
+    }
+}
+
+/// a salt in hex, or `-` for none
+fn salt(salt: &[u8]) -> String {
+    if salt.is_empty() { "-".to_string() } else { encoding::hex(salt) }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub mod util {
+        use super::*;
+
+        /// the key signing key of the root zone from 2017 (KSK-2017), which has key tag 20326
+        pub fn root_ksk() -> Dnskey {
+            let key = "AwEAAaz/tAm8yTn4Mfeh5eyI96WSVexTBAvkMgJzkKTOiW1vkIbzxeF3+/4RgWOq7HrxRixHlFlExOLAJr5emLvN7SWXgnLh4+B5xQlNVz8Og8kvArMtNROxVQuCaSnIDdD5LKyWbRd2n9WGe2R8PzgCmr3EgVLrjyBxWezF0jLHwVN8efS3rCj/EWgvIWgb9tarpVUDK/b58Da+sqqls3eNbuv7pr+eoZG+SrDK6nWeL3c6H5Apxz7LjVc1uTIdsIXxuOLYA4/ilBmSVIzuDWfdRUfhHdY6+cn8HFRm+2hM8AnXGXws9555KrUB5qihylGa8subX2Nn6UwNR1AkUTV74bU=";
+            Dnskey { flags: 257, protocol: 3, algorithm: 8, public_key: encoding::decode_base64(key).unwrap() }
+        }
+    }
+
+    #[test]
+    fn dnskey_key_tag() {
+        let key = util::root_ksk();
+        assert_eq!(key.key_tag(), 20326);
+        assert!(key.is_secure_entry_point());
+        assert_eq!(Dnskey::parse(&key.to_bytes()).unwrap(), key);
+        assert!(key.to_string().starts_with("257 3 8 AwEAAaz/tAm8yTn4Mfeh5eyI96WSVex"));
+    }
+
+    #[test]
+    fn rrsig_round_trip() {
+        let rrsig = Rrsig {
+            type_covered: 48,
+            algorithm: 8,
+            labels: 0,
+            original_ttl: 172_800,
+            expiration: 1_730_419_200,
+            inception: 1_728_604_800,
+            key_tag: 20326,
+            signer: DnsName { labels: Vec::new() },
+            signature: vec![1, 2, 3, 4, 5],
+        };
+        assert_eq!(Rrsig::parse(&rrsig.to_bytes()).unwrap(), rrsig);
+        assert_eq!(rrsig.to_string(), "DNSKEY 8 0 172800 20241101000000 20241011000000 20326 . AQIDBAU=");
+        assert_eq!(format_time(951_782_400), "20000229000000");
+        assert_eq!(parse_time("20000229000000"), Some(951_782_400));
+        assert_eq!(parse_time("20241101000000"), Some(1_730_419_200));
+        assert_eq!(parse_time("1730419200"), Some(1_730_419_200));
+        assert_eq!(parse_time("20241301000000"), None);
+    }
+
+    #[test]
+    fn nsec_type_bitmap() {
+        // RFC 4034 section 4.3: A MX RRSIG NSEC TYPE1234
+        let rdata = [
+            b"\x04host\x07example\x03com\x00".as_slice(),
+            &[0x00, 0x06, 0x40, 0x01, 0x00, 0x00, 0x00, 0x03, 0x04, 0x1b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20],
+        ].concat();
+        let nsec = Nsec::parse(&rdata).unwrap();
+        assert_eq!(nsec.types, vec![1, 15, 46, 47, 1234]);
+        assert_eq!(nsec.to_string(), "host.example.com. A MX RRSIG NSEC TYPE1234");
+        assert_eq!(nsec.to_bytes(), rdata);
+        assert!(parse_type_bitmap(&[0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn nsec3_and_param() {
+        let nsec3 = Nsec3 {
+            hash_algorithm: 1,
+            flags: 1,
+            iterations: 12,
+            salt: vec![0xaa, 0xbb, 0xcc, 0xdd],
+            next_hashed: b"foobar".to_vec(),
+            types: vec![1, 46],
+        };
+        assert_eq!(Nsec3::parse(&nsec3.to_bytes()).unwrap(), nsec3);
+        assert_eq!(nsec3.to_string(), "1 1 12 AABBCCDD CPNMUOJ1E8 A RRSIG");
+
+        let param = Nsec3Param { hash_algorithm: 1, flags: 0, iterations: 0, salt: Vec::new() };
+        assert_eq!(Nsec3Param::parse(&param.to_bytes()).unwrap(), param);
+        assert_eq!(param.to_string(), "1 0 0 -");
+
+        let ds = Ds { key_tag: 20326, algorithm: 8, digest_type: 2, digest: vec![0xe0, 0x6d] };
+        assert_eq!(Ds::parse(&ds.to_bytes()).unwrap(), ds);
+        assert_eq!(ds.to_string(), "20326 8 2 E06D");
+    }
+}
@@ -0,0 +1,110 @@
+//! The binary-to-text encodings used in the presentation format of DNSSEC records:
+//! base64 for keys and signatures, base32hex for NSEC3 hashes and hex for digests and salts.
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE32HEX: &[u8; 32] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+
+/// base64 with padding (RFC 4648 section 4)
+pub fn base64(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(char::from(BASE64[((bits >> (18 - 6 * i)) & 0x3f) as usize]));
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// decodes base64 with or without padding; `None` for anything else
+pub fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut data = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0_u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = BASE64.iter().position(|digit| *digit == c)?;
+        buffer = (buffer << 6) | u32::try_from(value).ok()?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            data.push(u8::try_from((buffer >> bits) & 0xff).ok()?);
+        }
+    }
+    Some(data)
+}
+
+/// base32 with the extended hex alphabet and without padding, as NSEC3 uses it (RFC 5155 section 3.3)
+pub fn base32hex(data: &[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut buffer = 0_u16;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | u16::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            text.push(char::from(BASE32HEX[usize::from((buffer >> bits) & 0x1f)]));
+        }
+    }
+    if bits > 0 {
+        text.push(char::from(BASE32HEX[usize::from((buffer << (5 - bits)) & 0x1f)]));
+    }
+    text
+}
+
+/// decodes base32hex without padding, in either case; `None` for anything else
+pub fn decode_base32hex(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::with_capacity(text.len() * 5 / 8);
+    let mut buffer = 0_u16;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = BASE32HEX.iter().position(|digit| *digit == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | u16::try_from(value).ok()?;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            data.push(u8::try_from((buffer >> bits) & 0xff).ok()?);
+        }
+    }
+    Some(data)
+}
+
+/// uppercase hex, as used for DS digests and NSEC3 salts
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02X}")).collect()
+}
+
+/// decodes hex in either case; `None` for anything else
+pub fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc4648_vectors() {
+        for (data, encoded) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("foobar", "Zm9vYmFy")] {
+            assert_eq!(base64(data.as_bytes()), encoded);
+            assert_eq!(decode_base64(encoded).as_deref(), Some(data.as_bytes()));
+        }
+        for (data, encoded) in [("f", "CO"), ("fo", "CPNG"), ("foo", "CPNMU"), ("foobar", "CPNMUOJ1E8")] {
+            assert_eq!(base32hex(data.as_bytes()), encoded);
+        }
+        assert_eq!(hex(&[0x0a, 0xbc]), "0ABC");
+        assert_eq!(decode_base32hex("cpnmuoj1e8").as_deref(), Some(b"foobar".as_slice()));
+        assert_eq!(decode_hex("0aBC").as_deref(), Some([0x0a, 0xbc].as_slice()));
+        assert_eq!(decode_hex("0g"), None);
+        assert_eq!(decode_base64("Zm9v!"), None);
+    }
+}
@@ -3,4 +3,8 @@ pub mod dns_question;
 pub mod dns_rr;
 pub mod dns_message;
 pub mod dns_name;
-pub mod dns_rdata;
\ No newline at end of file
+pub mod dns_rdata;
+pub mod dnssec;
+pub mod encoding;
+pub mod tsig;
+pub mod zone_file;
@@ -0,0 +1,504 @@
+//! Transaction signatures (RFC 8945): messages signed with an HMAC over a secret shared with the
+//! server, which is how servers tell who may transfer a zone or update it. A request is signed
+//! once; a response is signed over the request's MAC too, and every signed message of a transfer
+//! over the MAC of the one before.
+
+use std::{fs, io::{self, Cursor, Read}, path::Path, time::{SystemTime, UNIX_EPOCH}};
+
+use ring::hmac;
+
+use super::{dns_header::DnsHeader, dns_message::DnsMessage, dns_name::DnsName, dns_question::DnsQuestion, dns_rdata::DnsRdata, dns_rr::DnsResourceRecord, dnssec, encoding};
+
+pub const TSIG: u16 = 250;
+const ANY: u16 = 255;
+/// how far apart our clock and the server's may be, in seconds
+const FUDGE: u16 = 300;
+/// how many messages of a transfer may go unsigned in a row (RFC 8945 section 5.3.1)
+const MAX_UNSIGNED: usize = 99;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// the length of `bytes` as it goes before them, in two bytes
+fn length(bytes: &[u8], what: &str) -> Result<[u8; 2], io::Error> {
+    u16::try_from(bytes.len()).map(u16::to_be_bytes).map_err(|_| invalid(&format!("the {what} is longer than 65535 bytes")))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// the name of a TSIG error (RFC 8945 section 3)
+fn error_name(error: u16) -> String {
+    match error {
+        0 => "NOERROR".to_string(),
+        16 => "BADSIG".to_string(),
+        17 => "BADKEY".to_string(),
+        18 => "BADTIME".to_string(),
+        22 => "BADTRUNC".to_string(),
+        _ => error.to_string(),
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Hash, Clone, Copy)]
+pub enum Algorithm {
+    HmacSha256,
+    HmacSha512,
+}
+impl Algorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Self::HmacSha256 => "hmac-sha256",
+            Self::HmacSha512 => "hmac-sha512",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim_end_matches('.').to_ascii_lowercase().as_str() {
+            "hmac-sha256" => Some(Self::HmacSha256),
+            "hmac-sha512" => Some(Self::HmacSha512),
+            _ => None,
+        }
+    }
+
+    fn hmac(self) -> hmac::Algorithm {
+        match self {
+            Self::HmacSha256 => hmac::HMAC_SHA256,
+            Self::HmacSha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+/// The rdata of a TSIG record (RFC 8945 section 4.2).
+#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+pub struct Tsig {
+    pub algorithm: DnsName,
+    /// seconds since the epoch, in 48 bits
+    pub time_signed: u64,
+    pub fudge: u16,
+    /// private, like `other`, so that only [`Tsig::parse`] and [`Key`] make TSIGs, and their lengths always fit
+    mac: Vec<u8>,
+    /// the ID of the message when it was signed
+    pub original_id: u16,
+    pub error: u16,
+    other: Vec<u8>,
+}
+impl Tsig {
+    pub fn parse(rdata: &[u8]) -> Result<Self, io::Error> {
+        let mut cursor = Cursor::new(rdata);
+        let algorithm = DnsName::parse(&mut cursor)?;
+        let mut time = [0_u8; 8];
+        cursor.read_exact(&mut time[2..])?;
+        let fudge = dnssec::read_u16(&mut cursor)?;
+        let mut mac = vec![0_u8; usize::from(dnssec::read_u16(&mut cursor)?)];
+        cursor.read_exact(&mut mac)?;
+        let original_id = dnssec::read_u16(&mut cursor)?;
+        let error = dnssec::read_u16(&mut cursor)?;
+        let mut other = vec![0_u8; usize::from(dnssec::read_u16(&mut cursor)?)];
+        cursor.read_exact(&mut other)?;
+        Ok(Self {
+            algorithm,
+            time_signed: u64::from_be_bytes(time),
+            fudge,
+            mac,
+            original_id,
+            error,
+            other,
+        })
+    }
+
+    /// the time signed and fudge, which is all that's signed of the later messages of a transfer
+    fn timers(&self) -> Vec<u8> {
+        let mut timers = self.time_signed.to_be_bytes()[2..].to_vec();
+        timers.extend(self.fudge.to_be_bytes());
+        timers
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, io::Error> {
+        let mut rdata = self.algorithm.to_bytes();
+        rdata.extend(self.timers());
+        rdata.extend(length(&self.mac, "MAC")?);
+        rdata.extend(&self.mac);
+        rdata.extend(self.original_id.to_be_bytes());
+        rdata.extend(self.error.to_be_bytes());
+        rdata.extend(length(&self.other, "other data")?);
+        rdata.extend(&self.other);
+        Ok(rdata)
+    }
+}
+impl std::fmt::Display for Tsig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {}",
+            dnssec::absolute(&self.algorithm),
+            self.time_signed,
+            self.fudge,
+            self.mac.len(),
+            encoding::base64(&self.mac),
+            self.original_id,
+            error_name(self.error),
+            self.other.len(),
+        )
+    }
+}
+
+/// the words of a BIND configuration file, with comments left out and quotes taken off
+fn config_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => while chars.next_if(|&c| c != '\n').is_some() {},
+            '/' if chars.next_if_eq(&'/').is_some() => while chars.next_if(|&c| c != '\n').is_some() {},
+            '/' if chars.next_if_eq(&'*').is_some() => {
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            },
+            '"' => words.push(chars.by_ref().take_while(|&c| c != '"').collect()),
+            '{' | '}' | ';' => words.push(c.to_string()),
+            c if c.is_whitespace() => {},
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && !"{};\"".contains(c)) {
+                    word.push(c);
+                }
+                words.push(word);
+            },
+        }
+    }
+    words
+}
+
+/// A key shared with a server for signing messages.
+#[derive(Debug)]
+pub struct Key {
+    pub name: DnsName,
+    pub algorithm: Algorithm,
+    secret: Vec<u8>,
+}
+impl Key {
+    pub fn new(name: DnsName, algorithm: Algorithm, secret: Vec<u8>) -> Self {
+        Self { name, algorithm, secret }
+    }
+
+    /// the first key in a BIND-style key file, as `tsig-keygen` writes them:
+    /// `key "name" { algorithm hmac-sha256; secret "base64"; };`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let words = config_words(text);
+        let start = words.iter().position(|word| word == "key").ok_or("no key statement")?;
+        let (name, body) = match &words[start + 1..] {
+            [name, open, body @ ..] if open == "{" => (name, body),
+            _ => return Err("expected a key name and `{` after `key`".to_string()),
+        };
+
+        let (mut algorithm, mut secret) = (None, None);
+        let mut rest = body;
+        loop {
+            match rest {
+                [close, ..] if close == "}" => break,
+                [option, value, end, tail @ ..] if end == ";" => {
+                    match option.as_str() {
+                        "algorithm" => algorithm = Some(Algorithm::from_name(value).ok_or_else(|| format!("unsupported algorithm {value}, only hmac-sha256 and hmac-sha512 are"))?),
+                        "secret" => secret = Some(encoding::decode_base64(value).ok_or("the secret isn't valid base64")?),
+                        _ => {},
+                    }
+                    rest = tail;
+                },
+                _ => return Err(format!("unexpected end of key {name}")),
+            }
+        }
+        Ok(Self::new(
+            DnsName::from_string(name.trim_end_matches('.')),
+            algorithm.ok_or_else(|| format!("key {name} has no algorithm"))?,
+            secret.ok_or_else(|| format!("key {name} has no secret"))?,
+        ))
+    }
+
+    /// reads the first key in the key file at `path`
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// the TSIG variables signed along with the first message each way (RFC 8945 section 4.3.3)
+    fn variables(&self, tsig: &Tsig) -> Result<Vec<u8>, io::Error> {
+        let mut variables = self.name.to_lowercase().to_bytes();
+        variables.extend(ANY.to_be_bytes());
+        variables.extend(0_u32.to_be_bytes());
+        variables.extend(tsig.algorithm.to_lowercase().to_bytes());
+        variables.extend(tsig.timers());
+        variables.extend(tsig.error.to_be_bytes());
+        variables.extend(length(&tsig.other, "other data")?);
+        variables.extend(&tsig.other);
+        Ok(variables)
+    }
+
+    fn hmac_key(&self) -> hmac::Key {
+        hmac::Key::new(self.algorithm.hmac(), &self.secret)
+    }
+
+    /// what the MAC of `message` (without its TSIG record) is over: the MAC of the message before,
+    /// if any, the messages since then that weren't signed, the message and the TSIG fields
+    fn signed_data(&self, previous_mac: Option<&[u8]>, unsigned: &[u8], message: &[u8], tsig: &Tsig, first: bool) -> Result<Vec<u8>, io::Error> {
+        let mut data = Vec::new();
+        if let Some(previous_mac) = previous_mac {
+            data.extend(length(previous_mac, "MAC")?);
+            data.extend(previous_mac);
+        }
+        data.extend(unsigned);
+        data.extend(message);
+        data.extend(if first { self.variables(tsig)? } else { tsig.timers() });
+        Ok(data)
+    }
+
+    fn record(&self, tsig: Tsig) -> Result<DnsResourceRecord, io::Error> {
+        let rdata_raw = tsig.to_bytes()?;
+        Ok(DnsResourceRecord {
+            name: self.name.clone(),
+            rtype: TSIG,
+            class: ANY,
+            ttl: 0,
+            rdlength: u16::try_from(rdata_raw.len()).map_err(|_| invalid("the TSIG record is longer than 65535 bytes"))?,
+            rdata: DnsRdata::TsigRecord(tsig),
+            rdata_raw,
+        })
+    }
+
+    /// signs `msg` as it is now, returning what checks the signatures of the response
+    pub fn sign(&self, msg: &mut DnsMessage) -> Result<Verifier<'_>, io::Error> {
+        self.sign_at(msg, now())
+    }
+
+    fn sign_at(&self, msg: &mut DnsMessage, time_signed: u64) -> Result<Verifier<'_>, io::Error> {
+        let mut tsig = Tsig {
+            algorithm: DnsName::from_string(self.algorithm.name()),
+            time_signed,
+            fudge: FUDGE,
+            mac: Vec::new(),
+            original_id: msg.id(),
+            error: 0,
+            other: Vec::new(),
+        };
+        tsig.mac = hmac::sign(&self.hmac_key(), &self.signed_data(None, &[], &msg.to_bytes(), &tsig, true)?).as_ref().to_vec();
+        let request_mac = tsig.mac.clone();
+        msg.push_additional(self.record(tsig)?);
+        Ok(Verifier {
+            key: self,
+            previous_mac: request_mac,
+            unsigned: Vec::new(),
+            unsigned_count: 0,
+            first: true,
+        })
+    }
+}
+
+/// splits the TSIG record off the end of a message, if it has one: the message as it was signed,
+/// i.e. without the record and with its original ID, and the record
+fn split_tsig(data: &[u8]) -> Result<Option<(Vec<u8>, DnsResourceRecord)>, io::Error> {
+    let mut cursor = Cursor::new(data);
+    let header = DnsHeader::parse(&mut cursor)?;
+    if header.arcount == 0 {
+        return Ok(None);
+    }
+    for _ in 0..header.qdcount {
+        DnsQuestion::parse(&mut cursor)?;
+    }
+    for _ in 0..u32::from(header.ancount) + u32::from(header.nscount) + u32::from(header.arcount) - 1 {
+        DnsResourceRecord::parse(&mut cursor)?;
+    }
+    let start = usize::try_from(cursor.position()).map_err(|_| invalid("message too long"))?;
+    let record = DnsResourceRecord::parse(&mut cursor)?;
+    let DnsRdata::TsigRecord(tsig) = &record.rdata else {
+        return Ok(None);
+    };
+
+    let mut signed = data[..start].to_vec();
+    signed[..2].copy_from_slice(&tsig.original_id.to_be_bytes());
+    signed[10..12].copy_from_slice(&(header.arcount - 1).to_be_bytes());
+    Ok(Some((signed, record)))
+}
+
+/// Checks the signatures of the messages answering a signed request, in order.
+pub struct Verifier<'a> {
+    key: &'a Key,
+    previous_mac: Vec<u8>,
+    /// the messages since the last signed one
+    unsigned: Vec<u8>,
+    unsigned_count: usize,
+    first: bool,
+}
+impl Verifier<'_> {
+    /// checks the next message of the response. the first and last messages must be signed, and
+    /// at most 99 in a row between them may not be.
+    pub fn verify(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        let Some((message, record)) = split_tsig(data)? else {
+            if self.first {
+                return Err(invalid("the response isn't signed"));
+            }
+            self.unsigned_count += 1;
+            if self.unsigned_count > MAX_UNSIGNED {
+                return Err(invalid("too many unsigned messages in a row"));
+            }
+            self.unsigned.extend(data);
+            return Ok(());
+        };
+        let DnsRdata::TsigRecord(tsig) = &record.rdata else {
+            unreachable!("split_tsig only returns TSIG records");
+        };
+
+        let denied = |message: String| io::Error::new(io::ErrorKind::PermissionDenied, message);
+        if tsig.error != 0 {
+            return Err(denied(format!("the server rejected the signature: {}", error_name(tsig.error))));
+        }
+        if record.name.canonical_cmp(&self.key.name).is_ne() || Algorithm::from_name(&tsig.algorithm.to_string()) != Some(self.key.algorithm) {
+            return Err(denied(format!("the response is signed with another key, {}", record.name)));
+        }
+        // only full-length MACs are accepted, compared in constant time
+        let signed = self.key.signed_data(Some(&self.previous_mac), &self.unsigned, &message, tsig, self.first)?;
+        if hmac::verify(&self.key.hmac_key(), &signed, &tsig.mac).is_err() {
+            return Err(denied("the response's signature doesn't match".to_string()));
+        }
+        if now().abs_diff(tsig.time_signed) > u64::from(tsig.fudge) {
+            return Err(denied("the response was signed too long ago, or our clock is off".to_string()));
+        }
+
+        self.previous_mac.clone_from(&tsig.mac);
+        self.unsigned.clear();
+        self.unsigned_count = 0;
+        self.first = false;
+        Ok(())
+    }
+
+    /// checks that the response ended with a signed message
+    pub fn finish(&self) -> Result<(), io::Error> {
+        if self.first || self.unsigned_count > 0 {
+            return Err(invalid("the end of the response isn't signed"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod util {
+        use super::*;
+
+        pub fn key() -> Key {
+            Key::new(DnsName::from_string("key.example"), Algorithm::HmacSha256, b"secret".to_vec())
+        }
+
+        /// signs `msg` the way a server signs a response, returning its bytes and its MAC
+        pub fn sign_response(key: &Key, previous_mac: &[u8], unsigned: &[u8], mut msg: DnsMessage, first: bool) -> (Vec<u8>, Vec<u8>) {
+            let mut tsig = Tsig {
+                algorithm: DnsName::from_string(key.algorithm.name()),
+                time_signed: now(),
+                fudge: FUDGE,
+                mac: Vec::new(),
+                original_id: msg.id(),
+                error: 0,
+                other: Vec::new(),
+            };
+            tsig.mac = hmac::sign(&key.hmac_key(), &key.signed_data(Some(previous_mac), unsigned, &msg.to_bytes(), &tsig, first).unwrap()).as_ref().to_vec();
+            let mac = tsig.mac.clone();
+            msg.push_additional(key.record(tsig).unwrap());
+            (msg.to_bytes(), mac)
+        }
+
+        pub fn response() -> DnsMessage {
+            DnsMessage::query("example", 252)
+        }
+    }
+
+    #[test]
+    fn key_files() {
+        let text = "# made by tsig-keygen\nkey \"key.example.\" {\n\talgorithm hmac-sha512; // the default is sha256\n\t/* shh */ secret \"c2VjcmV0\";\n};\n";
+        let key = Key::parse(text).unwrap();
+        assert_eq!(key.name, DnsName::from_string("key.example"));
+        assert_eq!(key.algorithm, Algorithm::HmacSha512);
+        assert_eq!(key.secret, b"secret");
+
+        assert_eq!(Key::parse("key k { algorithm hmac-md5; secret \"c2VjcmV0\"; };").unwrap_err(), "unsupported algorithm hmac-md5, only hmac-sha256 and hmac-sha512 are");
+        assert_eq!(Key::parse("key k { algorithm hmac-sha256; };").unwrap_err(), "key k has no secret");
+        assert_eq!(Key::parse("key k { algorithm hmac-sha256;").unwrap_err(), "unexpected end of key k");
+        assert!(Key::parse("options { };").is_err());
+    }
+
+    #[test]
+    fn sign_request() {
+        let key = util::key();
+        let mut msg = DnsMessage::query("example", 252);
+        let unsigned = msg.to_bytes();
+        key.sign_at(&mut msg, 1_700_000_000).unwrap();
+
+        // RFC 8945 section 4.3.3: the key name, class ANY, TTL 0, then the rdata without the MAC and original ID
+        let mut signed = unsigned;
+        signed.extend(b"\x03key\x07example\x00\x00\xff\x00\x00\x00\x00");
+        signed.extend(b"\x0bhmac-sha256\x00\x00\x00\x65\x53\xf1\x00\x01\x2c\x00\x00\x00\x00");
+        let mac = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, b"secret"), &signed);
+
+        let parsed = DnsMessage::parse_any(&msg.to_bytes()).unwrap();
+        let (without, record) = split_tsig(&msg.to_bytes()).unwrap().unwrap();
+        assert_eq!(without, DnsMessage::query("example", 252).to_bytes());
+        assert_eq!(record.to_string(), format!("key.example. 0 CLASS255 TSIG hmac-sha256. 1700000000 300 32 {} 43962 NOERROR 0", encoding::base64(mac.as_ref())));
+        assert_eq!(parsed, msg);
+    }
+
+    #[test]
+    fn verify_responses() {
+        let key = util::key();
+        let mut request = DnsMessage::query("example", 252);
+        let request_mac = key.sign(&mut request).unwrap().previous_mac;
+
+        // a transfer of three messages, the second of which isn't signed
+        let (first, first_mac) = util::sign_response(&key, &request_mac, &[], util::response(), true);
+        let second = util::response().to_bytes();
+        let (third, _) = util::sign_response(&key, &first_mac, &second, util::response(), false);
+        let mut verifier = key.sign(&mut DnsMessage::query("example", 252)).unwrap();
+        verifier.previous_mac.clone_from(&request_mac);
+        for message in [&first, &second, &third] {
+            verifier.verify(message).unwrap();
+        }
+        verifier.finish().unwrap();
+
+        let verify_all = |messages: &[&[u8]]| -> Result<(), io::Error> {
+            let mut verifier = key.sign(&mut DnsMessage::query("example", 252)).unwrap();
+            verifier.previous_mac.clone_from(&request_mac);
+            for message in messages {
+                verifier.verify(message)?;
+            }
+            verifier.finish()
+        };
+        assert_eq!(verify_all(&[&second]).unwrap_err().to_string(), "the response isn't signed");
+        assert_eq!(verify_all(&[&first, &second]).unwrap_err().to_string(), "the end of the response isn't signed");
+        // signed over the first message, so it can't come without it
+        assert_eq!(verify_all(&[&third]).unwrap_err().to_string(), "the response's signature doesn't match");
+
+        let mut tampered = first.clone();
+        tampered[3] ^= 1;
+        assert_eq!(verify_all(&[&tampered]).unwrap_err().to_string(), "the response's signature doesn't match");
+
+        let other = Key::new(DnsName::from_string("other.example"), Algorithm::HmacSha256, b"secret".to_vec());
+        let (signed_by_other, _) = util::sign_response(&other, &request_mac, &[], util::response(), true);
+        assert_eq!(verify_all(&[&signed_by_other]).unwrap_err().to_string(), "the response is signed with another key, other.example");
+
+        let mut rejected = util::response();
+        let mut tsig = Tsig::parse(&split_tsig(&first).unwrap().unwrap().1.rdata_raw).unwrap();
+        tsig.error = 17;
+        tsig.mac.clear();
+        rejected.push_additional(key.record(tsig).unwrap());
+
+        let mut oversized = Tsig::parse(&split_tsig(&first).unwrap().unwrap().1.rdata_raw).unwrap();
+        oversized.other = vec![0; 65536];
+        assert_eq!(key.record(oversized).unwrap_err().to_string(), "the other data is longer than 65535 bytes");
+        assert_eq!(verify_all(&[&rejected.to_bytes()]).unwrap_err().to_string(), "the server rejected the signature: BADKEY");
+    }
+}
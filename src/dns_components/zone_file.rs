@@ -0,0 +1,654 @@
+//! Zone files in the master file format (RFC 1035 section 5): parsing them into records, with
+//! `$ORIGIN`, `$TTL` (RFC 2308 section 4) and `$INCLUDE`, and writing any records back out.
+
+use std::{cmp::Ordering, fs, io::Cursor, path::Path};
+
+use super::{dns_name::DnsName, dns_rdata::{Caa, CharacterString, DnsRdata, Soa, Srv}, dns_rr::{self, DnsResourceRecord}, dnssec::{self, Dnskey, Ds, Nsec, Nsec3, Nsec3Param, Rrsig}, encoding};
+
+/// how deeply `$INCLUDE`s may nest, so that a file including itself doesn't go on forever
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// A word of an entry. Quoted strings keep their blanks, and escapes are left for the record type to interpret.
+struct Token {
+    text: String,
+    quoted: bool,
+}
+
+/// The words of one entry, which parentheses may spread over several lines.
+struct Entry {
+    line: usize,
+    /// whether the entry starts with a blank, meaning it has the same owner as the one before
+    indented: bool,
+    tokens: Vec<Token>,
+}
+
+/// splits a zone file into entries, dropping comments
+fn entries(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    let mut line = 1;
+    let mut current = Entry { line, indented: false, tokens: Vec::new() };
+    let mut depth = 0;
+    let mut at_line_start = true;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                line += 1;
+                if depth == 0 {
+                    if !current.tokens.is_empty() {
+                        entries.push(current);
+                    }
+                    current = Entry { line, indented: false, tokens: Vec::new() };
+                    at_line_start = true;
+                    continue;
+                }
+            },
+            ' ' | '\t' | '\r' => current.indented |= at_line_start && depth == 0,
+            ';' => while chars.next_if(|c| *c != '\n').is_some() {},
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(format!("line {line}: `)` without `(`")),
+            ')' => depth -= 1,
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(format!("line {line}: unterminated string")),
+                        Some('"') => break,
+                        Some(c) => {
+                            line += usize::from(c == '\n');
+                            text.push(c);
+                            // an escaped quote doesn't end the string
+                            if c == '\\' {
+                                text.extend(chars.next());
+                            }
+                        },
+                    }
+                }
+                current.tokens.push(Token { text, quoted: true });
+            },
+            _ => {
+                let mut text = String::from(c);
+                let mut escaped = c == '\\';
+                while let Some(c) = chars.next_if(|c| escaped || !matches!(c, ' ' | '\t' | '\r' | '\n' | ';' | '(' | ')' | '"')) {
+                    escaped = !escaped && c == '\\';
+                    text.push(c);
+                }
+                current.tokens.push(Token { text, quoted: false });
+            },
+        }
+        at_line_start = false;
+    }
+    if depth > 0 {
+        return Err(format!("line {line}: `(` without `)`"));
+    }
+    if !current.tokens.is_empty() {
+        entries.push(current);
+    }
+    Ok(entries)
+}
+
+/// the bytes of a character-string, with `\X` standing for X and `\DDD` for the byte with that decimal value
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match rest {
+            [a, b, c, tail @ ..] if [a, b, c].iter().all(|digit| digit.is_ascii_digit()) => {
+                let value = (u16::from(a - b'0') * 100) + (u16::from(b - b'0') * 10) + u16::from(c - b'0');
+                bytes.push(u8::try_from(value).map_err(|_| format!("invalid escape in {text}"))?);
+                rest = tail;
+            },
+            [escaped, tail @ ..] => {
+                bytes.push(*escaped);
+                rest = tail;
+            },
+            [] => return Err(format!("{text} ends in the middle of an escape")),
+        }
+    }
+    Ok(bytes)
+}
+
+/// `name` as written in a zone file: `@` is the origin, and names without a trailing dot are relative to it
+pub fn name(text: &str, origin: &DnsName) -> Result<DnsName, String> {
+    if text == "@" {
+        return Ok(origin.clone());
+    }
+    let mut name = DnsName::from_text(text)?;
+    // a dot after an odd number of backslashes is part of the last label rather than the end of the name
+    let absolute = text.strip_suffix('.').is_some_and(|head| (head.len() - head.trim_end_matches('\\').len()) % 2 == 0);
+    if !absolute {
+        name.labels.extend(origin.labels.iter().cloned());
+    }
+    Ok(name)
+}
+
+/// a TTL in seconds, or in BIND's units like `1h30m` or `2w`
+fn ttl(text: &str) -> Option<u32> {
+    if let Ok(seconds) = text.parse() {
+        return Some(seconds);
+    }
+    let mut total = 0_u32;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604_800,
+            _ => return None,
+        };
+        total = total.checked_add(number.parse::<u32>().ok()?.checked_mul(unit)?)?;
+        number.clear();
+    }
+    number.is_empty().then_some(total)
+}
+
+/// the class a mnemonic or `CLASS1234` stands for
+fn class(text: &str) -> Option<u16> {
+    match text.to_ascii_uppercase().as_str() {
+        "IN" => Some(1),
+        "CH" => Some(3),
+        "HS" => Some(4),
+        other => other.strip_prefix("CLASS")?.parse().ok(),
+    }
+}
+
+/// the rdata of `rtype` written in the generic form of RFC 3597 section 5: `\# length hex`
+fn generic_rdata(rtype: u16, fields: &[&str]) -> Result<DnsRdata, String> {
+    let (length, hex) = fields.split_first().ok_or("missing rdata length")?;
+    let length: usize = length.parse().map_err(|_| format!("invalid rdata length {length}"))?;
+    let data = encoding::decode_hex(&hex.concat()).ok_or("invalid hex in rdata")?;
+    if data.len() != length {
+        return Err(format!("rdata is {} bytes long, not {length}", data.len()));
+    }
+    // decodes the types we know as if the rdata had come over the wire
+    let mut wire = vec![0];
+    wire.extend(rtype.to_be_bytes());
+    wire.extend([0, 1, 0, 0, 0, 0]);
+    wire.extend(u16::try_from(length).map_err(|_| "rdata too long")?.to_be_bytes());
+    wire.extend(data);
+    DnsResourceRecord::parse(&mut Cursor::new(&wire[..])).map(|record| record.rdata).map_err(|e| e.to_string())
+}
+
+/// the rdata of `rtype` from its presentation format
+fn rdata(rtype: u16, tokens: &[Token], origin: &DnsName) -> Result<DnsRdata, String> {
+    let fields: Vec<&str> = tokens.iter().map(|token| token.text.as_str()).collect();
+    if tokens.first().is_some_and(|token| !token.quoted && token.text == "\\#") {
+        return generic_rdata(rtype, &fields[1..]);
+    }
+    let type_name = dns_rr::type_name(rtype);
+    let field = |i: usize| fields.get(i).copied().ok_or_else(|| format!("{type_name} record is missing field {}", i + 1));
+    let invalid = |i: usize| format!("invalid field {} in {type_name} record: {}", i + 1, fields.get(i).unwrap_or(&""));
+    let number = |i: usize| field(i)?.parse::<u32>().map_err(|_| invalid(i));
+    let small = |i: usize| number(i).and_then(|value| u16::try_from(value).map_err(|_| invalid(i)));
+    let byte = |i: usize| number(i).and_then(|value| u8::try_from(value).map_err(|_| invalid(i)));
+    let seconds = |i: usize| ttl(field(i)?).ok_or_else(|| invalid(i));
+    let time = |i: usize| dnssec::parse_time(field(i)?).ok_or_else(|| invalid(i));
+    let name = |i: usize| field(i).and_then(|text| name(text, origin));
+    let rest = |i: usize| fields.get(i..).unwrap_or_default().concat();
+    let base64 = |i: usize| encoding::decode_base64(&rest(i)).ok_or_else(|| invalid(i));
+    let salt = |i: usize| match field(i)? {
+        "-" => Ok(Vec::new()),
+        text => encoding::decode_hex(text).ok_or_else(|| invalid(i)),
+    };
+    let types = |i: usize| {
+        (i..fields.len()).map(|i| dns_rr::type_code(fields[i]).ok_or_else(|| invalid(i))).collect::<Result<Vec<u16>, String>>()
+    };
+
+    let expected = match rtype {
+        1 | 2 | 5 | 12 | 28 => 1,
+        15 => 2,
+        257 => 3,
+        33 | 51 => 4,
+        6 => 7,
+        _ => usize::MAX,
+    };
+    if fields.len() > expected {
+        return Err(format!("too many fields in {type_name} record"));
+    }
+
+    let rdata = match rtype {
+        1 => DnsRdata::ARecord(field(0)?.parse().map_err(|_| invalid(0))?),
+        2 => DnsRdata::NsRecord(name(0)?),
+        5 => DnsRdata::CnameRecord(name(0)?),
+        6 => DnsRdata::SoaRecord(Soa {
+            mname: name(0)?,
+            rname: name(1)?,
+            serial: number(2)?,
+            refresh: seconds(3)?,
+            retry: seconds(4)?,
+            expire: seconds(5)?,
+            minimum: seconds(6)?,
+        }),
+        12 => DnsRdata::PtrRecord(name(0)?),
+        15 => DnsRdata::MxRecord(small(0)?, name(1)?),
+        16 if tokens.is_empty() => return Err("TXT record without strings".to_string()),
+        16 => DnsRdata::TxtRecord(tokens.iter().map(|token| CharacterString::new(unescape(&token.text)?)).collect::<Result<_, _>>()?),
+        28 => DnsRdata::AaaaRecord(field(0)?.parse().map_err(|_| invalid(0))?),
+        33 => DnsRdata::SrvRecord(Srv {
+            priority: small(0)?,
+            weight: small(1)?,
+            port: small(2)?,
+            target: name(3)?,
+        }),
+        43 => DnsRdata::DsRecord(Ds {
+            key_tag: small(0)?,
+            algorithm: byte(1)?,
+            digest_type: byte(2)?,
+            digest: encoding::decode_hex(&rest(3)).ok_or_else(|| invalid(3))?,
+        }),
+        46 => DnsRdata::RrsigRecord(Rrsig {
+            type_covered: dns_rr::type_code(field(0)?).ok_or_else(|| invalid(0))?,
+            algorithm: byte(1)?,
+            labels: byte(2)?,
+            original_ttl: seconds(3)?,
+            expiration: time(4)?,
+            inception: time(5)?,
+            key_tag: small(6)?,
+            signer: name(7)?,
+            signature: base64(8)?,
+        }),
+        47 => DnsRdata::NsecRecord(Nsec {
+            next: name(0)?,
+            types: types(1)?,
+        }),
+        48 => DnsRdata::DnskeyRecord(Dnskey {
+            flags: small(0)?,
+            protocol: byte(1)?,
+            algorithm: byte(2)?,
+            public_key: base64(3)?,
+        }),
+        50 => DnsRdata::Nsec3Record(Nsec3 {
+            hash_algorithm: byte(0)?,
+            flags: byte(1)?,
+            iterations: small(2)?,
+            salt: salt(3)?,
+            next_hashed: encoding::decode_base32hex(field(4)?).ok_or_else(|| invalid(4))?,
+            types: types(5)?,
+        }),
+        51 => DnsRdata::Nsec3ParamRecord(Nsec3Param {
+            hash_algorithm: byte(0)?,
+            flags: byte(1)?,
+            iterations: small(2)?,
+            salt: salt(3)?,
+        }),
+        257 => DnsRdata::CaaRecord(Caa {
+            flags: byte(0)?,
+            tag: Some(field(1)?).filter(|tag| Caa::valid_tag(tag)).ok_or_else(|| invalid(1))?.to_string(),
+            value: unescape(field(2)?)?,
+        }),
+        // these only appear in queries and transfers, never as records of a zone (RFC 6895 section 3.1)
+        41 | 250..=255 => return Err(format!("{type_name} is a meta-type, not a type of record a zone can hold")),
+        _ => return Err(format!("{type_name} records can only be written in the generic `\\# length hex` form")),
+    };
+    Ok(rdata)
+}
+
+/// What carries over from one entry to the next.
+struct Parser {
+    origin: DnsName,
+    /// set by `$TTL`
+    default_ttl: Option<u32>,
+    /// without a `$TTL`, records take the TTL of the record before them (RFC 1035 section 5.1)
+    last_ttl: Option<u32>,
+    last_owner: Option<DnsName>,
+    last_class: u16,
+    includes: usize,
+    records: Vec<DnsResourceRecord>,
+}
+impl Parser {
+    /// parses the text of `file`, which `$INCLUDE`s are relative to
+    fn parse(&mut self, text: &str, file: &str, dir: &Path) -> Result<(), String> {
+        for entry in entries(text).map_err(|e| format!("{file}: {e}"))? {
+            let located = |e: String| format!("{file}: line {}: {e}", entry.line);
+            let directive = (!entry.indented).then(|| entry.tokens[0].text.to_ascii_uppercase());
+            match directive.as_deref() {
+                Some("$ORIGIN") => {
+                    let origin = entry.tokens.get(1).ok_or_else(|| located("$ORIGIN without a name".to_string()))?;
+                    self.origin = name(&origin.text, &self.origin).map_err(located)?;
+                },
+                Some("$TTL") => {
+                    let text = &entry.tokens.get(1).ok_or_else(|| located("$TTL without a TTL".to_string()))?.text;
+                    self.default_ttl = Some(ttl(text).ok_or_else(|| located(format!("invalid TTL {text}")))?);
+                },
+                Some("$INCLUDE") => self.include(&entry.tokens, dir, located)?,
+                Some(other) if other.starts_with('$') => return Err(located(format!("unknown directive {other}"))),
+                _ => self.record(&entry).map_err(located)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// `$INCLUDE file [origin]`: the file's records, with the origin only changed while reading it.
+    /// errors in the included file say where they are themselves.
+    fn include(&mut self, tokens: &[Token], dir: &Path, located: impl Fn(String) -> String) -> Result<(), String> {
+        let file = tokens.get(1).ok_or_else(|| located("$INCLUDE without a file".to_string()))?;
+        if self.includes >= MAX_INCLUDE_DEPTH {
+            return Err(located(format!("$INCLUDEs nested more than {MAX_INCLUDE_DEPTH} deep")));
+        }
+        let path = dir.join(&file.text);
+        let text = fs::read_to_string(&path).map_err(|e| located(format!("can't read {}: {e}", path.display())))?;
+
+        let origin = self.origin.clone();
+        if let Some(included_origin) = tokens.get(2) {
+            self.origin = name(&included_origin.text, &origin).map_err(&located)?;
+        }
+        self.includes += 1;
+        let result = self.parse(&text, &path.display().to_string(), path.parent().unwrap_or(dir));
+        self.includes -= 1;
+        self.origin = origin;
+        result
+    }
+
+    fn record(&mut self, entry: &Entry) -> Result<(), String> {
+        let tokens = &entry.tokens;
+        let (owner, mut rest) = if entry.indented {
+            (self.last_owner.clone().ok_or("record without an owner")?, &tokens[..])
+        } else {
+            (name(&tokens[0].text, &self.origin)?, &tokens[1..])
+        };
+
+        // the TTL and the class are both optional, and may come in either order
+        let mut record_ttl = None;
+        let mut record_class = None;
+        while let Some((token, tail)) = rest.split_first() {
+            if record_ttl.is_none() && token.text.starts_with(|c: char| c.is_ascii_digit()) {
+                record_ttl = Some(ttl(&token.text).ok_or_else(|| format!("invalid TTL {}", token.text))?);
+            } else if let Some(code) = record_class.is_none().then(|| class(&token.text)).flatten() {
+                record_class = Some(code);
+            } else {
+                break;
+            }
+            rest = tail;
+        }
+        let (rtype, rdata_tokens) = rest.split_first().ok_or("record without a type")?;
+        let rtype = dns_rr::type_code(&rtype.text).ok_or_else(|| format!("unknown record type {}", rtype.text))?;
+        let rdata = rdata(rtype, rdata_tokens, &self.origin)?;
+
+        let ttl = match (record_ttl, &rdata) {
+            (Some(ttl), _) => {
+                self.last_ttl = Some(ttl);
+                ttl
+            },
+            (None, _) if self.default_ttl.or(self.last_ttl).is_some() => self.default_ttl.or(self.last_ttl).unwrap_or_default(),
+            // the way BIND fills in a missing TTL when there's nothing else to go by
+            (None, DnsRdata::SoaRecord(soa)) => {
+                self.last_ttl = Some(soa.minimum);
+                soa.minimum
+            },
+            (None, _) => return Err("record without a TTL, and no $TTL before it".to_string()),
+        };
+        let class = record_class.unwrap_or(self.last_class);
+        self.last_class = class;
+        self.last_owner = Some(owner.clone());
+
+        let rdata_raw = rdata.to_bytes();
+        self.records.push(DnsResourceRecord {
+            name: owner,
+            rtype,
+            class,
+            ttl,
+            rdlength: u16::try_from(rdata_raw.len()).map_err(|_| "rdata too long")?,
+            rdata,
+            rdata_raw,
+        });
+        Ok(())
+    }
+}
+
+fn parse_file(text: &str, file: &str, origin: &DnsName, default_ttl: Option<u32>, dir: &Path) -> Result<Vec<DnsResourceRecord>, String> {
+    let mut parser = Parser {
+        origin: origin.clone(),
+        default_ttl,
+        last_ttl: None,
+        last_owner: None,
+        last_class: 1,
+        includes: 0,
+        records: Vec::new(),
+    };
+    parser.parse(text, file, dir)?;
+    Ok(parser.records)
+}
+
+/// parses the text of a zone file. relative names are relative to `origin` until a `$ORIGIN` says otherwise,
+/// and `$INCLUDE`d files are looked for in `dir`.
+pub fn parse(text: &str, origin: &DnsName, dir: &Path) -> Result<Vec<DnsResourceRecord>, String> {
+    parse_file(text, "zone", origin, None, dir)
+}
+
+/// parses a single record written as in a zone file, taking `default_ttl` if it doesn't give one
+pub fn parse_record(text: &str, origin: &DnsName, default_ttl: u32) -> Result<DnsResourceRecord, String> {
+    match parse_file(text, &format!("record `{text}`"), origin, Some(default_ttl), Path::new("."))?.as_slice() {
+        [record] => Ok(record.clone()),
+        _ => Err(format!("expected a single record, got `{text}`")),
+    }
+}
+
+/// reads and parses the zone file at `path`
+pub fn load(path: &Path, origin: &DnsName) -> Result<Vec<DnsResourceRecord>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {e}", path.display()))?;
+    parse_file(&text, &path.display().to_string(), origin, None, path.parent().unwrap_or(Path::new(".")))
+}
+
+/// the order records are written in: by owner in canonical order, with the SOA first, then by type and rdata
+fn record_order(a: &DnsResourceRecord, b: &DnsResourceRecord) -> Ordering {
+    a.name.canonical_cmp(&b.name)
+        .then((a.rtype != 6).cmp(&(b.rtype != 6)))
+        .then(a.rtype.cmp(&b.rtype))
+        .then_with(|| a.rdata.to_bytes().cmp(&b.rdata.to_bytes()))
+}
+
+/// `records` as a zone file: one record per line with absolute names, sorted and without duplicates,
+/// so that two sets of records can be compared with a plain diff
+pub fn write(records: &[DnsResourceRecord]) -> String {
+    let mut sorted: Vec<&DnsResourceRecord> = records.iter().collect();
+    sorted.sort_by(|a, b| record_order(a, b));
+    sorted.dedup_by(|a, b| record_order(a, b) == Ordering::Equal && a.class == b.class);
+    sorted.iter().map(|record| format!("{record}\n")).collect()
+}
+
+/// writes `records` to `path` as [`write`] does. the file is written next to `path` first and then renamed over it,
+/// so that `path` is never left half written.
+pub fn save(path: &Path, records: &[DnsResourceRecord]) -> Result<(), String> {
+    let name = path.file_name().ok_or_else(|| format!("{} isn't a file name", path.display()))?;
+    let temporary = path.with_file_name(format!(".{}.inloc-tmp", name.to_string_lossy()));
+    fs::write(&temporary, write(records))
+        .and_then(|()| fs::rename(&temporary, path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temporary);
+            format!("can't write {}: {e}", path.display())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod util {
+        pub const ZONE: &str = r#"
+$ORIGIN example.com.
+$TTL 1h
+@   IN  SOA ns1 hostmaster (
+            2024010101 ; serial
+            2h 15m 2w 300 )
+        NS  ns1
+        NS  ns2.example.net.
+        MX  10 mail
+ns1 300 IN A 192.0.2.1
+mail    IN 600 AAAA 2001:db8::25
+        TXT "v=spf1 -all" "quote \" and \059"
+www CNAME   @
+raw TYPE65534 \# 3 0A0B0C
+ds      DS 12345 13 2 (
+            49FD46E6C4B45C55D4AC69CBD3CD3440
+            9CE30DA24A55A35B91FA1BAB1B7D9D1D )
+"#;
+    }
+
+    fn text(records: &[DnsResourceRecord]) -> Vec<String> {
+        records.iter().map(DnsResourceRecord::to_string).collect()
+    }
+
+    #[test]
+    fn parse_zone() {
+        let records = parse(util::ZONE, &DnsName::from_string("."), Path::new(".")).unwrap();
+        assert_eq!(text(&records), [
+            "example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. 2024010101 7200 900 1209600 300",
+            "example.com. 3600 IN NS ns1.example.com.",
+            "example.com. 3600 IN NS ns2.example.net.",
+            "example.com. 3600 IN MX 10 mail.example.com.",
+            "ns1.example.com. 300 IN A 192.0.2.1",
+            "mail.example.com. 600 IN AAAA 2001:db8::25",
+            r#"mail.example.com. 3600 IN TXT "v=spf1 -all" "quote \" and ;""#,
+            "www.example.com. 3600 IN CNAME example.com.",
+            r"raw.example.com. 3600 IN TYPE65534 \# 3 0A0B0C",
+            "ds.example.com. 3600 IN DS 12345 13 2 49FD46E6C4B45C55D4AC69CBD3CD34409CE30DA24A55A35B91FA1BAB1B7D9D1D",
+        ]);
+        assert_eq!(records[4].rdata_raw, [192, 0, 2, 1]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let origin = DnsName::from_string("example.com");
+        let error = |text: &str| parse(text, &origin, Path::new(".")).unwrap_err();
+        assert_eq!(error("www A 192.0.2.1"), "zone: line 1: record without a TTL, and no $TTL before it");
+        assert_eq!(error("$TTL 60\n\nwww A 192.0.2.300"), "zone: line 3: invalid field 1 in A record: 192.0.2.300");
+        assert_eq!(error("$TTL 60\nwww A ( 192.0.2.1"), "zone: line 2: `(` without `)`");
+        assert_eq!(error("$TTL 60\nwww BOGUS 1"), "zone: line 2: unknown record type BOGUS");
+        assert_eq!(error("$TTL 60\nwww A 192.0.2.1 192.0.2.2"), "zone: line 2: too many fields in A record");
+    }
+
+    #[test]
+    fn include_and_write() {
+        let dir = std::env::temp_dir().join(format!("inloc-test-{}-zone", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("hosts.db"), "a 60 A 192.0.2.1\nb 60 A 192.0.2.2\n").unwrap();
+        fs::write(dir.join("zone.db"), "$TTL 60\n@ SOA ns hostmaster 1 2 3 4 5\n$INCLUDE hosts.db sub\nc A 192.0.2.3\nb.sub A 192.0.2.2\n").unwrap();
+        let records = load(&dir.join("zone.db"), &DnsName::from_string("example")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(write(&records), [
+            "example. 60 IN SOA ns.example. hostmaster.example. 1 2 3 4 5\n",
+            "c.example. 60 IN A 192.0.2.3\n",
+            "a.sub.example. 60 IN A 192.0.2.1\n",
+            "b.sub.example. 60 IN A 192.0.2.2\n",
+        ].concat());
+        // what's written can be read back
+        let reparsed = parse(&write(&records), &DnsName::from_string("."), Path::new(".")).unwrap();
+        assert_eq!(write(&reparsed), write(&records));
+    }
+
+    #[test]
+    fn escaped_names() {
+        let zone = "$ORIGIN example.\n$TTL 60\n\
+            first\\.last MX 10 mail\\.\n\
+            dots\\\\. CNAME sp\\032ace\\;.example.\n";
+        let records = parse(zone, &DnsName::from_string("."), Path::new(".")).unwrap();
+        assert_eq!(records[0].name.labels[0].label, b"first.last");
+        assert_eq!(records[0].rdata, DnsRdata::MxRecord(10, DnsName::from_text(r"mail\..example").unwrap()));
+        assert_eq!(records[1].name.labels.len(), 1);
+        assert_eq!(text(&records), [
+            r"first\.last.example. 60 IN MX 10 mail\..example.",
+            r"dots\\. 60 IN CNAME sp\032ace\;.example.",
+        ]);
+        let reparsed = parse(&write(&records), &DnsName::from_string("."), Path::new(".")).unwrap();
+        assert_eq!(write(&reparsed), write(&records));
+        assert_eq!(
+            parse("$TTL 60\na..b A 192.0.2.1", &DnsName::from_string("example"), Path::new(".")).unwrap_err(),
+            "zone: line 2: empty label in a..b",
+        );
+    }
+
+    #[test]
+    fn save_replaces_file() {
+        let dir = std::env::temp_dir().join(format!("inloc-test-{}-save", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("zone.db");
+        fs::write(&path, "; old\n").unwrap();
+        let records = parse("$TTL 60\n@ A 192.0.2.1\n", &DnsName::from_string("example"), Path::new(".")).unwrap();
+        save(&path, &records).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        let left = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(written, write(&records));
+        assert_eq!(left, 1);
+    }
+
+    #[test]
+    fn dnssec_round_trip() {
+        let zone = "example. 3600 IN RRSIG A 13 1 3600 20241101000000 20241011000000 2371 example. AAECAw==\n\
+            example. 3600 IN NSEC a.example. A NS SOA RRSIG NSEC\n\
+            example. 3600 IN DNSKEY 257 3 13 AQID BAU=\n\
+            example. 3600 IN NSEC3PARAM 1 0 0 -\n\
+            0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example. 3600 IN NSEC3 1 1 12 AABBCCDD 2T7B4G4VSA5SMI47K61MV5BV1A22BOJR MX DNSKEY\n";
+        let records = parse(zone, &DnsName::from_string("."), Path::new(".")).unwrap();
+        assert_eq!(records.len(), 5);
+        assert_eq!(text(&records[2..3]), ["example. 3600 IN DNSKEY 257 3 13 AQIDBAU="]);
+        let reparsed = parse(&write(&records), &DnsName::from_string("."), Path::new(".")).unwrap();
+        assert_eq!(write(&reparsed), write(&records));
+    }
+
+    #[test]
+    fn txt_round_trip() {
+        let strings: Vec<CharacterString> = [&b"tab\tnewline\n\x1b[0m"[..], &[0x00, 0xff, b'"', b'\\', b';'], &[]].iter()
+            .map(|bytes| CharacterString::new(bytes.to_vec()).unwrap())
+            .collect();
+        let records = vec![DnsResourceRecord {
+            name: DnsName::from_string("example"),
+            rtype: 16,
+            class: 1,
+            ttl: 60,
+            rdlength: 0,
+            rdata: DnsRdata::TxtRecord(strings.clone()),
+            rdata_raw: Vec::new(),
+        }];
+        let written = write(&records);
+        assert_eq!(written.trim_end(), r#"example. 60 IN TXT "tab\009newline\010\027[0m" "\000\255\"\\;" """#);
+        let reparsed = parse(&written, &DnsName::from_string("."), Path::new(".")).unwrap();
+        assert_eq!(reparsed[0].rdata, DnsRdata::TxtRecord(strings));
+
+        let long = format!("$TTL 60\n@ TXT ok \"{}\"\n", "x".repeat(256));
+        assert_eq!(
+            parse(&long, &DnsName::from_string("example"), Path::new(".")).unwrap_err(),
+            "zone: line 2: character-string is 256 bytes long, more than 255",
+        );
+    }
+
+    #[test]
+    fn srv_and_caa_round_trip() {
+        let zone = "$ORIGIN example.\n$TTL 60\n\
+            _sip._tcp SRV 10 60 5060 sip\n\
+            _sip._udp SRV 0 0 0 .\n\
+            @ CAA 0 issue \"ca.example.net; account=230123\"\n\
+            @ CAA 128 iodef \"mailto:security@example\"\n\
+            raw SRV \\# 8 0001000200030000\n";
+        let records = parse(zone, &DnsName::from_string("."), Path::new(".")).unwrap();
+        assert_eq!(text(&records), [
+            "_sip._tcp.example. 60 IN SRV 10 60 5060 sip.example.",
+            "_sip._udp.example. 60 IN SRV 0 0 0 .",
+            r#"example. 60 IN CAA 0 issue "ca.example.net; account=230123""#,
+            r#"example. 60 IN CAA 128 iodef "mailto:security@example""#,
+            "raw.example. 60 IN SRV 1 2 3 .",
+        ]);
+        assert_eq!(records[2].rdata_raw, b"\x00\x05issueca.example.net; account=230123");
+        let reparsed = parse(&write(&records), &DnsName::from_string("."), Path::new(".")).unwrap();
+        assert_eq!(write(&reparsed), write(&records));
+
+        let origin = DnsName::from_string("example");
+        let error = |text: &str| parse(text, &origin, Path::new(".")).unwrap_err();
+        assert_eq!(error("$TTL 60\n@ CAA 0 is-sue x"), "zone: line 2: invalid field 2 in CAA record: is-sue");
+        assert_eq!(error("$TTL 60\n@ SRV 0 0 70000 sip"), "zone: line 2: invalid field 3 in SRV record: 70000");
+        assert_eq!(error("$TTL 60\n@ AXFR x"), "zone: line 2: AXFR is a meta-type, not a type of record a zone can hold");
+    }
+}
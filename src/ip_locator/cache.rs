@@ -0,0 +1,172 @@
+//! A persistent cache of locations from web providers, so that addresses seen on earlier runs
+//! don't have to be looked up again until their entries expire.
+//!
+//! Everything lives in one JSON file, keyed by the provider's server and then by address.
+
+use std::{collections::HashMap, fs, io, net::IpAddr, path::{Path, PathBuf}, sync::Mutex, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use serde::{Deserialize, Serialize};
+
+use super::LocationData;
+
+const FILE_NAME: &str = "geo-cache.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Entry {
+    /// seconds since the unix epoch
+    stored: u64,
+    location: LocationData,
+}
+
+type Entries = HashMap<String, HashMap<IpAddr, Entry>>;
+
+pub struct GeoCache {
+    path: PathBuf,
+    max_age: Duration,
+    entries: Mutex<Entries>,
+}
+
+/// How many entries the cache holds for one provider.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ProviderStats {
+    pub server: String,
+    pub fresh: usize,
+    pub expired: usize,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+impl GeoCache {
+    /// `$XDG_CACHE_HOME/inloc/geo-cache.json`, or `~/.cache/inloc/geo-cache.json` when that isn't set
+    pub fn default_path() -> Option<PathBuf> {
+        Some(crate::config::xdg_dir("XDG_CACHE_HOME", ".cache")?.join(FILE_NAME))
+    }
+
+    /// loads the cache at `path`. a missing file is an empty cache; so is an unreadable one,
+    /// since losing the cache only costs a few lookups.
+    pub fn open(path: &Path, max_age: Duration) -> Self {
+        let entries = match fs::read(path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                eprintln!("ignoring the geolocation cache at {}: {e}", path.display());
+                Entries::new()
+            }),
+            Err(_) => Entries::new(),
+        };
+        Self {
+            path: path.to_path_buf(),
+            max_age,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn is_fresh(&self, entry: &Entry, now: u64) -> bool {
+        now.saturating_sub(entry.stored) < self.max_age.as_secs()
+    }
+
+    /// the cached location of `ip` according to `server`, unless it has expired
+    pub fn get(&self, server: &str, ip: IpAddr) -> Option<LocationData> {
+        let entries = self.entries();
+        let entry = entries.get(server)?.get(&ip)?;
+        self.is_fresh(entry, now()).then(|| entry.location.clone())
+    }
+
+    pub fn insert(&self, server: &str, ip: IpAddr, location: LocationData) {
+        let entry = Entry { stored: now(), location };
+        self.entries().entry(server.to_string()).or_default().insert(ip, entry);
+    }
+
+    /// writes the cache back to disk, leaving out expired entries
+    pub fn save(&self) -> Result<(), io::Error> {
+        let now = now();
+        let mut entries = self.entries();
+        for addresses in entries.values_mut() {
+            addresses.retain(|_, entry| self.is_fresh(entry, now));
+        }
+        entries.retain(|_, addresses| !addresses.is_empty());
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // write next to the cache and rename, so that an interrupted run can't leave half a file behind
+        let temporary = self.path.with_extension("json.tmp");
+        fs::write(&temporary, serde_json::to_vec(&*entries)?)?;
+        fs::rename(&temporary, &self.path)
+    }
+
+    pub fn stats(&self) -> Vec<ProviderStats> {
+        let now = now();
+        let mut stats: Vec<ProviderStats> = self.entries().iter()
+            .map(|(server, addresses)| {
+                let fresh = addresses.values().filter(|entry| self.is_fresh(entry, now)).count();
+                ProviderStats { server: server.clone(), fresh, expired: addresses.len() - fresh }
+            })
+            .collect();
+        stats.sort_by(|a, b| a.server.cmp(&b.server));
+        stats
+    }
+
+    /// removes the cache file at `path`, if there is one
+    pub fn clear(path: &Path) -> Result<(), io::Error> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod util {
+        use super::*;
+
+        /// a cache file path that no other test uses
+        pub fn path(name: &str) -> PathBuf {
+            std::env::temp_dir().join(format!("inloc-test-{}-{name}", std::process::id())).join(FILE_NAME)
+        }
+    }
+
+    #[test]
+    fn round_trip_through_disk() {
+        let path = util::path("round-trip");
+        let ip: IpAddr = "93.184.215.14".parse().unwrap();
+        let location = LocationData { city: "London".to_string(), lat: Some(51.5), ..Default::default() };
+
+        let cache = GeoCache::open(&path, Duration::from_secs(3600));
+        assert_eq!(cache.get("ip-api.com", ip), None);
+        cache.insert("ip-api.com", ip, location.clone());
+        cache.save().unwrap();
+
+        let reopened = GeoCache::open(&path, Duration::from_secs(3600));
+        assert_eq!(reopened.get("ip-api.com", ip), Some(location));
+        // every provider has its own entries
+        assert_eq!(reopened.get("ipinfo.io", ip), None);
+        assert_eq!(reopened.stats(), vec![ProviderStats { server: "ip-api.com".to_string(), fresh: 1, expired: 0 }]);
+
+        GeoCache::clear(&path).unwrap();
+        GeoCache::clear(&path).unwrap();
+        assert!(GeoCache::open(&path, Duration::from_secs(3600)).stats().is_empty());
+    }
+
+    #[test]
+    fn expired_entries() {
+        let path = util::path("expired");
+        let ip: IpAddr = "1.1.1.1".parse().unwrap();
+
+        let cache = GeoCache::open(&path, Duration::ZERO);
+        cache.insert("ip-api.com", ip, LocationData::default());
+        assert_eq!(cache.get("ip-api.com", ip), None);
+        assert_eq!(cache.stats(), vec![ProviderStats { server: "ip-api.com".to_string(), fresh: 0, expired: 1 }]);
+        // expired entries aren't written back
+        cache.save().unwrap();
+        assert!(GeoCache::open(&path, Duration::from_secs(3600)).stats().is_empty());
+        GeoCache::clear(&path).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+//! Offline geolocation from IP range CSV databases, in the style of IP2Location and DB-IP:
+//! every row holds the first and last address of a range followed by location columns.
+
+use std::{fs::File, io::{BufRead, BufReader}, net::IpAddr, path::Path};
+
+use super::{GeoProvider, LocateError, LocationData};
+
+/// Which column (counting from 0) holds each field. Fields without a column stay empty.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ColumnMap {
+    pub start: usize,
+    pub end: usize,
+    pub continent: Option<usize>,
+    pub country: Option<usize>,
+    pub country_code: Option<usize>,
+    pub region: Option<usize>,
+    pub city: Option<usize>,
+    pub zip: Option<usize>,
+    pub lat: Option<usize>,
+    pub lon: Option<usize>,
+    pub timezone: Option<usize>,
+    pub isp: Option<usize>,
+    pub org: Option<usize>,
+    pub asn: Option<usize>,
+}
+impl ColumnMap {
+    /// parses either a preset name (`ip2location`, `dbip`) or a list like `start=0,end=1,country_code=2,city=5`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec {
+            "ip2location" => return Self::parse("start=0,end=1,country_code=2,country=3,region=4,city=5"),
+            "dbip" => return Self::parse("start=0,end=1,continent=2,country_code=3,region=4,city=5"),
+            _ => {},
+        }
+
+        let mut map = Self::default();
+        let (mut start, mut end) = (None, None);
+        for entry in spec.split(',') {
+            let (field, column) = entry.split_once('=').ok_or_else(|| format!("expected field=column, got {entry}"))?;
+            let column: usize = column.trim().parse().map_err(|_| format!("invalid column number in {entry}"))?;
+            match field.trim() {
+                "start" => start = Some(column),
+                "end" => end = Some(column),
+                "continent" => map.continent = Some(column),
+                "country" => map.country = Some(column),
+                "country_code" => map.country_code = Some(column),
+                "region" => map.region = Some(column),
+                "city" => map.city = Some(column),
+                "zip" => map.zip = Some(column),
+                "lat" => map.lat = Some(column),
+                "lon" => map.lon = Some(column),
+                "timezone" => map.timezone = Some(column),
+                "isp" => map.isp = Some(column),
+                "org" => map.org = Some(column),
+                "asn" => map.asn = Some(column),
+                other => return Err(format!("unknown field {other}")),
+            }
+        }
+        map.start = start.ok_or("the column map needs a start column")?;
+        map.end = end.ok_or("the column map needs an end column")?;
+        Ok(map)
+    }
+
+    fn location(&self, row: &[String]) -> LocationData {
+        let get = |column: Option<usize>| {
+            let value = column.and_then(|column| row.get(column)).map_or("", |value| value.trim());
+            // both vendors use `-` for "unknown"
+            if value == "-" { String::new() } else { value.to_string() }
+        };
+        let mut continent = get(self.continent);
+        if continent.len() == 2 {
+            continent = super::continent_name(&continent).to_string();
+        }
+        LocationData {
+            continent,
+            country: get(self.country),
+            country_code: get(self.country_code),
+            region_name: get(self.region),
+            city: get(self.city),
+            zip: get(self.zip),
+            lat: get(self.lat).parse().ok(),
+            lon: get(self.lon).parse().ok(),
+            timezone: get(self.timezone),
+            isp: get(self.isp),
+            org: get(self.org),
+            asn: get(self.asn),
+            ..Default::default()
+        }
+    }
+}
+
+/// splits a CSV line, handling quoted fields and doubled quotes inside them
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// A range bound as found in the file: either an address or a plain integer, as IP2Location writes them.
+/// Returns whether the value is IPv4, and the value itself.
+fn parse_bound(value: &str) -> Option<(bool, u128)> {
+    let value = value.trim();
+    if let Ok(addr) = value.parse::<IpAddr>() {
+        return Some(match addr {
+            IpAddr::V4(v4) => (true, u128::from(u32::from(v4))),
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => (true, u128::from(u32::from(v4))),
+                None => (false, u128::from(v6)),
+            },
+        });
+    }
+    let n: u128 = value.parse().ok()?;
+    if n <= u128::from(u32::MAX) {
+        return Some((true, n));
+    }
+    // IPv4-mapped ranges (::ffff:0:0/96) in IPv6 files describe IPv4 addresses
+    if n >> 32 == 0xFFFF {
+        return Some((true, n & u128::from(u32::MAX)));
+    }
+    Some((false, n))
+}
+
+/// Sorted, non-overlapping ranges, searched with a binary search.
+#[derive(Default)]
+struct RangeIndex {
+    ranges: Vec<(u128, u128, usize)>,
+}
+impl RangeIndex {
+    fn find(&self, ip: u128) -> Option<usize> {
+        let after = self.ranges.partition_point(|(start, _, _)| *start <= ip);
+        let (_, end, record) = self.ranges.get(after.checked_sub(1)?)?;
+        (ip <= *end).then_some(*record)
+    }
+}
+
+pub struct CsvProvider {
+    records: Vec<LocationData>,
+    v4: RangeIndex,
+    v6: RangeIndex,
+}
+impl CsvProvider {
+    pub fn open(paths: &[impl AsRef<Path>], columns: &ColumnMap) -> Result<Self, String> {
+        let mut provider = Self {
+            records: Vec::new(),
+            v4: RangeIndex::default(),
+            v6: RangeIndex::default(),
+        };
+        for path in paths {
+            let path = path.as_ref();
+            let file = File::open(path).map_err(|e| format!("can't read {}: {e}", path.display()))?;
+            provider.load(BufReader::new(file), columns).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+        provider.v4.ranges.sort_unstable();
+        provider.v6.ranges.sort_unstable();
+        Ok(provider)
+    }
+
+    fn load(&mut self, reader: impl BufRead, columns: &ColumnMap) -> Result<(), String> {
+        for (number, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let row = split_csv_line(&line);
+            let bounds = (
+                row.get(columns.start).and_then(|value| parse_bound(value)),
+                row.get(columns.end).and_then(|value| parse_bound(value)),
+            );
+            let ((start_v4, start), (end_v4, end)) = match bounds {
+                (Some(start), Some(end)) => (start, end),
+                // most likely a header row
+                _ if number == 0 => continue,
+                _ => return Err(format!("line {}: invalid range", number + 1)),
+            };
+            let (is_v4, start, end) = if start_v4 == end_v4 {
+                (start_v4, start, end)
+            } else {
+                // integer IPv6 files have ranges like 0-281470681743359, which only look like IPv4 at one end
+                let raw = |column: usize| row[column].trim().parse::<u128>().ok();
+                match (raw(columns.start), raw(columns.end)) {
+                    (Some(start), Some(end)) => (false, start, end),
+                    _ => return Err(format!("line {}: range mixes IPv4 and IPv6", number + 1)),
+                }
+            };
+            if start > end {
+                return Err(format!("line {}: invalid range", number + 1));
+            }
+
+            self.records.push(columns.location(&row));
+            let index = if is_v4 { &mut self.v4 } else { &mut self.v6 };
+            index.ranges.push((start, end, self.records.len() - 1));
+        }
+        Ok(())
+    }
+}
+impl GeoProvider for CsvProvider {
+    fn server(&self) -> Option<&str> {
+        None
+    }
+
+    fn locate(&self, ip: IpAddr) -> Result<LocationData, LocateError> {
+        let found = match parse_bound(&ip.to_string()) {
+            Some((true, n)) => self.v4.find(n),
+            Some((false, n)) => self.v6.find(n),
+            None => None,
+        };
+        found.map(|record| self.records[record].clone())
+            .ok_or_else(|| LocateError::NotFound { ip, reason: "not in the offline database".to_string() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod util {
+        use super::*;
+
+        pub fn provider(data: &str, spec: &str) -> CsvProvider {
+            let columns = ColumnMap::parse(spec).unwrap();
+            let mut provider = CsvProvider { records: Vec::new(), v4: RangeIndex::default(), v6: RangeIndex::default() };
+            provider.load(data.as_bytes(), &columns).unwrap();
+            provider.v4.ranges.sort_unstable();
+            provider.v6.ranges.sort_unstable();
+            provider
+        }
+    }
+
+    #[test]
+    fn ip2location_integer_ranges() {
+        let data = "\"0\",\"281470681743359\",\"-\",\"-\",\"-\",\"-\"\n\
+                    \"281470681743360\",\"281470698520575\",\"-\",\"-\",\"-\",\"-\"\n\
+                    \"1572394752\",\"1572395007\",\"GB\",\"United Kingdom of Great Britain and Northern Ireland\",\"England\",\"London\"\n\
+                    \"281470698520576\",\"281470698520831\",\"US\",\"United States of America\",\"California\",\"Los Angeles\"\n";
+        let provider = util::provider(data, "ip2location");
+
+        // 93.184.215.0 - 93.184.215.255
+        let location = provider.locate("93.184.215.14".parse().unwrap()).unwrap();
+        assert_eq!(location.country_code, "GB");
+        assert_eq!(location.city, "London");
+        // ::ffff:1.0.0.0/120 in an IPv6 file is stored as IPv4
+        assert_eq!(provider.locate("1.0.0.9".parse().unwrap()).unwrap().city, "Los Angeles");
+        // `-` means unknown
+        assert_eq!(provider.locate("0.1.2.3".parse().unwrap()).unwrap(), LocationData::default());
+        assert_eq!(provider.locate("::1".parse().unwrap()).unwrap(), LocationData::default());
+        assert!(provider.locate("93.184.216.1".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn dbip_address_ranges() {
+        let data = "start,end,continent,country,region,city\n\
+                    2001:db8::,2001:db8:ffff:ffff:ffff:ffff:ffff:ffff,EU,DE,Hesse,\"Frankfurt, am Main\"\n\
+                    1.1.1.0,1.1.1.255,OC,AU,Queensland,Brisbane\n";
+        let provider = util::provider(data, "dbip");
+
+        let location = provider.locate("2001:db8::1".parse().unwrap()).unwrap();
+        assert_eq!(location.continent, "Europe");
+        assert_eq!(location.city, "Frankfurt, am Main");
+        assert_eq!(provider.locate("1.1.1.1".parse().unwrap()).unwrap().region_name, "Queensland");
+        assert!(provider.locate("2001:db9::1".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn column_map_parsing() {
+        let map = ColumnMap::parse("end=1, start=0, org=7, lat=8").unwrap();
+        assert_eq!(map, ColumnMap { start: 0, end: 1, org: Some(7), lat: Some(8), ..Default::default() });
+        assert!(ColumnMap::parse("start=0").is_err());
+        assert!(ColumnMap::parse("start=0,end=1,altitude=4").is_err());
+        assert!(ColumnMap::parse("start=x,end=1").is_err());
+    }
+
+    #[test]
+    fn split_quoted_fields() {
+        assert_eq!(split_csv_line(r#"a,"b,c","say ""hi""",,"#), vec!["a", "b,c", "say \"hi\"", "", ""]);
+    }
+}
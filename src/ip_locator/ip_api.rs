@@ -0,0 +1,308 @@
+use std::{net::IpAddr, sync::Mutex, time::{Duration, Instant}};
+
+use reqwest::{blocking::Client, header::HeaderMap};
+
+use super::{GeoProvider, LocateError, LocationData};
+
+/// the free tier, which only speaks plain HTTP
+pub const BASE_URL: &str = "http://ip-api.com";
+/// the paid tier, which needs a key
+pub const PRO_BASE_URL: &str = "https://pro.ip-api.com";
+/// status, message, continent, country, countryCode, regionName, city, zip, lat, lon, timezone,
+/// isp, org, as, mobile, proxy, hosting
+const FIELDS: u32 = 18_075_643;
+
+#[derive(serde::Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct Response {
+    status: String,
+    message: String,
+    continent: String,
+    country: String,
+    country_code: String,
+    region_name: String,
+    city: String,
+    zip: String,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    timezone: String,
+    isp: String,
+    org: String,
+    #[serde(rename = "as")]
+    asn: String,
+    mobile: Option<bool>,
+    proxy: Option<bool>,
+    hosting: Option<bool>,
+}
+impl Response {
+    fn into_location(self, ip: IpAddr) -> Result<LocationData, LocateError> {
+        if self.status == "fail" {
+            return Err(LocateError::NotFound { ip, reason: self.message });
+        }
+        Ok(LocationData {
+            continent: self.continent,
+            country: self.country,
+            country_code: self.country_code,
+            region_name: self.region_name,
+            city: self.city,
+            zip: self.zip,
+            lat: self.lat,
+            lon: self.lon,
+            timezone: self.timezone,
+            isp: self.isp,
+            org: self.org,
+            asn: self.asn,
+            mobile: self.mobile,
+            proxy: self.proxy,
+            hosting: self.hosting,
+        })
+    }
+}
+
+/// The free ip-api.com JSON API.
+pub struct IpApi {
+    base_url: String,
+    server: String,
+    client: Client,
+    key: Option<String>,
+    /// when the quota announced in the last response runs out, until when no requests should be made
+    exhausted_until: Mutex<Option<Instant>>,
+}
+impl IpApi {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            server: super::host_of(base_url),
+            client: Client::new(),
+            key: None,
+            exhausted_until: Mutex::new(None),
+        }
+    }
+
+    pub fn with_key(mut self, key: Option<String>) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// uses `client`, e.g. one that sends extra headers, instead of a plain one
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// every response says how many requests are left (`X-Rl`) and when that count resets (`X-Ttl`, in seconds)
+    fn note_quota(&self, headers: &HeaderMap) {
+        let (Some(remaining), Some(ttl)) = (super::header_number(headers, "x-rl"), super::header_number(headers, "x-ttl")) else {
+            return;
+        };
+        let mut exhausted_until = self.exhausted_until.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let now = Instant::now();
+        *exhausted_until = (remaining == 0).then(|| now.checked_add(super::seconds_to_wait(ttl)).unwrap_or(now));
+    }
+}
+impl GeoProvider for IpApi {
+    fn server(&self) -> Option<&str> {
+        Some(&self.server)
+    }
+
+    fn backoff(&self) -> Option<Duration> {
+        let exhausted_until = self.exhausted_until.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+        exhausted_until.map(|until| until.saturating_duration_since(Instant::now())).filter(|wait| !wait.is_zero())
+    }
+
+    fn locate(&self, ip: IpAddr) -> Result<LocationData, LocateError> {
+        let url = format!("{}/json/{ip}?fields={FIELDS}", self.base_url);
+        let mut request = self.client.get(url);
+        if let Some(key) = &self.key {
+            request = request.query(&[("key", key)]);
+        }
+        let content = request.send()
+            .map_err(|e| LocateError::Request { ip, reason: format!("{:?}", e.without_url()) })?;
+        self.note_quota(content.headers());
+        if let Some(retry_in) = super::rate_limited(&content) {
+            return Err(LocateError::RateLimited { ip, retry_in });
+        }
+        let data: Response = serde_json::from_reader(content)
+            .map_err(|_| LocateError::InvalidResponse { ip })?;
+        data.into_location(ip)
+    }
+
+    /// the batch endpoint takes up to 100 addresses per request
+    fn batch_size(&self) -> usize {
+        100
+    }
+
+    fn locate_batch(&self, ips: &[IpAddr]) -> Vec<Result<LocationData, LocateError>> {
+        if let [ip] = ips {
+            return vec![self.locate(*ip)];
+        }
+        let url = format!("{}/batch?fields={FIELDS}", self.base_url);
+        let query: Vec<String> = ips.iter().map(IpAddr::to_string).collect();
+        let body = serde_json::to_string(&query).expect("a list of strings always serializes");
+
+        let mut request = self.client.post(url).header("Content-Type", "application/json").body(body);
+        if let Some(key) = &self.key {
+            request = request.query(&[("key", key)]);
+        }
+        let content = match request.send() {
+            Ok(content) => content,
+            Err(e) => {
+                // the URL carries the API key
+                let reason = format!("{:?}", e.without_url());
+                return ips.iter().map(|&ip| Err(LocateError::Request { ip, reason: reason.clone() })).collect();
+            },
+        };
+        self.note_quota(content.headers());
+        if let Some(retry_in) = super::rate_limited(&content) {
+            return ips.iter().map(|&ip| Err(LocateError::RateLimited { ip, retry_in })).collect();
+        }
+        match serde_json::from_reader::<_, Vec<Response>>(content) {
+            // answers come back in the order the addresses were sent
+            Ok(data) if data.len() == ips.len() => ips.iter().zip(data).map(|(&ip, data)| data.into_location(ip)).collect(),
+            _ => ips.iter().map(|&ip| Err(LocateError::InvalidResponse { ip })).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ip_locator::WebConfig;
+    use crate::rate_limit::RateLimiter;
+    use crate::test_http::{self, Response};
+
+    #[test]
+    fn locate_success() {
+        let body = r#"{"status":"success","continent":"Europe","country":"United Kingdom","countryCode":"GB","regionName":"England","city":"London","zip":"EC2V","lat":51.5074,"lon":-0.127758,"timezone":"Europe/London","isp":"Edgecast Inc.","org":"","as":"AS15133 Edgecast Inc.","mobile":false,"proxy":false,"hosting":true}"#;
+        let (base_url, server) = test_http::serve(vec![Response::json(body)]);
+
+        let location = IpApi::new(&base_url).locate("93.184.215.14".parse().unwrap()).unwrap();
+        assert_eq!(location.continent, "Europe");
+        assert_eq!(location.country_code, "GB");
+        assert_eq!(location.city, "London");
+        assert_eq!(location.isp, "Edgecast Inc.");
+        assert_eq!(location.asn, "AS15133 Edgecast Inc.");
+        assert_eq!((location.lat, location.lon), (Some(51.5074), Some(-0.127758)));
+        assert_eq!(location.timezone, "Europe/London");
+        assert_eq!(location.zip, "EC2V");
+        assert_eq!(location.hosting, Some(true));
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].request_line, format!("GET /json/93.184.215.14?fields={FIELDS} HTTP/1.1"));
+    }
+
+    #[test]
+    fn locate_failure_message() {
+        let body = r#"{"status":"fail","message":"private range"}"#;
+        let (base_url, server) = test_http::serve(vec![Response::json(body)]);
+
+        let res = IpApi::new(&base_url).locate("10.0.0.1".parse().unwrap());
+        assert_eq!(res, Err(LocateError::NotFound { ip: "10.0.0.1".parse().unwrap(), reason: "private range".to_string() }));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn locate_with_key_and_headers() {
+        let (base_url, server) = test_http::serve(vec![Response::json(r#"{"status":"success","city":"Ashburn"}"#)]);
+        let web = WebConfig { headers: vec![("X-Client".to_string(), "inloc".to_string())], ..Default::default() };
+        let provider = IpApi::new(&base_url).with_key(Some("s3cret".to_string())).with_client(web.client().unwrap());
+
+        assert_eq!(provider.locate("8.8.8.8".parse().unwrap()).unwrap().city, "Ashburn");
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].request_line, format!("GET /json/8.8.8.8?fields={FIELDS}&key=s3cret HTTP/1.1"));
+        assert_eq!(requests[0].header("x-client"), Some("inloc"));
+    }
+
+    #[test]
+    fn quota_headers() {
+        let body = r#"{"status":"success","countryCode":"US"}"#;
+        let exhausted = Response::json(body).with_header("X-Rl", "0").with_header("X-Ttl", "42");
+        let limited = Response { status: 429, ..Response::json("") }.with_header("X-Rl", "0").with_header("X-Ttl", "41");
+        let (base_url, server) = test_http::serve(vec![Response::json(body).with_header("X-Rl", "44").with_header("X-Ttl", "60"), exhausted, limited]);
+        let provider = IpApi::new(&base_url);
+        let ip = "8.8.8.8".parse().unwrap();
+
+        provider.locate(ip).unwrap();
+        assert_eq!(provider.backoff(), None);
+        // the last request of the window still succeeds, but the next ones have to wait
+        provider.locate(ip).unwrap();
+        let backoff = provider.backoff().unwrap();
+        assert!(backoff > Duration::from_secs(40) && backoff <= Duration::from_secs(42));
+
+        let res = provider.locate(ip);
+        assert_eq!(res, Err(LocateError::RateLimited { ip, retry_in: Duration::from_secs(41) }));
+        assert_eq!(res.unwrap_err().to_string(), "rate limited while locating 8.8.8.8, retry in 41 s");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn huge_quota_headers() {
+        let huge = u64::MAX.to_string();
+        let exhausted = Response::json(r#"{"status":"success"}"#).with_header("X-Rl", "0").with_header("X-Ttl", &huge);
+        let limited = Response { status: 429, ..Response::json("") }.with_header("Retry-After", &huge);
+        let (base_url, server) = test_http::serve(vec![exhausted, limited]);
+        let provider = IpApi::new(&base_url);
+        let ip = "8.8.8.8".parse().unwrap();
+
+        provider.locate(ip).unwrap();
+        assert!(provider.backoff().unwrap() <= crate::rate_limit::MAX_PAUSE);
+        assert_eq!(provider.locate(ip), Err(LocateError::RateLimited { ip, retry_in: crate::rate_limit::MAX_PAUSE }));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn lookup_all_retries_when_rate_limited() {
+        let limited = Response { status: 429, ..Response::json("") }.with_header("X-Rl", "0").with_header("X-Ttl", "0");
+        let (base_url, server) = test_http::serve(vec![limited, Response::json(r#"{"status":"success","city":"Ashburn"}"#)]);
+
+        let ip = "8.8.8.8".parse().unwrap();
+        let results = super::super::lookup_all(&IpApi::new(&base_url), &[ip], &RateLimiter::new(None, 1), None, 1);
+        assert_eq!(results[0].as_ref().unwrap().city, "Ashburn");
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn locate_batch_in_order() {
+        let body = r#"[{"status":"success","country":"United States","countryCode":"US","city":"Ashburn"},{"status":"fail","message":"reserved range"},{"status":"success","countryCode":"AU","city":"Brisbane"}]"#;
+        let (base_url, server) = test_http::serve(vec![Response::json(body)]);
+
+        let ips: Vec<IpAddr> = ["8.8.8.8", "0.0.0.1", "1.1.1.1"].iter().map(|ip| ip.parse().unwrap()).collect();
+        let results = IpApi::new(&base_url).locate_batch(&ips);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().city, "Ashburn");
+        assert_eq!(results[1], Err(LocateError::NotFound { ip: ips[1], reason: "reserved range".to_string() }));
+        assert_eq!(results[2].as_ref().unwrap().country_code, "AU");
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].request_line, format!("POST /batch?fields={FIELDS} HTTP/1.1"));
+        assert_eq!(requests[0].body, r#"["8.8.8.8","0.0.0.1","1.1.1.1"]"#);
+    }
+
+    #[test]
+    fn locate_batch_length_mismatch() {
+        let (base_url, server) = test_http::serve(vec![Response::json(r#"[{"status":"success"}]"#)]);
+
+        let ips: Vec<IpAddr> = ["8.8.8.8", "1.1.1.1"].iter().map(|ip| ip.parse().unwrap()).collect();
+        let results = IpApi::new(&base_url).locate_batch(&ips);
+        assert_eq!(results, vec![Err(LocateError::InvalidResponse { ip: ips[0] }), Err(LocateError::InvalidResponse { ip: ips[1] })]);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn failed_request_hides_key() {
+        let provider = IpApi::new(&test_http::unreachable()).with_key(Some("s3cret".to_string()));
+        let ip = "8.8.8.8".parse().unwrap();
+        let Err(LocateError::Request { reason, .. }) = provider.locate(ip) else {
+            panic!("expected the request to fail");
+        };
+        assert!(!reason.contains("s3cret"), "{reason}");
+
+        let results = provider.locate_batch(&[ip, "1.1.1.1".parse().unwrap()]);
+        for result in results {
+            let Err(e @ LocateError::Request { .. }) = result else {
+                panic!("expected the request to fail");
+            };
+            assert!(!e.to_string().contains("s3cret"), "{e}");
+        }
+    }
+}
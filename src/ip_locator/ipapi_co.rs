@@ -0,0 +1,135 @@
+use std::net::IpAddr;
+
+use reqwest::blocking::Client;
+
+use super::{GeoProvider, LocateError, LocationData};
+
+pub const BASE_URL: &str = "https://ipapi.co";
+
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+struct Response {
+    error: bool,
+    reason: String,
+    city: String,
+    region: String,
+    /// two letter country code
+    country: String,
+    country_name: String,
+    continent_code: String,
+    postal: String,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    timezone: String,
+    /// e.g. `AS15169`
+    asn: String,
+    org: String,
+}
+
+/// ipapi.co, which answers with continent codes rather than names.
+pub struct IpApiCo {
+    base_url: String,
+    server: String,
+    client: Client,
+    key: Option<String>,
+}
+impl IpApiCo {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            server: super::host_of(base_url),
+            client: Client::new(),
+            key: None,
+        }
+    }
+
+    pub fn with_key(mut self, key: Option<String>) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// uses `client`, e.g. one that sends extra headers, instead of a plain one
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+}
+impl GeoProvider for IpApiCo {
+    fn server(&self) -> Option<&str> {
+        Some(&self.server)
+    }
+
+    fn locate(&self, ip: IpAddr) -> Result<LocationData, LocateError> {
+        let url = format!("{}/{ip}/json/", self.base_url);
+        let mut request = self.client.get(url);
+        if let Some(key) = &self.key {
+            request = request.query(&[("key", key)]);
+        }
+        let content = request.send()
+            .map_err(|e| LocateError::Request { ip, reason: format!("{:?}", e.without_url()) })?;
+        if let Some(retry_in) = super::rate_limited(&content) {
+            return Err(LocateError::RateLimited { ip, retry_in });
+        }
+        let data: Response = serde_json::from_reader(content)
+            .map_err(|_| LocateError::InvalidResponse { ip })?;
+        if data.error {
+            return Err(LocateError::NotFound { ip, reason: data.reason });
+        }
+        Ok(LocationData {
+            continent: super::continent_name(&data.continent_code).to_string(),
+            country: data.country_name,
+            country_code: data.country,
+            region_name: data.region,
+            city: data.city,
+            zip: data.postal,
+            lat: data.latitude,
+            lon: data.longitude,
+            timezone: data.timezone,
+            asn: data.asn,
+            org: data.org,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_http::{self, Response};
+
+    #[test]
+    fn locate_success() {
+        let body = r#"{"ip":"8.8.8.8","city":"Mountain View","region":"California","country":"US","country_name":"United States","continent_code":"NA","postal":"94043","latitude":37.42301,"longitude":-122.083352,"timezone":"America/Los_Angeles","asn":"AS15169","org":"GOOGLE"}"#;
+        let (base_url, server) = test_http::serve(vec![Response::json(body)]);
+
+        let location = IpApiCo::new(&base_url).locate("8.8.8.8".parse().unwrap()).unwrap();
+        assert_eq!(location.continent, "North America");
+        assert_eq!(location.country, "United States");
+        assert_eq!(location.country_code, "US");
+        assert_eq!(location.org, "GOOGLE");
+        assert_eq!(location.asn, "AS15169");
+        assert_eq!(location.zip, "94043");
+        assert_eq!(location.lat, Some(37.42301));
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].request_line, "GET /8.8.8.8/json/ HTTP/1.1");
+    }
+
+    #[test]
+    fn locate_error_reason() {
+        let body = r#"{"ip":"127.0.0.1","error":true,"reason":"Reserved IP Address","reserved":true}"#;
+        let (base_url, server) = test_http::serve(vec![Response::json(body)]);
+        let res = IpApiCo::new(&base_url).locate("127.0.0.1".parse().unwrap());
+        assert_eq!(res, Err(LocateError::NotFound { ip: "127.0.0.1".parse().unwrap(), reason: "Reserved IP Address".to_string() }));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn failed_request_hides_key() {
+        let provider = IpApiCo::new(&test_http::unreachable()).with_key(Some("s3cret".to_string()));
+        let Err(e @ LocateError::Request { .. }) = provider.locate("8.8.8.8".parse().unwrap()) else {
+            panic!("expected the request to fail");
+        };
+        assert!(!e.to_string().contains("s3cret"), "{e}");
+    }
+}
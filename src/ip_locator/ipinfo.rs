@@ -0,0 +1,145 @@
+use std::net::IpAddr;
+
+use reqwest::blocking::Client;
+
+use super::{GeoProvider, LocateError, LocationData};
+
+pub const BASE_URL: &str = "https://ipinfo.io";
+
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+struct Response {
+    bogon: bool,
+    city: String,
+    region: String,
+    /// two letter country code
+    country: String,
+    /// `latitude,longitude`
+    loc: String,
+    postal: String,
+    timezone: String,
+    /// e.g. `AS15169 Google LLC`
+    org: String,
+}
+
+/// ipinfo.io. Its free tier only has country codes, and the AS owner stands in for the organization.
+pub struct IpInfo {
+    base_url: String,
+    server: String,
+    client: Client,
+    key: Option<String>,
+}
+impl IpInfo {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            server: super::host_of(base_url),
+            client: Client::new(),
+            key: None,
+        }
+    }
+
+    pub fn with_key(mut self, key: Option<String>) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// uses `client`, e.g. one that sends extra headers, instead of a plain one
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+}
+impl GeoProvider for IpInfo {
+    fn server(&self) -> Option<&str> {
+        Some(&self.server)
+    }
+
+    fn locate(&self, ip: IpAddr) -> Result<LocationData, LocateError> {
+        let url = format!("{}/{ip}/json", self.base_url);
+        let mut request = self.client.get(url);
+        if let Some(token) = &self.key {
+            request = request.bearer_auth(token);
+        }
+        let content = request.send()
+            .map_err(|e| LocateError::Request { ip, reason: format!("{:?}", e.without_url()) })?;
+        if let Some(retry_in) = super::rate_limited(&content) {
+            return Err(LocateError::RateLimited { ip, retry_in });
+        }
+        if !content.status().is_success() {
+            return Err(LocateError::NotFound { ip, reason: format!("HTTP {}", content.status()) });
+        }
+        let data: Response = serde_json::from_reader(content)
+            .map_err(|_| LocateError::InvalidResponse { ip })?;
+        if data.bogon {
+            return Err(LocateError::NotFound { ip, reason: "bogon address".to_string() });
+        }
+        let coordinate = |i: usize| data.loc.split(',').nth(i).and_then(|value| value.trim().parse().ok());
+        // `org` is the AS number followed by its owner's name
+        let (asn, org) = match data.org.split_once(' ') {
+            Some((asn, org)) if asn.starts_with("AS") => (asn.to_string(), org.to_string()),
+            _ => (String::new(), data.org),
+        };
+        Ok(LocationData {
+            country_code: data.country,
+            region_name: data.region,
+            city: data.city,
+            zip: data.postal,
+            lat: coordinate(0),
+            lon: coordinate(1),
+            timezone: data.timezone,
+            asn,
+            org,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_http::{self, Response};
+
+    #[test]
+    fn locate_success() {
+        let body = r#"{"ip":"8.8.8.8","city":"Mountain View","region":"California","country":"US","loc":"37.4056,-122.0775","org":"AS15169 Google LLC"}"#;
+        let (base_url, server) = test_http::serve(vec![Response::json(body)]);
+
+        let location = IpInfo::new(&base_url).locate("8.8.8.8".parse().unwrap()).unwrap();
+        assert_eq!(location.country_code, "US");
+        assert_eq!(location.region_name, "California");
+        assert_eq!(location.city, "Mountain View");
+        assert_eq!(location.org, "Google LLC");
+        assert_eq!(location.asn, "AS15169");
+        assert_eq!(location.lat, Some(37.4056));
+        assert_eq!(location.lon, Some(-122.0775));
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].request_line, "GET /8.8.8.8/json HTTP/1.1");
+    }
+
+    #[test]
+    fn locate_with_token() {
+        let (base_url, server) = test_http::serve(vec![Response::json(r#"{"ip":"8.8.8.8","country":"US"}"#)]);
+        let provider = IpInfo::new(&base_url).with_key(Some("t0ken".to_string()));
+        assert_eq!(provider.locate("8.8.8.8".parse().unwrap()).unwrap().country_code, "US");
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].header("authorization"), Some("Bearer t0ken"));
+    }
+
+    #[test]
+    fn locate_bogon() {
+        let (base_url, server) = test_http::serve(vec![Response::json(r#"{"ip":"10.0.0.1","bogon":true}"#)]);
+        assert!(IpInfo::new(&base_url).locate("10.0.0.1".parse().unwrap()).is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn locate_rate_limited() {
+        let response = Response { status: 429, ..Response::json(r#"{"error":{"title":"Rate limit exceeded"}}"#) }.with_header("Retry-After", "30");
+        let (base_url, server) = test_http::serve(vec![response]);
+        let res = IpInfo::new(&base_url).locate("8.8.8.8".parse().unwrap());
+        assert_eq!(res, Err(LocateError::RateLimited { ip: "8.8.8.8".parse().unwrap(), retry_in: std::time::Duration::from_secs(30) }));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,539 @@
+//! A reader for MaxMind DB (`.mmdb`) files such as GeoLite2/GeoIP2 City and ASN,
+//! following the MaxMind DB file format specification 2.0.
+
+use std::{collections::BTreeMap, net::IpAddr, path::Path};
+
+use super::{GeoProvider, LocateError, LocationData};
+
+/// the metadata section starts right after the last occurrence of this marker
+const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+/// the search tree and the data section are separated by this many zero bytes
+const DATA_SECTION_SEPARATOR: usize = 16;
+
+/// A decoded value from the data section.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Double(f64),
+    Bytes(Vec<u8>),
+    Uint(u128),
+    Int(i32),
+    Map(BTreeMap<String, Value>),
+    Array(Vec<Value>),
+    Bool(bool),
+    Float(f32),
+}
+impl Value {
+    /// follows `path` through maps (by key) and arrays (by index)
+    pub fn get(&self, path: &[&str]) -> Option<&Self> {
+        let Some((first, rest)) = path.split_first() else {
+            return Some(self);
+        };
+        let next = match self {
+            Self::Map(map) => map.get(*first)?,
+            Self::Array(items) => items.get(first.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+        next.get(rest)
+    }
+
+    pub fn get_str(&self, path: &[&str]) -> Option<&str> {
+        match self.get(path)? {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_uint(&self) -> Option<u128> {
+        match self {
+            Self::Uint(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Double(n) => Some(*n),
+            Self::Float(n) => Some(f64::from(*n)),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes values from a section of the file. Pointers are relative to the start of `data`.
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+impl Decoder<'_> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&[u8], String> {
+        self.data.get(offset..offset + len).ok_or_else(|| format!("data section truncated at offset {offset}"))
+    }
+
+    fn uint(&self, offset: usize, len: usize) -> Result<u128, String> {
+        if len > 16 {
+            return Err(format!("integer of {len} bytes is too large"));
+        }
+        Ok(self.bytes(offset, len)?.iter().fold(0, |acc, byte| (acc << 8) | u128::from(*byte)))
+    }
+
+    /// decodes the value at `offset`, returning it along with the offset just past it
+    fn decode(&self, offset: usize) -> Result<(Value, usize), String> {
+        let control = *self.bytes(offset, 1)?.first().unwrap_or(&0);
+        let mut offset = offset + 1;
+
+        let mut kind = control >> 5;
+        if kind == 1 {
+            // pointers have their own size encoding, and the value they point to is used in their place
+            let size_bits = (control >> 3) & 0b11;
+            let value_bits = usize::from(control & 0b111);
+            let (pointer, len) = match size_bits {
+                0 => ((value_bits << 8) | self.uint(offset, 1)? as usize, 1),
+                1 => (((value_bits << 16) | self.uint(offset, 2)? as usize) + 2048, 2),
+                2 => (((value_bits << 24) | self.uint(offset, 3)? as usize) + 526_336, 3),
+                _ => (self.uint(offset, 4)? as usize, 4),
+            };
+            // the spec forbids pointers to pointers, and following them could recurse forever
+            if self.bytes(pointer, 1)?[0] >> 5 == 1 {
+                return Err(format!("pointer at offset {} points to another pointer", offset - 1));
+            }
+            let (value, _) = self.decode(pointer)?;
+            return Ok((value, offset + len));
+        }
+        if kind == 0 {
+            // extended type, stored in the next byte
+            kind = 7 + self.bytes(offset, 1)?[0];
+            offset += 1;
+        }
+
+        let size = usize::from(control & 0b1_1111);
+        let size = match size {
+            29 => 29 + self.uint(offset, 1)? as usize,
+            30 => 285 + self.uint(offset, 2)? as usize,
+            31 => 65_821 + self.uint(offset, 3)? as usize,
+            _ => size,
+        };
+        offset += match control & 0b1_1111 {
+            29 => 1,
+            30 => 2,
+            31 => 3,
+            _ => 0,
+        };
+
+        let value = match kind {
+            2 => Value::String(String::from_utf8_lossy(self.bytes(offset, size)?).to_string()),
+            3 => {
+                let bytes: [u8; 8] = self.bytes(offset, 8)?.try_into().map_err(|_| "bad double")?;
+                return Ok((Value::Double(f64::from_be_bytes(bytes)), offset + 8));
+            },
+            4 => Value::Bytes(self.bytes(offset, size)?.to_vec()),
+            5 | 6 | 9 | 10 => Value::Uint(self.uint(offset, size)?),
+            7 => {
+                let mut map = BTreeMap::new();
+                for _ in 0..size {
+                    let (key, next) = self.decode(offset)?;
+                    let Value::String(key) = key else {
+                        return Err(format!("map key at offset {offset} is not a string"));
+                    };
+                    let (value, next) = self.decode(next)?;
+                    map.insert(key, value);
+                    offset = next;
+                }
+                return Ok((Value::Map(map), offset));
+            },
+            8 => {
+                let n = self.uint(offset, size)? as u32;
+                // int32s are sign extended from however many bytes were stored
+                let n = if size > 0 && size < 4 {
+                    let shift = 32 - 8 * size as u32;
+                    ((n << shift) as i32) >> shift
+                } else {
+                    n as i32
+                };
+                Value::Int(n)
+            },
+            11 => {
+                let mut items = Vec::with_capacity(size);
+                for _ in 0..size {
+                    let (value, next) = self.decode(offset)?;
+                    items.push(value);
+                    offset = next;
+                }
+                return Ok((Value::Array(items), offset));
+            },
+            14 => return Ok((Value::Bool(size != 0), offset)),
+            15 => {
+                let bytes: [u8; 4] = self.bytes(offset, 4)?.try_into().map_err(|_| "bad float")?;
+                return Ok((Value::Float(f32::from_be_bytes(bytes)), offset + 4));
+            },
+            _ => return Err(format!("unsupported data type {kind} at offset {offset}")),
+        };
+        Ok((value, offset + size))
+    }
+}
+
+/// An opened `.mmdb` file, held in memory.
+pub struct MmdbReader {
+    buf: Vec<u8>,
+    node_count: u32,
+    record_size: u16,
+    ip_version: u16,
+}
+impl MmdbReader {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let buf = std::fs::read(path).map_err(|e| format!("can't read {}: {e}", path.display()))?;
+        Self::from_bytes(buf).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Self, String> {
+        let marker = buf.windows(METADATA_MARKER.len())
+            .rposition(|window| window == METADATA_MARKER)
+            .ok_or("not a MaxMind DB file (no metadata marker)")?;
+        let metadata_start = marker + METADATA_MARKER.len();
+        let (metadata, _) = Decoder { data: &buf[metadata_start..] }.decode(0)?;
+
+        let field = |name: &str| metadata.get(&[name]).and_then(Value::as_uint).ok_or(format!("metadata is missing {name}"));
+        let node_count = u32::try_from(field("node_count")?).map_err(|_| "node count too large")?;
+        let record_size = u16::try_from(field("record_size")?).map_err(|_| "record size too large")?;
+        let ip_version = u16::try_from(field("ip_version")?).map_err(|_| "bad ip version")?;
+        if ![24, 28, 32].contains(&record_size) {
+            return Err(format!("unsupported record size {record_size}"));
+        }
+
+        let reader = Self {
+            buf,
+            node_count,
+            record_size,
+            ip_version,
+        };
+        if reader.data_start() > metadata_start {
+            return Err("search tree runs past the end of the file".to_string());
+        }
+        Ok(reader)
+    }
+
+    fn tree_size(&self) -> usize {
+        usize::from(self.record_size) * 2 / 8 * self.node_count as usize
+    }
+
+    fn data_start(&self) -> usize {
+        self.tree_size() + DATA_SECTION_SEPARATOR
+    }
+
+    /// reads the left (`bit == 0`) or right record of a node
+    fn record(&self, node: u32, bit: u8) -> Result<u32, String> {
+        let node_bytes = usize::from(self.record_size) / 4;
+        let start = node as usize * node_bytes;
+        let b = self.buf.get(start..start + node_bytes).ok_or("search tree truncated")?;
+        let read = |bytes: &[u8]| bytes.iter().fold(0_u32, |acc, byte| (acc << 8) | u32::from(*byte));
+        Ok(match (self.record_size, bit) {
+            (24, 0) => read(&b[0..3]),
+            (24, _) => read(&b[3..6]),
+            // the middle byte holds the high nibble of both records
+            (28, 0) => (u32::from(b[3] & 0xF0) << 20) | read(&b[0..3]),
+            (28, _) => (u32::from(b[3] & 0x0F) << 24) | read(&b[4..7]),
+            (_, 0) => read(&b[0..4]),
+            (_, _) => read(&b[4..8]),
+        })
+    }
+
+    /// finds the data record for `ip`, if the database has one
+    pub fn lookup(&self, ip: IpAddr) -> Result<Option<Value>, String> {
+        let (bits, len): (u128, u32) = match (ip, self.ip_version) {
+            (IpAddr::V4(v4), 4) => (u128::from(u32::from(v4)), 32),
+            // IPv4 addresses live in the ::/96 subtree of an IPv6 database
+            (IpAddr::V4(v4), _) => (u128::from(u32::from(v4)), 128),
+            (IpAddr::V6(v6), 6) => (u128::from(v6), 128),
+            (IpAddr::V6(_), _) => return Err("an IPv4-only database can't locate IPv6 addresses".to_string()),
+        };
+
+        let mut node = 0;
+        for i in (0..len).rev() {
+            if node >= self.node_count {
+                break;
+            }
+            node = self.record(node, ((bits >> i) & 1) as u8)?;
+        }
+
+        if node == self.node_count {
+            return Ok(None);
+        }
+        if node < self.node_count {
+            return Err("search tree ended on a node".to_string());
+        }
+        let offset = ((node - self.node_count) as usize).checked_sub(DATA_SECTION_SEPARATOR)
+            .ok_or("search tree points into the data section separator")?;
+        let decoder = Decoder { data: &self.buf[self.data_start()..] };
+        let (value, _) = decoder.decode(offset)?;
+        Ok(Some(value))
+    }
+}
+
+/// Looks addresses up in local City/ASN/ISP databases; fields are taken from the first database that has them.
+pub struct MmdbProvider {
+    readers: Vec<MmdbReader>,
+}
+impl MmdbProvider {
+    pub fn open(paths: &[impl AsRef<Path>]) -> Result<Self, String> {
+        let readers = paths.iter().map(|path| MmdbReader::open(path.as_ref())).collect::<Result<_, _>>()?;
+        Ok(Self { readers })
+    }
+}
+impl GeoProvider for MmdbProvider {
+    fn server(&self) -> Option<&str> {
+        None
+    }
+
+    fn locate(&self, ip: IpAddr) -> Result<LocationData, LocateError> {
+        let mut location = LocationData::default();
+        let mut found = false;
+        for reader in &self.readers {
+            let record = reader.lookup(ip).map_err(|reason| LocateError::Database { ip, reason })?;
+            let Some(record) = record else {
+                continue;
+            };
+            found = true;
+            let fill = |field: &mut String, paths: &[&[&str]]| {
+                if field.is_empty() {
+                    if let Some(value) = paths.iter().find_map(|path| record.get_str(path)) {
+                        *field = value.to_string();
+                    }
+                }
+            };
+            fill(&mut location.continent, &[&["continent", "names", "en"]]);
+            fill(&mut location.country, &[&["country", "names", "en"]]);
+            fill(&mut location.country_code, &[&["country", "iso_code"]]);
+            fill(&mut location.region_name, &[&["subdivisions", "0", "names", "en"]]);
+            fill(&mut location.city, &[&["city", "names", "en"]]);
+            fill(&mut location.zip, &[&["postal", "code"]]);
+            fill(&mut location.timezone, &[&["location", "time_zone"]]);
+            fill(&mut location.org, &[&["autonomous_system_organization"], &["organization"]]);
+            fill(&mut location.isp, &[&["isp"]]);
+
+            location.lat = location.lat.or_else(|| record.get(&["location", "latitude"]).and_then(Value::as_f64));
+            location.lon = location.lon.or_else(|| record.get(&["location", "longitude"]).and_then(Value::as_f64));
+            if location.asn.is_empty() {
+                if let Some(number) = record.get(&["autonomous_system_number"]).and_then(Value::as_uint) {
+                    location.asn = format!("AS{number}");
+                }
+            }
+        }
+        if !found {
+            return Err(LocateError::NotFound { ip, reason: "not in the offline database".to_string() });
+        }
+        Ok(location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// builds small databases in memory, writing just enough of the format for the reader
+    mod util {
+        use super::*;
+
+        pub fn encode(value: &Value) -> Vec<u8> {
+            fn control(kind: u8, size: usize) -> Vec<u8> {
+                let (bits, extra) = match size {
+                    0..=28 => (size as u8, vec![]),
+                    29..=284 => (29, vec![(size - 29) as u8]),
+                    _ => (30, ((size - 285) as u16).to_be_bytes().to_vec()),
+                };
+                let mut out = if kind > 7 {
+                    vec![bits, kind - 7]
+                } else {
+                    vec![(kind << 5) | bits]
+                };
+                out.extend(extra);
+                out
+            }
+            match value {
+                Value::String(s) => [control(2, s.len()), s.as_bytes().to_vec()].concat(),
+                Value::Uint(n) => {
+                    let bytes: Vec<u8> = n.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+                    [control(6, bytes.len()), bytes].concat()
+                },
+                Value::Map(map) => {
+                    let mut out = control(7, map.len());
+                    for (key, value) in map {
+                        out.extend(encode(&Value::String(key.clone())));
+                        out.extend(encode(value));
+                    }
+                    out
+                },
+                Value::Array(items) => {
+                    let mut out = control(11, items.len());
+                    for item in items {
+                        out.extend(encode(item));
+                    }
+                    out
+                },
+                Value::Bool(b) => control(14, usize::from(*b)),
+                other => panic!("the test encoder does not support {other:?}"),
+            }
+        }
+
+        pub fn map(entries: &[(&str, Value)]) -> Value {
+            Value::Map(entries.iter().map(|(k, v)| (k.to_string(), v.clone())).collect())
+        }
+
+        pub fn text(s: &str) -> Value {
+            Value::String(s.to_string())
+        }
+
+        /// a 24 bit record size database mapping each network to a record
+        pub fn database(ip_version: u16, networks: &[(u128, u32, Value)]) -> Vec<u8> {
+            let bits = if ip_version == 4 { 32 } else { 128 };
+            // nodes[i] = [left, right]; None means empty
+            let mut nodes: Vec<[Option<u32>; 2]> = vec![[None, None]];
+            let mut leaves: Vec<(usize, usize, usize)> = Vec::new(); // (node, side, record index)
+            for (index, (network, prefix, _)) in networks.iter().enumerate() {
+                let mut node = 0;
+                for i in 0..*prefix {
+                    let bit = ((network >> (bits - 1 - i)) & 1) as usize;
+                    if i + 1 == *prefix {
+                        leaves.push((node, bit, index));
+                    } else {
+                        node = match nodes[node][bit] {
+                            Some(next) => next as usize,
+                            None => {
+                                nodes.push([None, None]);
+                                let next = nodes.len() - 1;
+                                nodes[node][bit] = Some(next as u32);
+                                next
+                            },
+                        };
+                    }
+                }
+            }
+
+            let node_count = nodes.len() as u32;
+            let mut data = Vec::new();
+            let mut offsets = Vec::new();
+            for (_, _, value) in networks {
+                offsets.push(data.len() as u32);
+                data.extend(encode(value));
+            }
+
+            let mut records: Vec<[u32; 2]> = nodes.iter()
+                .map(|[l, r]| [l.unwrap_or(node_count), r.unwrap_or(node_count)])
+                .collect();
+            for (node, side, index) in leaves {
+                records[node][side] = node_count + 16 + offsets[index];
+            }
+
+            let mut buf = Vec::new();
+            for [l, r] in records {
+                buf.extend(&l.to_be_bytes()[1..]);
+                buf.extend(&r.to_be_bytes()[1..]);
+            }
+            buf.extend([0_u8; 16]);
+            buf.extend(data);
+            buf.extend(METADATA_MARKER);
+            buf.extend(encode(&map(&[
+                ("node_count", Value::Uint(u128::from(node_count))),
+                ("record_size", Value::Uint(24)),
+                ("ip_version", Value::Uint(u128::from(ip_version))),
+                ("database_type", text("Test-City")),
+            ])));
+            buf
+        }
+
+        pub fn city(continent: &str, country: &str, iso: &str, region: &str, city: &str) -> Value {
+            let names = |name: &str| map(&[("names", map(&[("en", text(name))]))]);
+            map(&[
+                ("continent", names(continent)),
+                ("country", map(&[("iso_code", text(iso)), ("names", map(&[("en", text(country))]))])),
+                ("subdivisions", Value::Array(vec![names(region)])),
+                ("city", names(city)),
+            ])
+        }
+    }
+
+    #[test]
+    fn lookup_ipv4_database() {
+        let db = util::database(4, &[
+            (u128::from(0x5DB8_D700_u32), 24, util::city("Europe", "United Kingdom", "GB", "England", "London")),
+        ]);
+        let reader = MmdbReader::from_bytes(db).unwrap();
+
+        let record = reader.lookup("93.184.215.14".parse().unwrap()).unwrap().unwrap();
+        assert_eq!(record.get_str(&["city", "names", "en"]), Some("London"));
+        assert_eq!(record.get_str(&["subdivisions", "0", "names", "en"]), Some("England"));
+
+        assert_eq!(reader.lookup("93.184.216.1".parse().unwrap()), Ok(None));
+        assert!(reader.lookup("2001:db8::1".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn lookup_ipv6_database_with_ipv4_address() {
+        let v4_mapped = u128::from(0x0101_0100_u32); // 1.1.1.0 under ::/96
+        let v6 = u128::from("2001:db8::".parse::<std::net::Ipv6Addr>().unwrap());
+        let db = util::database(6, &[
+            (v4_mapped, 120, util::map(&[("autonomous_system_organization", util::text("CLOUDFLARENET"))])),
+            (v6, 32, util::map(&[("isp", util::text("Documentation"))])),
+        ]);
+        let provider = MmdbProvider { readers: vec![MmdbReader::from_bytes(db).unwrap()] };
+
+        let location = provider.locate("1.1.1.1".parse().unwrap()).unwrap();
+        assert_eq!(location.org, "CLOUDFLARENET");
+        let location = provider.locate("2001:db8:1::5".parse().unwrap()).unwrap();
+        assert_eq!(location.isp, "Documentation");
+        assert!(provider.locate("8.8.8.8".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn provider_merges_databases() {
+        let network = u128::from(0x5DB8_D700_u32);
+        let city = util::database(4, &[(network, 24, util::city("Europe", "United Kingdom", "GB", "England", "London"))]);
+        let asn = util::database(4, &[(network, 16, util::map(&[
+            ("autonomous_system_number", Value::Uint(15133)),
+            ("autonomous_system_organization", util::text("EDGECAST")),
+        ]))]);
+        let provider = MmdbProvider {
+            readers: vec![MmdbReader::from_bytes(city).unwrap(), MmdbReader::from_bytes(asn).unwrap()],
+        };
+        let location = provider.locate("93.184.215.14".parse().unwrap()).unwrap();
+        assert_eq!(location, LocationData {
+            continent: "Europe".to_string(),
+            country: "United Kingdom".to_string(),
+            country_code: "GB".to_string(),
+            region_name: "England".to_string(),
+            city: "London".to_string(),
+            org: "EDGECAST".to_string(),
+            asn: "AS15133".to_string(),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn decode_pointer_and_28_bit_records() {
+        // a string, then a pointer back to it
+        let data = [0x43, b'a', b'b', b'c', 0x20, 0x00];
+        let decoder = Decoder { data: &data };
+        assert_eq!(decoder.decode(4), Ok((Value::String("abc".to_string()), 6)));
+
+        // pointers to pointers are rejected rather than followed
+        let data = [0x20, 0x00];
+        assert!(Decoder { data: &data }.decode(0).is_err());
+        let data = [0x43, b'a', b'b', b'c', 0x20, 0x00, 0x20, 0x04];
+        assert!(Decoder { data: &data }.decode(6).is_err());
+
+        let mut buf = vec![0x12, 0x34, 0x56, 0xAB, 0x65, 0x43, 0x21];
+        buf.extend(METADATA_MARKER);
+        buf.extend(util::encode(&util::map(&[
+            ("node_count", Value::Uint(1)),
+            ("record_size", Value::Uint(28)),
+            ("ip_version", Value::Uint(4)),
+        ])));
+        // the search tree would overlap the metadata, so check records without the size check
+        let reader = MmdbReader { buf, node_count: 1, record_size: 28, ip_version: 4 };
+        assert_eq!(reader.record(0, 0), Ok(0x0A12_3456));
+        assert_eq!(reader.record(0, 1), Ok(0x0B65_4321));
+    }
+
+    #[test]
+    fn reject_non_mmdb() {
+        assert!(MmdbReader::from_bytes(b"hello world".to_vec()).is_err());
+    }
+}
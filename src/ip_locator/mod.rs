@@ -0,0 +1,338 @@
+use std::{net::IpAddr, path::PathBuf, time::Duration};
+
+use colored::Colorize;
+use reqwest::{blocking::Client, header::{HeaderMap, HeaderName, HeaderValue}};
+
+use crate::{bulk, rate_limit::RateLimiter};
+
+use cache::GeoCache;
+
+pub mod cache;
+pub mod csv_db;
+pub mod ip_api;
+pub mod ipapi_co;
+pub mod ipinfo;
+pub mod mmdb;
+pub mod special;
+
+/// Where an address is, as far as a geolocation provider can tell.
+/// Providers leave fields they don't know about empty.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LocationData {
+    pub continent: String,
+    pub country: String,
+    pub country_code: String,
+    pub region_name: String,
+    pub city: String,
+    pub zip: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lon: Option<f64>,
+    pub timezone: String,
+    pub isp: String,
+    pub org: String,
+    /// e.g. `AS15133 Edgecast Inc.`
+    pub asn: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mobile: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hosting: Option<bool>,
+}
+impl std::fmt::Display for LocationData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut result = Vec::new();
+
+        if !self.continent.is_empty() {
+            result.push(format!("continent: {}", self.continent.bold()));
+        }
+
+        if !self.country.is_empty() || !self.country_code.is_empty() {
+            result.push(format!("country: {} ({})", self.country_code.bold(), self.country.bold()));
+        }
+
+        if !self.region_name.is_empty() {
+            result.push(format!("region/state: {}", self.region_name.bold()));
+        }
+
+        if !self.city.is_empty() {
+            result.push(format!("city: {}", self.city.bold()));
+        }
+
+        if !self.zip.is_empty() {
+            result.push(format!("zip: {}", self.zip.bold()));
+        }
+
+        if let (Some(lat), Some(lon)) = (self.lat, self.lon) {
+            result.push(format!("coordinates: {}", format!("{lat}, {lon}").bold()));
+        }
+
+        if !self.timezone.is_empty() {
+            result.push(format!("timezone: {}", self.timezone.bold()));
+        }
+
+        if !self.org.is_empty() {
+            result.push(format!("organization: {}", self.org.bold()));
+        }
+
+        if !self.isp.is_empty() {
+            result.push(format!("isp: {}", self.isp.bold()));
+        }
+
+        if !self.asn.is_empty() {
+            result.push(format!("asn: {}", self.asn.bold()));
+        }
+
+        let flags: Vec<&str> = [(self.mobile, "mobile"), (self.proxy, "proxy"), (self.hosting, "hosting")]
+            .into_iter()
+            .filter(|(flag, _)| *flag == Some(true))
+            .map(|(_, name)| name)
+            .collect();
+        if !flags.is_empty() {
+            result.push(format!("flags: {}", flags.join(", ").yellow().bold()));
+        }
+
+        write!(f, "\t{}", result.join("\n\t"))
+    }
+}
+
+/// Why an address couldn't be located.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocateError {
+    /// the provider couldn't be reached
+    Request { ip: IpAddr, reason: String },
+    /// the provider answered with something we couldn't make sense of
+    InvalidResponse { ip: IpAddr },
+    /// the provider has nothing on this address
+    NotFound { ip: IpAddr, reason: String },
+    /// a local database couldn't be read
+    Database { ip: IpAddr, reason: String },
+    /// the provider refused because our quota is used up
+    RateLimited { ip: IpAddr, retry_in: Duration },
+}
+impl std::fmt::Display for LocateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request { ip, reason } => write!(f, "error finding location data for {ip}: {reason}"),
+            Self::InvalidResponse { ip } => write!(f, "invalid response for {ip}"),
+            Self::NotFound { ip, reason } => write!(f, "no location data for {ip}: {reason}"),
+            Self::Database { ip, reason } => write!(f, "error reading location data for {ip}: {reason}"),
+            Self::RateLimited { ip, retry_in } => write!(f, "rate limited while locating {ip}, retry in {} s", retry_in.as_secs()),
+        }
+    }
+}
+impl std::error::Error for LocateError {}
+
+/// Something that can turn an IP address into a location.
+pub trait GeoProvider: Send + Sync {
+    /// the server requests go to, used as the rate limiting key. `None` for local lookups.
+    fn server(&self) -> Option<&str>;
+
+    fn locate(&self, ip: IpAddr) -> Result<LocationData, LocateError>;
+
+    /// how long to hold off before the next request, when the provider has announced that our quota is used up
+    fn backoff(&self) -> Option<Duration> {
+        None
+    }
+
+    /// the most addresses [`GeoProvider::locate_batch`] is given at once
+    fn batch_size(&self) -> usize {
+        1
+    }
+
+    /// locates several addresses, returning one result per address in the same order.
+    /// providers that can answer for many addresses in a single request should override this.
+    fn locate_batch(&self, ips: &[IpAddr]) -> Vec<Result<LocationData, LocateError>> {
+        ips.iter().map(|ip| self.locate(*ip)).collect()
+    }
+}
+
+/// The geolocation providers that can be picked on the command line.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProviderKind {
+    /// ip-api.com (the default, no key needed)
+    IpApi,
+    /// ipinfo.io
+    Ipinfo,
+    /// ipapi.co
+    IpapiCo,
+}
+
+/// Local databases to geolocate from instead of a web service.
+pub struct OfflineDatabases {
+    /// MaxMind DB files
+    pub mmdb: Vec<PathBuf>,
+    /// IP range CSV files, and how their columns are laid out
+    pub csv: Vec<PathBuf>,
+    pub csv_columns: csv_db::ColumnMap,
+}
+
+/// How to talk to a web provider, for paid tiers and proxies.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WebConfig {
+    /// replaces the provider's own base URL, e.g. `https://pro.ip-api.com`
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+    /// extra headers sent with every request
+    pub headers: Vec<(String, String)>,
+}
+impl WebConfig {
+    fn client(&self) -> Result<Client, String> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("invalid header name {name}"))?;
+            let value = HeaderValue::from_str(value).map_err(|_| format!("invalid value for header {name}"))?;
+            headers.append(name, value);
+        }
+        Client::builder().default_headers(headers).build().map_err(|e| e.to_string())
+    }
+}
+
+/// parses a header given as `Name: value`
+pub fn parse_header(header: &str) -> Result<(String, String), String> {
+    let (name, value) = header.split_once(':').ok_or_else(|| format!("expected Name: value, got {header}"))?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format!("missing header name in {header}"));
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
+/// sets up the chosen provider. offline databases, when given, take precedence over any web service;
+/// only one kind of offline database is used at a time.
+pub fn provider(kind: ProviderKind, offline: &OfflineDatabases, web: &WebConfig) -> Result<Box<dyn GeoProvider>, String> {
+    if !offline.mmdb.is_empty() {
+        return Ok(Box::new(mmdb::MmdbProvider::open(&offline.mmdb)?));
+    }
+    if !offline.csv.is_empty() {
+        return Ok(Box::new(csv_db::CsvProvider::open(&offline.csv, &offline.csv_columns)?));
+    }
+    let client = web.client()?;
+    let key = web.api_key.clone();
+    Ok(match kind {
+        ProviderKind::IpApi => {
+            // keys only work with the paid tier, which has its own (HTTPS) host
+            let default = if key.is_some() { ip_api::PRO_BASE_URL } else { ip_api::BASE_URL };
+            let base_url = web.base_url.as_deref().unwrap_or(default);
+            Box::new(ip_api::IpApi::new(base_url).with_key(key).with_client(client))
+        },
+        ProviderKind::Ipinfo => {
+            let base_url = web.base_url.as_deref().unwrap_or(ipinfo::BASE_URL);
+            Box::new(ipinfo::IpInfo::new(base_url).with_key(key).with_client(client))
+        },
+        ProviderKind::IpapiCo => {
+            let base_url = web.base_url.as_deref().unwrap_or(ipapi_co::BASE_URL);
+            Box::new(ipapi_co::IpApiCo::new(base_url).with_key(key).with_client(client))
+        },
+    })
+}
+
+/// how often a batch is retried after the provider said we were rate limited
+const RATE_LIMIT_RETRIES: usize = 2;
+
+/// locates every address in `ips`, in batches as large as the provider allows, with up to `jobs`
+/// batches in flight at once. addresses found in `cache` aren't looked up again, and new locations from
+/// web providers are added to it.
+pub fn lookup_all(provider: &dyn GeoProvider, ips: &[IpAddr], limiter: &RateLimiter, cache: Option<&GeoCache>, jobs: usize) -> Vec<Result<LocationData, LocateError>> {
+    // offline databases are fast enough on their own
+    let cache = cache.zip(provider.server());
+    let cached: Vec<Option<LocationData>> = ips.iter()
+        .map(|ip| cache.and_then(|(cache, server)| cache.get(server, *ip)))
+        .collect();
+    let missing: Vec<IpAddr> = ips.iter().zip(&cached)
+        .filter(|(_, location)| location.is_none())
+        .map(|(ip, _)| *ip)
+        .collect();
+
+    let located = locate_all(provider, &missing, limiter, jobs);
+    if let Some((cache, server)) = cache {
+        for (ip, result) in missing.iter().zip(&located) {
+            if let Ok(location) = result {
+                cache.insert(server, *ip, location.clone());
+            }
+        }
+    }
+
+    let mut located = located.into_iter();
+    cached.into_iter()
+        .map(|location| match location {
+            Some(location) => Ok(location),
+            None => located.next().expect("one result per missing address"),
+        })
+        .collect()
+}
+
+/// asks `provider` about every address in `ips`. each batch counts as one request for the rate limit of the
+/// provider's server. requests are held back while the provider's quota is used up, and rate limited batches are retried.
+fn locate_all(provider: &dyn GeoProvider, ips: &[IpAddr], limiter: &RateLimiter, jobs: usize) -> Vec<Result<LocationData, LocateError>> {
+    let batches: Vec<&[IpAddr]> = ips.chunks(provider.batch_size().max(1)).collect();
+    let mut results = Vec::with_capacity(ips.len());
+    bulk::run_ordered(&batches, jobs, false, |batch| {
+        let mut retries = 0;
+        loop {
+            if let Some(server) = provider.server() {
+                if let Some(backoff) = provider.backoff() {
+                    limiter.pause(server, backoff);
+                }
+                limiter.wait(server);
+            }
+            let located = provider.locate_batch(batch);
+            let retry_in = located.iter().find_map(|result| match result {
+                Err(LocateError::RateLimited { retry_in, .. }) => Some(*retry_in),
+                _ => None,
+            });
+            match (retry_in, provider.server()) {
+                (Some(retry_in), Some(server)) if retries < RATE_LIMIT_RETRIES => {
+                    eprintln!("rate limited by {server}, retrying in {} s", retry_in.as_secs());
+                    limiter.pause(server, retry_in);
+                    retries += 1;
+                },
+                _ => return located,
+            }
+        }
+    }, |located| results.extend(located));
+    results
+}
+
+/// how long to wait before asking again, if `response` says we've been rate limited.
+/// servers that don't say fall back to a minute.
+fn rate_limited(response: &reqwest::blocking::Response) -> Option<Duration> {
+    if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let seconds = ["retry-after", "x-ttl"].into_iter().find_map(|name| header_number(response.headers(), name));
+    Some(seconds_to_wait(seconds.unwrap_or(60)))
+}
+
+/// a wait the server asked for, capped so that a bogus header can't stall or overflow anything
+fn seconds_to_wait(seconds: u64) -> Duration {
+    Duration::from_secs(seconds).min(crate::rate_limit::MAX_PAUSE)
+}
+
+/// a header holding a plain number, like `X-Rl: 44`
+fn header_number(headers: &reqwest::header::HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// the host part of a base URL like `http://ip-api.com`, for use as a rate limiting key
+pub fn host_of(base_url: &str) -> String {
+    let rest = base_url.split_once("://").map_or(base_url, |(_, rest)| rest);
+    rest.split('/').next().unwrap_or_default().to_string()
+}
+
+/// turns a two letter continent code into its name, for providers that only give the code
+fn continent_name(code: &str) -> &'static str {
+    match code {
+        "AF" => "Africa",
+        "AN" => "Antarctica",
+        "AS" => "Asia",
+        "EU" => "Europe",
+        "NA" => "North America",
+        "OC" => "Oceania",
+        "SA" => "South America",
+        _ => "",
+    }
+}
@@ -0,0 +1,114 @@
+//! Addresses from the IANA special-purpose address registries, which no geolocation provider
+//! can say anything useful about. They're labelled locally instead of being sent off.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// A block set aside for a special purpose, e.g. "private (RFC1918)".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpecialPurpose {
+    pub name: &'static str,
+    pub rfc: &'static str,
+}
+impl std::fmt::Display for SpecialPurpose {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.rfc)
+    }
+}
+
+/// the IPv4 registry, leaving out the blocks that are globally reachable (like AS112).
+/// more specific blocks come first.
+const V4: &[(Ipv4Addr, u8, &str, &str)] = &[
+    (Ipv4Addr::new(0, 0, 0, 0), 8, "this network", "RFC791"),
+    (Ipv4Addr::new(10, 0, 0, 0), 8, "private", "RFC1918"),
+    (Ipv4Addr::new(100, 64, 0, 0), 10, "shared address space (CGNAT)", "RFC6598"),
+    (Ipv4Addr::new(127, 0, 0, 0), 8, "loopback", "RFC1122"),
+    (Ipv4Addr::new(169, 254, 0, 0), 16, "link-local", "RFC3927"),
+    (Ipv4Addr::new(172, 16, 0, 0), 12, "private", "RFC1918"),
+    (Ipv4Addr::new(192, 0, 0, 0), 24, "IETF protocol assignments", "RFC6890"),
+    (Ipv4Addr::new(192, 0, 2, 0), 24, "documentation (TEST-NET-1)", "RFC5737"),
+    (Ipv4Addr::new(192, 88, 99, 0), 24, "deprecated 6to4 relay anycast", "RFC7526"),
+    (Ipv4Addr::new(192, 168, 0, 0), 16, "private", "RFC1918"),
+    (Ipv4Addr::new(198, 18, 0, 0), 15, "benchmarking", "RFC2544"),
+    (Ipv4Addr::new(198, 51, 100, 0), 24, "documentation (TEST-NET-2)", "RFC5737"),
+    (Ipv4Addr::new(203, 0, 113, 0), 24, "documentation (TEST-NET-3)", "RFC5737"),
+    (Ipv4Addr::new(224, 0, 0, 0), 4, "multicast", "RFC5771"),
+    (Ipv4Addr::new(255, 255, 255, 255), 32, "limited broadcast", "RFC919"),
+    (Ipv4Addr::new(240, 0, 0, 0), 4, "reserved", "RFC1112"),
+];
+
+/// the IPv6 registry, again without the globally reachable blocks (like Teredo or 6to4).
+/// IPv4-mapped addresses are classified by the address they embed.
+const V6: &[(Ipv6Addr, u8, &str, &str)] = &[
+    (Ipv6Addr::UNSPECIFIED, 128, "unspecified", "RFC4291"),
+    (Ipv6Addr::LOCALHOST, 128, "loopback", "RFC4291"),
+    (Ipv6Addr::new(0x64, 0xff9b, 1, 0, 0, 0, 0, 0), 48, "local-use IPv4/IPv6 translation", "RFC8215"),
+    (Ipv6Addr::new(0x100, 0, 0, 0, 0, 0, 0, 0), 64, "discard-only", "RFC6666"),
+    (Ipv6Addr::new(0x2001, 0x2, 0, 0, 0, 0, 0, 0), 48, "benchmarking", "RFC5180"),
+    (Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), 32, "documentation", "RFC3849"),
+    (Ipv6Addr::new(0x3fff, 0, 0, 0, 0, 0, 0, 0), 20, "documentation", "RFC9637"),
+    (Ipv6Addr::new(0x5f00, 0, 0, 0, 0, 0, 0, 0), 16, "segment routing (SRv6) SIDs", "RFC9602"),
+    (Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7, "unique local", "RFC4193"),
+    (Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10, "link-local", "RFC4291"),
+    (Ipv6Addr::new(0xff00, 0, 0, 0, 0, 0, 0, 0), 8, "multicast", "RFC4291"),
+];
+
+fn in_block(ip: u128, block: u128, prefix: u8, bits: u32) -> bool {
+    let shift = bits - u32::from(prefix);
+    ip.checked_shr(shift).unwrap_or(0) == block.checked_shr(shift).unwrap_or(0)
+}
+
+/// what `ip` is set aside for, if it's a special-purpose address
+pub fn classify(ip: IpAddr) -> Option<SpecialPurpose> {
+    let found = match ip {
+        IpAddr::V4(v4) => V4.iter()
+            .find(|(block, prefix, ..)| in_block(u32::from(v4).into(), u32::from(*block).into(), *prefix, 32))
+            .map(|&(_, _, name, rfc)| (name, rfc)),
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => return classify(IpAddr::V4(v4)),
+            None => V6.iter()
+                .find(|(block, prefix, ..)| in_block(u128::from(v6), u128::from(*block), *prefix, 128))
+                .map(|&(_, _, name, rfc)| (name, rfc)),
+        },
+    };
+    found.map(|(name, rfc)| SpecialPurpose { name, rfc })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod util {
+        use super::*;
+
+        pub fn label(ip: &str) -> Option<String> {
+            classify(ip.parse().unwrap()).map(|purpose| purpose.to_string())
+        }
+    }
+
+    #[test]
+    fn ipv4_blocks() {
+        assert_eq!(util::label("10.1.2.3").as_deref(), Some("private (RFC1918)"));
+        assert_eq!(util::label("172.31.255.255").as_deref(), Some("private (RFC1918)"));
+        assert_eq!(util::label("172.32.0.1"), None);
+        assert_eq!(util::label("100.127.0.1").as_deref(), Some("shared address space (CGNAT) (RFC6598)"));
+        assert_eq!(util::label("127.0.0.1").as_deref(), Some("loopback (RFC1122)"));
+        assert_eq!(util::label("192.0.2.14").as_deref(), Some("documentation (TEST-NET-1) (RFC5737)"));
+        assert_eq!(util::label("239.255.255.250").as_deref(), Some("multicast (RFC5771)"));
+        assert_eq!(util::label("255.255.255.255").as_deref(), Some("limited broadcast (RFC919)"));
+        assert_eq!(util::label("250.0.0.1").as_deref(), Some("reserved (RFC1112)"));
+        assert_eq!(util::label("8.8.8.8"), None);
+        assert_eq!(util::label("93.184.215.14"), None);
+    }
+
+    #[test]
+    fn ipv6_blocks() {
+        assert_eq!(util::label("::1").as_deref(), Some("loopback (RFC4291)"));
+        assert_eq!(util::label("::").as_deref(), Some("unspecified (RFC4291)"));
+        assert_eq!(util::label("fe80::1").as_deref(), Some("link-local (RFC4291)"));
+        assert_eq!(util::label("fd12:3456::1").as_deref(), Some("unique local (RFC4193)"));
+        assert_eq!(util::label("2001:db8::1").as_deref(), Some("documentation (RFC3849)"));
+        assert_eq!(util::label("ff02::1").as_deref(), Some("multicast (RFC4291)"));
+        assert_eq!(util::label("::ffff:192.168.1.1").as_deref(), Some("private (RFC1918)"));
+        assert_eq!(util::label("2606:4700:4700::1111"), None);
+    }
+}
@@ -0,0 +1,363 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, net::IpAddr};
+
+use serde::Serialize;
+
+use crate::asn::{Cymru, OriginInfo};
+use crate::bulk;
+use crate::dns_client::{self, DNS_SERVER};
+use crate::dns_components::{dns_message::DnsMessage, dns_name::DnsName, dns_question::DnsQuestion, dns_rdata::DnsRdata, dns_rr::DnsResourceRecord};
+use crate::ip_locator::{self, cache::GeoCache, special, GeoProvider, LocationData};
+use crate::rate_limit::RateLimiter;
+use crate::rdap::{self, Rdap, RdapInfo};
+use crate::targets::Target;
+use crate::whois::{self, Whois, WhoisInfo};
+
+/// What every lookup needs access to, shared between all worker threads.
+pub struct LookupContext {
+    pub limiter: RateLimiter,
+    pub geo: Box<dyn GeoProvider>,
+    /// locations from earlier runs; `None` with `--no-cache`
+    pub cache: Option<GeoCache>,
+    /// how many requests may be in flight at once
+    pub jobs: usize,
+}
+
+/// Everything found out about a single target. This is the one model every output format is built from.
+#[derive(Serialize, Debug)]
+pub struct LookupResult {
+    /// the target as the user gave it (after normalization)
+    pub target: String,
+    /// the DNS question that was sent, if the target needed resolving
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub question: Option<DnsQuestion>,
+    /// CNAME records followed from the question name, in the order they were followed
+    pub chain: Vec<CnameLink>,
+    pub addresses: Vec<AddressInfo>,
+    /// registration details of the domain, when asked for with `--whois`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whois: Option<WhoisInfo>,
+    /// registration details of the domain, when asked for with `--rdap`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rdap: Option<RdapInfo>,
+    /// set when the lookup failed as a whole
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CnameLink {
+    pub alias: DnsName,
+    pub canonical: DnsName,
+    pub ttl: u32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AddressInfo {
+    /// the owner name of the address record; `None` when the address was given directly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<DnsName>,
+    pub ip: IpAddr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<LocationData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location_error: Option<String>,
+    /// who announces the address, when asked for with `--asn`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<OriginInfo>,
+    /// registration details of the network, when asked for with `--whois`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whois: Option<WhoisInfo>,
+    /// registration details of the network, when asked for with `--rdap`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rdap: Option<RdapInfo>,
+    /// set for special-purpose addresses (private, loopback, documentation, ...), which aren't geolocated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub special: Option<String>,
+}
+impl AddressInfo {
+    fn new(name: Option<DnsName>, ip: IpAddr, ttl: Option<u32>) -> Self {
+        Self {
+            name,
+            ip,
+            ttl,
+            location: None,
+            location_error: None,
+            origin: None,
+            whois: None,
+            rdap: None,
+            special: special::classify(ip).map(|purpose| purpose.to_string()),
+        }
+    }
+}
+
+/// looks up a single target: hostnames are resolved, addresses and networks are taken as they are.
+/// nothing is geolocated yet, see [`geolocate`].
+pub fn lookup(target: &Target, ctx: &LookupContext) -> LookupResult {
+    match target {
+        Target::Host(host) => resolve(host, ctx),
+        Target::Ip(addr) | Target::Cidr(addr, _) => LookupResult {
+            target: target.to_string(),
+            question: None,
+            chain: Vec::new(),
+            addresses: vec![AddressInfo::new(None, *addr, None)],
+            whois: None,
+            rdap: None,
+            error: None,
+        },
+    }
+}
+
+/// a result for every owner name of the A and AAAA records among `records`, e.g. those of a
+/// zone transfer, in the order the names first appear
+pub fn from_records(records: &[DnsResourceRecord]) -> Vec<LookupResult> {
+    let mut results: Vec<LookupResult> = Vec::new();
+    // where each name's result is in `results`, as zones can have a great many names
+    let mut indexes: HashMap<String, usize> = HashMap::new();
+    for record in records {
+        let ip = match record.rdata {
+            DnsRdata::ARecord(ip) => IpAddr::V4(ip),
+            DnsRdata::AaaaRecord(ip) => IpAddr::V6(ip),
+            _ => continue,
+        };
+        let address = AddressInfo::new(Some(record.name.clone()), ip, Some(record.ttl));
+        let target = record.name.to_string();
+        match indexes.get(&target) {
+            Some(&index) => results[index].addresses.push(address),
+            None => {
+                indexes.insert(target.clone(), results.len());
+                results.push(LookupResult {
+                    target,
+                    question: None,
+                    chain: Vec::new(),
+                    addresses: vec![address],
+                    whois: None,
+                    rdap: None,
+                    error: None,
+                });
+            },
+        }
+    }
+    results
+}
+
+/// resolves `host`, collecting every address found along its CNAME chain
+fn resolve(host: &str, ctx: &LookupContext) -> LookupResult {
+    let mut result = LookupResult {
+        target: host.to_string(),
+        question: Some(DnsQuestion::query(host)),
+        chain: Vec::new(),
+        addresses: Vec::new(),
+        whois: None,
+        rdap: None,
+        error: None,
+    };
+
+    let msg = match dns_client::query(DNS_SERVER, host, 1, &ctx.limiter) {
+        Ok(msg) => msg,
+        Err(e) => {
+            result.error = Some(e.to_string());
+            return result;
+        }
+    };
+
+    let (chain, addresses) = follow_chain(&msg, DnsName::from_string(host));
+    result.chain = chain;
+    result.addresses = addresses.into_iter()
+        .map(|(name, ip, ttl)| AddressInfo::new(Some(name), ip, Some(ttl)))
+        .collect();
+    result
+}
+
+/// geolocates the addresses of all `results` at once, so that providers which can answer
+/// for many addresses in one request get to do so. every address is only looked up once,
+/// and special-purpose addresses aren't looked up at all.
+pub fn geolocate(results: &mut [LookupResult], ctx: &LookupContext) {
+    let mut seen = HashSet::new();
+    let ips: Vec<IpAddr> = results.iter()
+        .flat_map(|result| result.addresses.iter())
+        .filter(|address| address.special.is_none())
+        .map(|address| address.ip)
+        .filter(|ip| seen.insert(*ip))
+        .collect();
+
+    let locations: HashMap<IpAddr, _> = ips.iter().copied()
+        .zip(ip_locator::lookup_all(ctx.geo.as_ref(), &ips, &ctx.limiter, ctx.cache.as_ref(), ctx.jobs))
+        .collect();
+
+    for address in results.iter_mut().flat_map(|result| result.addresses.iter_mut()) {
+        match locations.get(&address.ip) {
+            Some(Ok(location)) => address.location = Some(location.clone()),
+            Some(Err(e)) => address.location_error = Some(e.to_string()),
+            None => {},
+        }
+    }
+}
+
+/// finds the origin AS and announced prefix of every address in `results` (except special-purpose ones)
+pub fn find_origins(results: &mut [LookupResult], ctx: &LookupContext, cymru: &Cymru) {
+    let mut seen = HashSet::new();
+    let ips: Vec<IpAddr> = results.iter()
+        .flat_map(|result| result.addresses.iter())
+        .filter(|address| address.special.is_none())
+        .map(|address| address.ip)
+        .filter(|ip| seen.insert(*ip))
+        .collect();
+
+    let mut origins = HashMap::new();
+    bulk::run_ordered(&ips, ctx.jobs, false, |ip| (*ip, cymru.origin(*ip, &ctx.limiter)), |(ip, origin)| match origin {
+        Ok(origin) => {
+            origins.insert(ip, origin);
+        },
+        Err(e) => eprintln!("error finding the origin of {ip}: {e}"),
+    });
+
+    for address in results.iter_mut().flat_map(|result| result.addresses.iter_mut()) {
+        address.origin = origins.get(&address.ip).cloned().flatten();
+    }
+}
+
+/// looks up the registration of every domain and address in `results` (except special-purpose addresses)
+pub fn find_whois(results: &mut [LookupResult], ctx: &LookupContext, client: &Whois) {
+    let mut seen = HashSet::new();
+    let queries: Vec<String> = results.iter()
+        .flat_map(|result| {
+            let domain = result.question.as_ref().map(|_| whois::registered_domain(&result.target).to_string());
+            let addresses = result.addresses.iter()
+                .filter(|address| address.special.is_none())
+                .map(|address| address.ip.to_string());
+            domain.into_iter().chain(addresses)
+        })
+        .filter(|query| seen.insert(query.clone()))
+        .collect();
+
+    let mut answers = HashMap::new();
+    bulk::run_ordered(&queries, ctx.jobs, false, |query| (query.clone(), client.lookup(query, &ctx.limiter)), |(query, info)| match info {
+        Ok(info) => {
+            answers.insert(query, info);
+        },
+        Err(e) => eprintln!("error looking up {query} in WHOIS: {e}"),
+    });
+    for result in results.iter_mut() {
+        if result.question.is_some() {
+            result.whois = answers.get(whois::registered_domain(&result.target)).cloned();
+        }
+        for address in &mut result.addresses {
+            address.whois = answers.get(&address.ip.to_string()).cloned();
+        }
+    }
+}
+
+/// looks up the registration of every domain and address in `results` over RDAP (except special-purpose addresses)
+pub fn find_rdap(results: &mut [LookupResult], ctx: &LookupContext, client: &Rdap) {
+    let mut seen = HashSet::new();
+    let queries: Vec<rdap::Query> = results.iter()
+        .flat_map(|result| {
+            let domain = result.question.as_ref().map(|_| rdap::Query::Domain(whois::registered_domain(&result.target).to_string()));
+            let addresses = result.addresses.iter()
+                .filter(|address| address.special.is_none())
+                .map(|address| rdap::Query::Ip(address.ip));
+            domain.into_iter().chain(addresses)
+        })
+        .filter(|query| seen.insert(query.clone()))
+        .collect();
+
+    let mut answers = HashMap::new();
+    bulk::run_ordered(&queries, ctx.jobs, false, |query| (query.clone(), client.lookup(query, &ctx.limiter)), |(query, info)| match info {
+        Ok(info) => {
+            answers.insert(query, info);
+        },
+        Err(e) => eprintln!("error looking up {query} in RDAP: {e}"),
+    });
+    for result in results.iter_mut() {
+        if result.question.is_some() {
+            result.rdap = answers.get(&rdap::Query::Domain(whois::registered_domain(&result.target).to_string())).cloned();
+        }
+        for address in &mut result.addresses {
+            address.rdap = answers.get(&rdap::Query::Ip(address.ip)).cloned();
+        }
+    }
+}
+
+/// walks the answer section starting at `qname`, collecting the CNAMEs followed and the addresses found.
+/// every name is only visited once, so a CNAME loop can't send us round in circles.
+fn follow_chain(msg: &DnsMessage, qname: DnsName) -> (Vec<CnameLink>, Vec<(DnsName, IpAddr, u32)>) {
+    let mut chain = Vec::new();
+    let mut addresses = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([qname]);
+
+    while let Some(name) = queue.pop_front() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        for answer in msg.answers().iter().filter(|answer| answer.name == name) {
+            match &answer.rdata {
+                DnsRdata::ARecord(addr) => addresses.push((name.clone(), IpAddr::V4(*addr), answer.ttl)),
+                DnsRdata::CnameRecord(canonical) => {
+                    chain.push(CnameLink {
+                        alias: name.clone(),
+                        canonical: canonical.clone(),
+                        ttl: answer.ttl,
+                    });
+                    queue.push_back(canonical.clone());
+                },
+                _ => {},
+            }
+        }
+    }
+
+    (chain, addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod util {
+        /// www.example.com. CNAME example.com. / example.com. A 93.184.215.14, using a compression pointer
+        pub fn cname_response() -> Vec<u8> {
+            let mut msg = vec![0xab, 0xba, 0x81, 0x80, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00];
+            msg.extend([3, b'w', b'w', b'w', 7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0, 0x00, 0x01, 0x00, 0x01]);
+            // www.example.com CNAME example.com (pointer to offset 16)
+            msg.extend([0xc0, 0x0c, 0x00, 0x05, 0x00, 0x01, 0x00, 0x00, 0x01, 0x2c, 0x00, 0x02, 0xc0, 0x10]);
+            // example.com A 93.184.215.14
+            msg.extend([0xc0, 0x10, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0b, 0xbb, 0x00, 0x04, 93, 184, 215, 14]);
+            msg
+        }
+    }
+
+    #[test]
+    fn follow_cname_chain() {
+        let msg = DnsMessage::parse(&util::cname_response()).unwrap();
+        let (chain, addresses) = follow_chain(&msg, DnsName::from_string("www.example.com"));
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain[0].alias, DnsName::from_string("www.example.com"));
+        assert_eq!(chain[0].canonical, DnsName::from_string("example.com"));
+        assert_eq!(chain[0].ttl, 300);
+        assert_eq!(addresses, vec![(DnsName::from_string("example.com"), "93.184.215.14".parse().unwrap(), 3003)]);
+    }
+
+    #[test]
+    fn follow_chain_unrelated_name() {
+        let msg = DnsMessage::parse(&util::cname_response()).unwrap();
+        let (chain, addresses) = follow_chain(&msg, DnsName::from_string("other.example.com"));
+        assert!(chain.is_empty());
+        assert!(addresses.is_empty());
+    }
+
+    #[test]
+    fn results_from_zone_records() {
+        let zone = "$TTL 60\n@ SOA ns h 1 2 3 4 5\nwww A 192.0.2.1\nmail A 192.0.2.2\nwww AAAA 2001:db8::1\n";
+        let records = crate::dns_components::zone_file::parse(zone, &DnsName::from_string("example"), std::path::Path::new(".")).unwrap();
+        let results = from_records(&records);
+        let addresses: Vec<(&str, Vec<String>)> = results.iter()
+            .map(|result| (result.target.as_str(), result.addresses.iter().map(|address| address.ip.to_string()).collect()))
+            .collect();
+        assert_eq!(addresses, [
+            ("www.example", vec!["192.0.2.1".to_string(), "2001:db8::1".to_string()]),
+            ("mail.example", vec!["192.0.2.2".to_string()]),
+        ]);
+    }
+}
@@ -1,92 +1,737 @@
+use std::{fs::File, io::{self, BufReader}, path::{Path, PathBuf}, time::Duration};
+use clap::{Parser, Subcommand};
 use colored::Colorize;
-use std::{collections::HashMap, net::{Ipv4Addr, UdpSocket}};
-use clap::Parser;
 
-use dns_components::{dns_message::DnsMessage, dns_name::DnsName, dns_rdata::DnsRdata};
+use dns_components::{dns_header, dns_name::DnsName, dns_rdata::DnsRdata, dns_rr, tsig, zone_file};
+use ip_locator::{cache::GeoCache, csv_db::ColumnMap, OfflineDatabases, ProviderKind, WebConfig};
+use lookup::{LookupContext, LookupResult};
+use output::{ColorChoice, Column, Format, Printer};
+use rate_limit::RateLimiter;
+use targets::Target;
 
+mod asn;
+mod bulk;
+mod config;
+mod dns_client;
 mod dns_components;
 mod ip_locator;
+mod lookup;
+mod output;
+mod rate_limit;
+mod rdap;
+mod targets;
+mod transfer;
+mod update;
+mod validator;
+mod whois;
+mod zone_check;
+#[cfg(test)]
+mod test_dns;
+#[cfg(test)]
+mod test_http;
 
 #[derive(Parser)]
 struct Arguments {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(short,long)]
     urls: Vec<String>,
     #[arg(short,long)]
     ips: Vec<String>,
+    /// read targets from a file, one hostname, IP, CIDR block or URL per line (`-` for stdin)
+    #[arg(long, value_name = "FILE")]
+    input: Option<PathBuf>,
+    /// how many lookups to run at the same time
+    #[arg(short,long, default_value_t = 1)]
+    jobs: usize,
+    /// maximum requests per minute sent to any single server (DNS or geolocation)
+    #[arg(long)]
+    rate_limit: Option<u32>,
+    /// how many requests a server may get in a burst before --rate-limit spaces them out
+    #[arg(long, default_value_t = 1, requires = "rate_limit")]
+    rate_burst: u32,
+    /// show how many lookups have finished on stderr
+    #[arg(long)]
+    progress: bool,
+    /// how results are written to stdout
+    #[arg(short,long, value_enum, default_value_t = Format::Terminal)]
+    format: Format,
+    /// columns to include with `--format csv` or `--format tsv`, comma separated
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = Column::ALL)]
+    columns: Vec<Column>,
+    /// which geolocation service to ask
+    #[arg(long, value_enum, default_value_t = ProviderKind::IpApi)]
+    geo_provider: ProviderKind,
+    /// geolocate offline using MaxMind DB files (e.g. GeoLite2 City and ASN); can be repeated
+    #[arg(long, value_name = "PATH")]
+    geo_db: Vec<PathBuf>,
+    /// geolocate offline using IP range CSV files (IP2Location/DB-IP style); can be repeated
+    #[arg(long, value_name = "PATH", conflicts_with = "geo_db")]
+    geo_csv: Vec<PathBuf>,
+    /// the column layout of the `--geo-csv` files: `ip2location`, `dbip`, or a map like `start=0,end=1,country_code=2,city=5`
+    #[arg(long, value_name = "MAP", default_value = "ip2location", value_parser = ColumnMap::parse)]
+    geo_csv_columns: ColumnMap,
+    /// when to color terminal output
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// base URL of the geolocation provider, e.g. for a paid tier or a proxy
+    #[arg(long, value_name = "URL")]
+    geo_url: Option<String>,
+    /// API key for the geolocation provider (or set INLOC_GEO_KEY)
+    #[arg(long, value_name = "KEY")]
+    geo_key: Option<String>,
+    /// extra header sent with every geolocation request, as `Name: value`; may be repeated
+    #[arg(long, value_name = "HEADER", value_parser = ip_locator::parse_header)]
+    geo_header: Vec<(String, String)>,
+    /// also look up the origin AS and announced prefix of every address, through Team Cymru's DNS service
+    #[arg(long)]
+    asn: bool,
+    /// also look up the registration (WHOIS) of every domain and address
+    #[arg(long)]
+    whois: bool,
+    /// also look up the registration of every domain and address over RDAP
+    #[arg(long)]
+    rdap: bool,
+    /// don't use the geolocation cache, neither reading nor writing it
+    #[arg(long)]
+    no_cache: bool,
+    /// how long cached locations are used before they are looked up again
+    #[arg(long, value_name = "HOURS", default_value_t = 168)]
+    cache_expiry: u64,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// manage the geolocation cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// ask the DNS server for records of any type, e.g. DNSKEY, DS or NSEC3PARAM, and print them in zone file format
+    Query {
+        name: String,
+        /// the record type, as a mnemonic like `DNSKEY` or as `TYPE1234`
+        #[arg(default_value = "A")]
+        rtype: String,
+        /// validate the answers with DNSSEC, from the root trust anchor down
+        #[arg(long)]
+        validate: bool,
+    },
+    /// read a zone file and print its records sorted, with absolute names, one per line
+    FormatZone {
+        /// the zone file, or `-` for standard input
+        file: PathBuf,
+        /// what relative names are relative to until the file says `$ORIGIN`
+        #[arg(long, default_value = ".")]
+        origin: String,
+    },
+    /// ask a zone's name servers for every record in its zone file and report what's missing, extra or different
+    VerifyZone {
+        file: PathBuf,
+        /// what relative names are relative to until the file says `$ORIGIN`
+        #[arg(long, default_value = ".")]
+        origin: String,
+        /// a name server to ask, as a name or an address with an optional port; may be repeated.
+        /// by default, the name servers the file lists for its apex are asked.
+        #[arg(long = "server", value_name = "SERVER")]
+        servers: Vec<String>,
+    },
+    /// transfer a whole zone (AXFR) from one of its name servers and print its records
+    Axfr {
+        zone: String,
+        /// the name server to ask, as a name or an address with an optional port
+        #[arg(long)]
+        server: String,
+        /// print the records sorted, with the SOA first and without duplicates, as `format-zone` does
+        #[arg(long)]
+        zone_file: bool,
+        /// geolocate every address in the zone instead, printing the results like a lookup
+        #[arg(long, conflicts_with = "zone_file")]
+        geolocate: bool,
+        /// sign the request with the TSIG key in this BIND-style key file, and check the response is signed with it
+        #[arg(long, value_name = "FILE")]
+        tsig_key: Option<PathBuf>,
+    },
+    /// transfer what changed in a zone (IXFR) since a known version, and print the records removed and added
+    Ixfr {
+        zone: String,
+        /// the name server to ask, as a name or an address with an optional port
+        #[arg(long)]
+        server: String,
+        /// the serial of the version to get the changes since
+        #[arg(long, conflicts_with = "file", required_unless_present = "file")]
+        serial: Option<u32>,
+        /// a copy of the zone to get the changes since; with `--output` or `--in-place`, the changes are applied to it
+        #[arg(long)]
+        file: Option<PathBuf>,
+        /// write the copy with the changes applied here, or to stdout with `-` (the changes then go to stderr)
+        #[arg(long, value_name = "PATH", requires = "file", conflicts_with = "serial")]
+        output: Option<PathBuf>,
+        /// write the copy with the changes applied back over `--file`. that drops its comments, directives and
+        /// formatting, and inlines what it includes.
+        #[arg(long, requires = "file", conflicts_with_all = ["output", "serial"])]
+        in_place: bool,
+        /// sign the request with the TSIG key in this BIND-style key file, and check the response is signed with it
+        #[arg(long, value_name = "FILE")]
+        tsig_key: Option<PathBuf>,
+    },
+    /// change records on a zone's primary server with a dynamic update (RFC 2136), and report whether it worked.
+    /// names are relative to the zone, and every change is made or none is.
+    Update {
+        zone: String,
+        /// the name server to send the update to, as a name or an address with an optional port
+        #[arg(long)]
+        server: String,
+        /// a record to add, in zone file syntax, e.g. `"www 300 A 192.0.2.1"`; may be repeated
+        #[arg(long = "add", value_name = "RECORD")]
+        add: Vec<String>,
+        /// an RRset to delete, as `"NAME TYPE"`, or every record of a name, as `NAME`; may be repeated
+        #[arg(long = "delete", value_name = "NAME [TYPE]")]
+        delete: Vec<String>,
+        /// a single record to delete, in zone file syntax; may be repeated
+        #[arg(long = "delete-record", value_name = "RECORD")]
+        delete_record: Vec<String>,
+        /// only update if this RRset, or with no type this name, exists; may be repeated
+        #[arg(long = "if-exists", value_name = "NAME [TYPE]")]
+        if_exists: Vec<String>,
+        /// only update if this RRset, or with no type this name, doesn't exist; may be repeated
+        #[arg(long = "if-absent", value_name = "NAME [TYPE]")]
+        if_absent: Vec<String>,
+        /// sign the update with the TSIG key in this BIND-style key file, and check the response is signed with it
+        #[arg(long, value_name = "FILE")]
+        tsig_key: Option<PathBuf>,
+    },
+    /// look things up over RDAP, or update the registries saying which server to ask
+    Rdap {
+        #[command(subcommand)]
+        action: RdapAction,
+    },
+}
+
+#[derive(Subcommand, Clone, Copy)]
+enum CacheAction {
+    /// delete every cached location
+    Clear,
+    /// show where the cache is and how many locations it holds
+    Stats,
+}
 
-const DNS_SERVER: &str = "8.8.8.8:53";
+#[derive(Subcommand)]
+enum RdapAction {
+    /// download the current bootstrap registries from IANA
+    Update,
+    /// look up an address, an AS number (e.g. `AS15133`) or a domain
+    Lookup {
+        query: String,
+    },
+}
 
 fn main() {
-    let args = Arguments::parse();
+    let mut args = Arguments::parse();
+    output::color::apply(args.color);
+    let cache_expiry = Duration::from_secs(args.cache_expiry.saturating_mul(3600));
 
-    if args.urls.is_empty() && args.ips.is_empty() {
-        eprintln!("no arguments specified; please see --help");
-        return;
+    match args.command.take() {
+        Some(Command::Cache { action }) => {
+            if !run_cache_command(action, cache_expiry) {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Some(Command::Query { name, rtype, validate }) => {
+            let limiter = RateLimiter::new(args.rate_limit, args.rate_burst);
+            let succeeded = if validate {
+                run_validate_command(&name, &rtype, &limiter)
+            } else {
+                run_query_command(&name, &rtype, &limiter)
+            };
+            if !succeeded {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Some(Command::FormatZone { file, origin }) => {
+            if !run_format_zone_command(&file, &DnsName::from_string(&origin)) {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Some(Command::VerifyZone { file, origin, servers }) => {
+            let limiter = RateLimiter::new(args.rate_limit, args.rate_burst);
+            if !run_verify_zone_command(&file, &DnsName::from_string(&origin), &servers, &limiter, args.jobs, args.progress) {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Some(Command::Axfr { zone, server, zone_file, geolocate, tsig_key }) => {
+            let (zone, server) = (DnsName::from_string(&zone), zone_check::server_address(&server));
+            if !run_axfr_command(&zone, &server, tsig_key.as_deref(), zone_file, geolocate, &args, cache_expiry) {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Some(Command::Ixfr { zone, server, serial, file, output, in_place, tsig_key }) => {
+            let limiter = RateLimiter::new(args.rate_limit, args.rate_burst);
+            let output = if in_place { file.clone() } else { output };
+            if !run_ixfr_command(&DnsName::from_string(&zone), &zone_check::server_address(&server), tsig_key.as_deref(), serial, file.as_deref(), output.as_deref(), &limiter) {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Some(Command::Update { zone, server, add, delete, delete_record, if_exists, if_absent, tsig_key }) => {
+            let zone = DnsName::from_string(&zone);
+            let parsed = update_request(&zone, &if_exists, &if_absent, &delete, &delete_record, &add)
+                .and_then(|(prerequisites, changes)| Ok((prerequisites, changes, load_tsig_key(tsig_key.as_deref())?)));
+            let (prerequisites, changes, key) = match parsed {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    eprintln!("error: {e}");
+                    std::process::exit(1);
+                }
+            };
+            let limiter = RateLimiter::new(args.rate_limit, args.rate_burst);
+            if !run_update_command(&zone, &zone_check::server_address(&server), &prerequisites, &changes, key.as_ref(), &limiter) {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Some(Command::Rdap { action }) => {
+            if !run_rdap_command(action, &RateLimiter::new(args.rate_limit, args.rate_burst)) {
+                std::process::exit(1);
+            }
+            return;
+        },
+        None => {},
     }
 
-    for url in &args.urls {
-        dns_lookup(url);
+    let mut targets = Vec::new();
+    for entry in args.urls.iter().chain(&args.ips) {
+        match targets::parse_target(entry) {
+            Ok(target) => targets.push(target),
+            Err(e) => eprintln!("error: {e}"),
+        }
     }
-    for ip in &args.ips {
-        println!("{}", ip.bold());
-        println!("{}", ip_locator::locate(ip));
+    if let Some(path) = &args.input {
+        match read_input(path) {
+            Ok(from_file) => targets.extend(from_file),
+            Err(e) => {
+                eprintln!("error reading {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        }
     }
-}
 
-fn send_dns_request(url: &str) -> Result<DnsMessage, std::io::Error> {
-    let request = DnsMessage::single_query(url);
+    if targets.is_empty() {
+        eprintln!("no arguments specified; please see --help");
+        std::process::exit(1);
+    }
 
-    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    let ctx = match lookup_context(&args, cache_expiry) {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+    };
 
-    socket.connect(DNS_SERVER)?;
+    let mut printer = Printer::new(args.format, args.columns.clone());
 
-    socket.send(&request.to_bytes())?;
+    let rdap = if args.rdap {
+        rdap_client().map_err(|e| eprintln!("error: {e}")).ok()
+    } else {
+        None
+    };
 
-    let mut buffer = [0_u8; 512];
-    socket.recv(&mut buffer)?;
+    // results are geolocated a chunk at a time, so that providers which can answer for many
+    // addresses at once get to do so, while output still appears as the lookups finish
+    let chunk_size = ctx.geo.batch_size().max(ctx.jobs).max(1);
+    let mut chunk = Vec::with_capacity(chunk_size);
+    bulk::run_ordered(&targets, args.jobs, args.progress, |target| lookup::lookup(target, &ctx), |result| {
+        chunk.push(result);
+        if chunk.len() >= chunk_size {
+            finish_chunk(&mut chunk, &ctx, &args, rdap.as_ref(), &mut printer);
+        }
+    });
+    finish_chunk(&mut chunk, &ctx, &args, rdap.as_ref(), &mut printer);
+    if let Some(cache) = &ctx.cache {
+        if let Err(e) = cache.save() {
+            eprintln!("error saving the geolocation cache: {e}");
+        }
+    }
+    printer.finish();
+}
 
-    let response = DnsMessage::parse(&buffer)?;
+/// geolocates and otherwise enriches a chunk of finished lookups, then prints them
+fn finish_chunk(chunk: &mut Vec<LookupResult>, ctx: &LookupContext, args: &Arguments, rdap: Option<&rdap::Rdap>, printer: &mut Printer) {
+    lookup::geolocate(chunk, ctx);
+    if args.asn {
+        lookup::find_origins(chunk, ctx, &asn::Cymru::new(dns_client::DNS_SERVER));
+    }
+    if args.whois {
+        lookup::find_whois(chunk, ctx, &whois::Whois::new(whois::IANA));
+    }
+    if let Some(client) = rdap {
+        lookup::find_rdap(chunk, ctx, client);
+    }
+    for result in chunk.drain(..) {
+        printer.print(result);
+    }
+}
 
-    Ok(response)
+/// what looking up and geolocating addresses needs, from the flags, the environment and the config file
+fn lookup_context(args: &Arguments, cache_expiry: Duration) -> Result<LookupContext, String> {
+    let offline = OfflineDatabases {
+        mmdb: args.geo_db.clone(),
+        csv: args.geo_csv.clone(),
+        csv_columns: args.geo_csv_columns.clone(),
+    };
+    let config = config::default_path().map_or_else(|| Ok(config::Config::default()), |path| config::load(&path))?;
+    // flags win over the environment, which wins over the config file
+    let web = WebConfig {
+        base_url: args.geo_url.clone().or(config.web.base_url),
+        api_key: args.geo_key.clone()
+            .or_else(|| std::env::var("INLOC_GEO_KEY").ok().filter(|key| !key.is_empty()))
+            .or(config.web.api_key),
+        headers: config.web.headers.into_iter().chain(args.geo_header.iter().cloned()).collect(),
+    };
+    let geo = ip_locator::provider(args.geo_provider, &offline, &web)?;
+    Ok(LookupContext {
+        limiter: RateLimiter::new(args.rate_limit, args.rate_burst),
+        geo,
+        cache: (!args.no_cache)
+            .then(GeoCache::default_path)
+            .flatten()
+            .map(|path| GeoCache::open(&path, cache_expiry)),
+        jobs: args.jobs,
+    })
 }
 
-fn print_ip_info(data: &DnsRdata, addr: Ipv4Addr) {
-    println!("found IP address for {}: {}", data.to_string().bold(), addr.to_string().cyan().bold());
-    println!("{}", ip_locator::locate(&addr.to_string()));
+/// returns whether the command succeeded, as do the other `run_*_command` functions, so that scripts can
+/// tell from the exit status
+fn run_cache_command(action: CacheAction, expiry: Duration) -> bool {
+    let Some(path) = GeoCache::default_path() else {
+        eprintln!("error: can't tell where the cache is, neither XDG_CACHE_HOME nor HOME is set");
+        return false;
+    };
+    match action {
+        CacheAction::Clear => match GeoCache::clear(&path) {
+            Ok(()) => println!("cleared {}", path.display()),
+            Err(e) => {
+                eprintln!("error clearing {}: {e}", path.display());
+                return false;
+            },
+        },
+        CacheAction::Stats => {
+            let size = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+            println!("{} ({size} bytes)", path.display());
+            for stats in GeoCache::open(&path, expiry).stats() {
+                println!("{}: {} fresh, {} expired", stats.server, stats.fresh, stats.expired);
+            }
+        },
+    }
+    true
 }
 
-fn print_name_info(data: &DnsRdata, name: &DnsName) {
-    println!("{} is an alias for {}", data.to_string().bold(), name.to_string().bold());
+fn run_query_command(name: &str, rtype: &str, limiter: &RateLimiter) -> bool {
+    let Some(qtype) = dns_rr::type_code(rtype) else {
+        eprintln!("error: unknown record type {rtype}");
+        return false;
+    };
+    let msg = match dns_client::query(dns_client::DNS_SERVER, name.trim_end_matches('.'), qtype, limiter) {
+        Ok(msg) => msg,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return false;
+        }
+    };
+    for answer in msg.answers() {
+        match &answer.rdata {
+            DnsRdata::DnskeyRecord(key) => {
+                let role = if key.is_secure_entry_point() { "KSK" } else { "ZSK" };
+                println!("{answer} ; {role}, key tag {}", key.key_tag());
+            },
+            _ => println!("{answer}"),
+        }
+    }
+    true
+}
+
+/// like [`run_query_command`], with the DNSSEC status of every answer, or of the denial when there's none
+fn run_validate_command(name: &str, rtype: &str, limiter: &RateLimiter) -> bool {
+    let Some(qtype) = dns_rr::type_code(rtype) else {
+        eprintln!("error: unknown record type {rtype}");
+        return false;
+    };
+    let validator = validator::Validator::new(validator::Upstream { server: dns_client::DNS_SERVER, limiter });
+    let validated = match validator.validate(&DnsName::from_string(name), qtype) {
+        Ok(validated) => validated,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return false;
+        }
+    };
+    for (answer, status) in &validated.records {
+        println!("{answer} ; {status}");
+    }
+    if let Some(status) = validated.denial {
+        println!("; no {} records for {name}: {status}", dns_rr::type_name(qtype));
+    }
+    true
 }
 
-fn print_data(data: &DnsRdata, name: &DnsRdata) {
-    match data {
-        DnsRdata::ARecord(addr) => print_ip_info(name, *addr),
-        DnsRdata::CnameRecord(next_name) => print_name_info(name, next_name),
+fn run_format_zone_command(file: &Path, origin: &DnsName) -> bool {
+    let records = if file.as_os_str() == "-" {
+        io::read_to_string(io::stdin().lock())
+            .map_err(|e| format!("can't read standard input: {e}"))
+            .and_then(|text| zone_file::parse(&text, origin, Path::new(".")))
+    } else {
+        zone_file::load(file, origin)
+    };
+    match records {
+        Ok(records) => {
+            print!("{}", zone_file::write(&records));
+            true
+        },
+        Err(e) => {
+            eprintln!("error: {e}");
+            false
+        },
     }
 }
 
-fn print_info(url: &str, ip_table: &HashMap<DnsRdata, Vec<DnsRdata>>) {
-    let name = DnsName::from_string(url);
-    let key = DnsRdata::CnameRecord(name);
-    if let Some(results) = ip_table.get(&key) {
-        for res in results {
-            print_data(res, &key);
-            if let DnsRdata::CnameRecord(new_name) = res {
-                print_info(&new_name.to_string(), ip_table);
+/// returns whether every server matched the file, so that deploy scripts can tell from the exit status
+fn run_verify_zone_command(file: &Path, origin: &DnsName, servers: &[String], limiter: &RateLimiter, jobs: usize, progress: bool) -> bool {
+    let records = match zone_file::load(file, origin) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return false;
+        }
+    };
+    let servers = if servers.is_empty() {
+        zone_check::name_servers(&records)
+    } else {
+        servers.iter().map(|server| zone_check::server_address(server)).collect()
+    };
+    if servers.is_empty() {
+        eprintln!("error: {} has no NS records at its apex, please name the servers to ask with --server", file.display());
+        return false;
+    }
+
+    let mut matched = true;
+    for server in servers {
+        let report = zone_check::check(&records, &server, limiter, jobs, progress);
+        println!("{}", server.bold());
+        for difference in &report.differences {
+            println!("  {difference}");
+        }
+        println!("  {} RRsets checked, {} differences", report.checked, report.differences.len());
+        matched &= report.differences.is_empty();
+    }
+    matched
+}
+
+/// the key in the key file at `path`, if there is one
+fn load_tsig_key(path: Option<&Path>) -> Result<Option<tsig::Key>, String> {
+    path.map(tsig::Key::load).transpose()
+}
+
+fn run_axfr_command(zone: &DnsName, server: &str, tsig_key: Option<&Path>, as_zone_file: bool, geolocate: bool, args: &Arguments, cache_expiry: Duration) -> bool {
+    let key = match load_tsig_key(tsig_key) {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return false;
+        }
+    };
+    let ctx = if geolocate {
+        match lookup_context(args, cache_expiry) {
+            Ok(ctx) => Some(ctx),
+            Err(e) => {
+                eprintln!("error: {e}");
+                return false;
             }
         }
+    } else {
+        None
+    };
+    let records = match transfer::axfr(server, zone, key.as_ref(), &RateLimiter::new(args.rate_limit, args.rate_burst)) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return false;
+        }
+    };
+
+    let Some(ctx) = ctx else {
+        if as_zone_file {
+            print!("{}", zone_file::write(&records));
+        } else {
+            for record in &records {
+                println!("{record}");
+            }
+        }
+        return true;
+    };
+    let mut results = lookup::from_records(&records);
+    lookup::geolocate(&mut results, &ctx);
+    if let Some(cache) = &ctx.cache {
+        if let Err(e) = cache.save() {
+            eprintln!("error saving the geolocation cache: {e}");
+        }
     }
+    let mut printer = Printer::new(args.format, args.columns.clone());
+    for result in results {
+        printer.print(result);
+    }
+    printer.finish();
+    true
 }
 
-fn dns_lookup(url: &str) {
-    let msg = send_dns_request(url);
-    if let Err(e) = msg {
+/// prints the changes since the version given by `serial` or `file`, and writes `file` with them applied to `output`
+fn run_ixfr_command(zone: &DnsName, server: &str, tsig_key: Option<&Path>, serial: Option<u32>, file: Option<&Path>, output: Option<&Path>, limiter: &RateLimiter) -> bool {
+    let loaded = load_tsig_key(tsig_key).and_then(|key| Ok((key, file.map(|file| zone_file::load(file, zone)).transpose()?)));
+    let (key, mut local) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return false;
+        }
+    };
+    let known = match (serial, file.zip(local.as_ref())) {
+        (Some(serial), _) => transfer::known_soa(zone, serial),
+        (None, Some((file, records))) => match records.iter().find(|record| record.rtype == 6) {
+            Some(soa) => soa.clone(),
+            None => {
+                eprintln!("error: {} has no SOA record", file.display());
+                return false;
+            },
+        },
+        (None, None) => unreachable!("clap requires --serial or --file"),
+    };
+
+    let to_stdout = output.is_some_and(|output| output.as_os_str() == "-");
+    // with the zone on stdout, everything else goes to stderr
+    let report = |text: &str| if to_stdout { eprintln!("{text}") } else { println!("{text}") };
+    let diffs = match transfer::ixfr(server, zone, &known, key.as_ref(), limiter) {
+        Ok(transfer::Ixfr::UpToDate) => {
+            report("; up to date");
+            Vec::new()
+        },
+        Ok(transfer::Ixfr::Incremental(diffs)) => diffs,
+        // without a copy to compare with, everything in a full transfer counts as added
+        Ok(transfer::Ixfr::Full(records)) => vec![transfer::Diff::between(local.as_deref().unwrap_or_default(), &records)],
+        Err(e) => {
+            eprintln!("error: {e}");
+            return false;
+        }
+    };
+    for diff in &diffs {
+        report(&diff.to_string());
+    }
+
+    let Some((output, records)) = output.zip(local.as_mut()) else {
+        return true;
+    };
+    // an up to date copy is left as it is, rather than rewritten
+    if diffs.is_empty() && file == Some(output) {
+        return true;
+    }
+    for diff in &diffs {
+        diff.apply(records);
+    }
+    if to_stdout {
+        print!("{}", zone_file::write(records));
+    } else if let Err(e) = zone_file::save(output, records) {
         eprintln!("error: {e}");
-        return;
+        return false;
+    }
+    true
+}
+
+/// the prerequisites and changes of an update, from the arguments of `update`. deletions come before additions,
+/// so that an RRset can be replaced.
+fn update_request(zone: &DnsName, if_exists: &[String], if_absent: &[String], delete: &[String], delete_record: &[String], add: &[String]) -> Result<(Vec<update::Prerequisite>, Vec<update::Change>), String> {
+    let mut prerequisites = Vec::new();
+    for text in if_exists {
+        prerequisites.push(update::Prerequisite::exists(text, zone)?);
+    }
+    for text in if_absent {
+        prerequisites.push(update::Prerequisite::absent(text, zone)?);
+    }
+    let mut changes = Vec::new();
+    for text in delete {
+        changes.push(update::Change::delete_rrset(text, zone)?);
     }
-    let ip_table = msg.unwrap().get_ip_table();
-    print_info(url, &ip_table);
-}
\ No newline at end of file
+    for text in delete_record {
+        changes.push(update::Change::delete_record(text, zone)?);
+    }
+    for text in add {
+        changes.push(update::Change::add(text, zone)?);
+    }
+    Ok((prerequisites, changes))
+}
+
+/// returns whether the update was made, so that scripts can tell from the exit status
+fn run_update_command(zone: &DnsName, server: &str, prerequisites: &[update::Prerequisite], changes: &[update::Change], key: Option<&tsig::Key>, limiter: &RateLimiter) -> bool {
+    if changes.is_empty() {
+        eprintln!("error: nothing to change, please give --add, --delete or --delete-record");
+        return false;
+    }
+    match update::update(server, zone, prerequisites, changes, key, limiter) {
+        Ok(0) => {
+            println!("{zone}. updated: {}", dns_header::rcode_name(0));
+            true
+        },
+        Ok(rcode) => {
+            println!("{zone}. not updated: {}", dns_header::rcode_name(rcode));
+            false
+        },
+        Err(e) => {
+            eprintln!("error: {e}");
+            false
+        }
+    }
+}
+
+fn rdap_client() -> Result<rdap::Rdap, String> {
+    rdap::bootstrap::Bootstrap::load_or_fetch(rdap::bootstrap_dir().as_deref(), &reqwest::blocking::Client::new()).map(rdap::Rdap::new)
+}
+
+fn run_rdap_command(action: RdapAction, limiter: &RateLimiter) -> bool {
+    match action {
+        RdapAction::Update => {
+            let Some(dir) = rdap::bootstrap_dir() else {
+                eprintln!("error: can't tell where the cache is, neither XDG_CACHE_HOME nor HOME is set");
+                return false;
+            };
+            match rdap::bootstrap::update(&dir, &reqwest::blocking::Client::new()) {
+                Ok(()) => println!("updated the RDAP bootstrap registries in {}", dir.display()),
+                Err(e) => {
+                    eprintln!("error: {e}");
+                    return false;
+                },
+            }
+        },
+        RdapAction::Lookup { query } => {
+            let result = rdap::Query::parse(&query)
+                .and_then(|query| rdap_client()?.lookup(&query, limiter));
+            match result {
+                Ok(info) => println!("{}\n{info}", query.bold()),
+                Err(e) => {
+                    eprintln!("error: {e}");
+                    return false;
+                },
+            }
+        },
+    }
+    true
+}
+
+fn read_input(path: &Path) -> Result<Vec<Target>, io::Error> {
+    if path.as_os_str() == "-" {
+        targets::read_targets(io::stdin().lock())
+    } else {
+        targets::read_targets(BufReader::new(File::open(path)?))
+    }
+}
@@ -0,0 +1,63 @@
+use std::io::IsTerminal;
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorChoice {
+    /// color when stdout is a terminal, following the NO_COLOR and CLICOLOR_FORCE conventions
+    Auto,
+    Always,
+    Never,
+}
+
+/// decides whether to color output.
+/// an explicit `always`/`never` wins; otherwise a non-zero CLICOLOR_FORCE forces color,
+/// a non-empty NO_COLOR turns it off, and failing both we color only when writing to a terminal.
+fn should_colorize(choice: ColorChoice, no_color: Option<&str>, clicolor_force: Option<&str>, is_tty: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            if clicolor_force.is_some_and(|value| !value.is_empty() && value != "0") {
+                return true;
+            }
+            if no_color.is_some_and(|value| !value.is_empty()) {
+                return false;
+            }
+            is_tty
+        },
+    }
+}
+
+/// applies the color policy to everything printed through `colored`, for the rest of the run
+pub fn apply(choice: ColorChoice) {
+    let no_color = std::env::var("NO_COLOR").ok();
+    let clicolor_force = std::env::var("CLICOLOR_FORCE").ok();
+    let enabled = should_colorize(choice, no_color.as_deref(), clicolor_force.as_deref(), std::io::stdout().is_terminal());
+    colored::control::set_override(enabled);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explicit_choice_wins() {
+        assert!(should_colorize(ColorChoice::Always, Some("1"), None, false));
+        assert!(!should_colorize(ColorChoice::Never, None, Some("1"), true));
+    }
+
+    #[test]
+    fn auto_follows_tty() {
+        assert!(should_colorize(ColorChoice::Auto, None, None, true));
+        assert!(!should_colorize(ColorChoice::Auto, None, None, false));
+    }
+
+    #[test]
+    fn auto_honors_environment() {
+        assert!(!should_colorize(ColorChoice::Auto, Some("1"), None, true));
+        // an empty NO_COLOR doesn't count
+        assert!(should_colorize(ColorChoice::Auto, Some(""), None, true));
+        assert!(should_colorize(ColorChoice::Auto, None, Some("1"), false));
+        assert!(!should_colorize(ColorChoice::Auto, None, Some("0"), false));
+        assert!(should_colorize(ColorChoice::Auto, Some("1"), Some("1"), false));
+    }
+}
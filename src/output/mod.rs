@@ -0,0 +1,90 @@
+use crate::lookup::LookupResult;
+
+pub use color::ColorChoice;
+pub use table::Column;
+
+pub mod color;
+mod table;
+mod terminal;
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// human readable, colored text
+    Terminal,
+    /// a single JSON array, printed once every lookup has finished
+    Json,
+    /// one JSON object per line, printed as soon as each lookup finishes
+    Ndjson,
+    /// comma separated values, one row per address
+    Csv,
+    /// tab separated values, one row per address
+    Tsv,
+}
+
+/// Collects results and writes them to stdout in the chosen format.
+pub struct Printer {
+    format: Format,
+    columns: Vec<Column>,
+    collected: Vec<LookupResult>,
+}
+impl Printer {
+    /// `columns` only matters for the table formats, whose header row is written straight away
+    pub fn new(format: Format, columns: Vec<Column>) -> Self {
+        let printer = Self {
+            format,
+            columns,
+            collected: Vec::new(),
+        };
+        if let Some(separator) = printer.separator() {
+            println!("{}", table::header(&printer.columns, separator));
+        }
+        printer
+    }
+
+    fn separator(&self) -> Option<char> {
+        match self.format {
+            Format::Csv => Some(','),
+            Format::Tsv => Some('\t'),
+            _ => None,
+        }
+    }
+
+    pub fn print(&mut self, result: LookupResult) {
+        match self.format {
+            Format::Terminal => {
+                let text = terminal::render(&result);
+                if !text.is_empty() {
+                    println!("{text}");
+                }
+            },
+            Format::Json => self.collected.push(result),
+            Format::Ndjson => match serde_json::to_string(&result) {
+                Ok(line) => println!("{line}"),
+                Err(e) => eprintln!("error serializing result for {}: {e}", result.target),
+            },
+            Format::Csv | Format::Tsv => {
+                // errors don't fit in the table, so they go to stderr
+                if let Some(e) = &result.error {
+                    eprintln!("error looking up {}: {e}", result.target);
+                }
+                for e in result.addresses.iter().filter_map(|address| address.location_error.as_ref()) {
+                    eprintln!("{e}");
+                }
+                let separator = self.separator().unwrap_or(',');
+                for row in table::rows(&result, &self.columns, separator) {
+                    println!("{row}");
+                }
+            },
+        }
+    }
+
+    /// flushes anything that can only be written once all results are in
+    pub fn finish(self) {
+        if self.format == Format::Json {
+            match serde_json::to_string_pretty(&self.collected) {
+                Ok(text) => println!("{text}"),
+                Err(e) => eprintln!("error serializing results: {e}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use crate::lookup::{AddressInfo, LookupResult};
+
+/// A column of the flattened CSV/TSV output.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Column {
+    Hostname,
+    Chain,
+    Ip,
+    Ttl,
+    Continent,
+    Country,
+    Region,
+    City,
+    Org,
+    Isp,
+    /// the origin AS number, with `--asn`
+    Asn,
+    /// the announced prefix covering the address, with `--asn`
+    Prefix,
+    /// what a special-purpose address is set aside for
+    Special,
+}
+impl Column {
+    pub const ALL: [Self; 13] = [
+        Self::Hostname,
+        Self::Chain,
+        Self::Ip,
+        Self::Ttl,
+        Self::Continent,
+        Self::Country,
+        Self::Region,
+        Self::City,
+        Self::Org,
+        Self::Isp,
+        Self::Asn,
+        Self::Prefix,
+        Self::Special,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Hostname => "hostname",
+            Self::Chain => "chain",
+            Self::Ip => "ip",
+            Self::Ttl => "ttl",
+            Self::Continent => "continent",
+            Self::Country => "country",
+            Self::Region => "region",
+            Self::City => "city",
+            Self::Org => "org",
+            Self::Isp => "isp",
+            Self::Asn => "asn",
+            Self::Prefix => "prefix",
+            Self::Special => "special",
+        }
+    }
+
+    fn value(self, result: &LookupResult, address: Option<&AddressInfo>) -> String {
+        let location = address.and_then(|address| address.location.as_ref());
+        let origin = address.and_then(|address| address.origin.as_ref());
+        match self {
+            Self::Hostname => result.target.clone(),
+            Self::Chain => {
+                let mut names: Vec<String> = result.chain.iter().map(|link| link.alias.to_string()).collect();
+                if let Some(last) = result.chain.last() {
+                    names.push(last.canonical.to_string());
+                }
+                names.join(" > ")
+            },
+            Self::Ip => address.map(|address| address.ip.to_string()).unwrap_or_default(),
+            Self::Ttl => address.and_then(|address| address.ttl).map(|ttl| ttl.to_string()).unwrap_or_default(),
+            Self::Continent => location.map(|l| l.continent.clone()).unwrap_or_default(),
+            Self::Country => location.map(|l| l.country.clone()).unwrap_or_default(),
+            Self::Region => location.map(|l| l.region_name.clone()).unwrap_or_default(),
+            Self::City => location.map(|l| l.city.clone()).unwrap_or_default(),
+            Self::Org => location.map(|l| l.org.clone()).unwrap_or_default(),
+            Self::Isp => location.map(|l| l.isp.clone()).unwrap_or_default(),
+            Self::Asn => origin.map(|origin| format!("AS{}", origin.asn)).unwrap_or_default(),
+            Self::Prefix => origin.map(|origin| origin.prefix.clone()).unwrap_or_default(),
+            Self::Special => address.and_then(|address| address.special.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+/// quotes a CSV field when needed; TSV has no quoting, so tabs and line breaks are replaced with spaces.
+/// fields that a spreadsheet would take for a formula get a leading `'`, as most of them come from third parties.
+fn escape(field: &str, separator: char) -> String {
+    let quoted;
+    let field = if field.starts_with(['=', '+', '-', '@']) {
+        quoted = format!("'{field}");
+        &quoted
+    } else {
+        field
+    };
+    if separator == '\t' {
+        return field.replace(['\t', '\n', '\r'], " ");
+    }
+    if field.contains([separator, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn join(fields: impl Iterator<Item = String>, separator: char) -> String {
+    fields.map(|field| escape(&field, separator)).collect::<Vec<String>>().join(&separator.to_string())
+}
+
+pub fn header(columns: &[Column], separator: char) -> String {
+    join(columns.iter().map(|column| column.name().to_string()), separator)
+}
+
+/// flattens a result into one row per address. a target without any addresses still gets a row,
+/// so that it doesn't silently go missing from the spreadsheet.
+pub fn rows(result: &LookupResult, columns: &[Column], separator: char) -> Vec<String> {
+    let row = |address: Option<&AddressInfo>| join(columns.iter().map(|column| column.value(result, address)), separator);
+    if result.addresses.is_empty() {
+        return vec![row(None)];
+    }
+    result.addresses.iter().map(|address| row(Some(address))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns_components::{dns_name::DnsName, dns_question::DnsQuestion};
+    use crate::ip_locator::LocationData;
+    use crate::lookup::CnameLink;
+
+    mod util {
+        use super::*;
+
+        pub fn location() -> LocationData {
+            LocationData {
+                continent: "Europe".to_string(),
+                country: "United Kingdom".to_string(),
+                country_code: "GB".to_string(),
+                region_name: "England".to_string(),
+                city: "London".to_string(),
+                isp: "Edgecast Inc.".to_string(),
+                org: "Edgecast, \"EU\"".to_string(),
+                ..Default::default()
+            }
+        }
+
+        pub fn result() -> LookupResult {
+            LookupResult {
+                target: "www.example.com".to_string(),
+                question: Some(DnsQuestion::query("www.example.com")),
+                chain: vec![CnameLink {
+                    alias: DnsName::from_string("www.example.com"),
+                    canonical: DnsName::from_string("example.com"),
+                    ttl: 300,
+                }],
+                addresses: vec![AddressInfo {
+                    name: Some(DnsName::from_string("example.com")),
+                    ip: "93.184.215.14".parse().unwrap(),
+                    ttl: Some(3003),
+                    location: Some(location()),
+                    location_error: None,
+                    origin: None,
+                    whois: None,
+                    rdap: None,
+                    special: None,
+                }],
+                whois: None,
+                rdap: None,
+                error: None,
+            }
+        }
+    }
+
+    #[test]
+    fn csv_all_columns() {
+        let rows = rows(&util::result(), &Column::ALL, ',');
+        assert_eq!(header(&Column::ALL, ','), "hostname,chain,ip,ttl,continent,country,region,city,org,isp,asn,prefix,special");
+        assert_eq!(rows, vec![
+            "www.example.com,www.example.com > example.com,93.184.215.14,3003,Europe,United Kingdom,England,London,\"Edgecast, \"\"EU\"\"\",Edgecast Inc.,,,".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn tsv_selected_columns() {
+        let columns = [Column::Ip, Column::City];
+        assert_eq!(header(&columns, '\t'), "ip\tcity");
+        assert_eq!(rows(&util::result(), &columns, '\t'), vec!["93.184.215.14\tLondon".to_string()]);
+    }
+
+    #[test]
+    fn special_address() {
+        let mut result = util::result();
+        result.addresses[0].location = None;
+        result.addresses[0].special = Some("private (RFC1918)".to_string());
+        assert_eq!(rows(&result, &[Column::City, Column::Special], ','), vec![",private (RFC1918)".to_string()]);
+    }
+
+    #[test]
+    fn formulas_neutralized() {
+        let mut result = util::result();
+        let location = result.addresses[0].location.as_mut().unwrap();
+        location.org = "=HYPERLINK(\"http://evil\")".to_string();
+        location.isp = "@SUM(A1)".to_string();
+        location.city = "-1+2".to_string();
+        let columns = [Column::City, Column::Org, Column::Isp];
+        assert_eq!(rows(&result, &columns, ','), vec!["'-1+2,\"'=HYPERLINK(\"\"http://evil\"\")\",'@SUM(A1)".to_string()]);
+        assert_eq!(rows(&result, &columns, '\t'), vec!["'-1+2\t'=HYPERLINK(\"http://evil\")\t'@SUM(A1)".to_string()]);
+    }
+
+    #[test]
+    fn row_without_addresses() {
+        let mut result = util::result();
+        result.addresses.clear();
+        assert_eq!(rows(&result, &[Column::Hostname, Column::Ip], ','), vec!["www.example.com,".to_string()]);
+    }
+}
@@ -0,0 +1,70 @@
+use colored::Colorize;
+
+use crate::lookup::{AddressInfo, LookupResult};
+use crate::whois;
+
+fn address_info(address: &AddressInfo) -> String {
+    let location = match (&address.location, &address.location_error, &address.special) {
+        (Some(location), _, _) => location.to_string(),
+        (None, Some(e), _) => format!("\t{}", e.red()).italic().to_string(),
+        (None, None, Some(special)) => format!("\t{}", special.yellow()),
+        (None, None, None) => String::new(),
+    };
+    let location = match &address.origin {
+        Some(origin) if location.is_empty() => format!("\torigin: {}", origin.to_string().bold()),
+        Some(origin) => format!("{location}\n\torigin: {}", origin.to_string().bold()),
+        None => location,
+    };
+    let location = match &address.whois {
+        Some(whois) if location.is_empty() => whois.to_string(),
+        Some(whois) => format!("{location}\n{whois}"),
+        None => location,
+    };
+    let location = match &address.rdap {
+        Some(rdap) if location.is_empty() => rdap.to_string(),
+        Some(rdap) => format!("{location}\n{rdap}"),
+        None => location,
+    };
+    match &address.name {
+        Some(name) => format!(
+            "found IP address for {}: {}\n{location}",
+            name.to_string().bold(),
+            address.ip.to_string().cyan().bold(),
+        ),
+        None => location,
+    }
+}
+
+/// renders a result the way it's shown in a terminal.
+/// a failed lookup is reported on stderr, so the returned text may be empty.
+pub fn render(result: &LookupResult) -> String {
+    if let Some(e) = &result.error {
+        eprintln!("error: {e}");
+        return String::new();
+    }
+
+    let mut lines = Vec::new();
+    if result.question.is_none() {
+        // the target was an address or network, so it's its own heading
+        let heading = match result.addresses.first() {
+            Some(address) if address.ip.to_string() != result.target => {
+                format!("{} (network address {})", result.target.bold(), address.ip)
+            },
+            _ => result.target.bold().to_string(),
+        };
+        lines.push(heading);
+    }
+    for link in &result.chain {
+        lines.push(format!("{} is an alias for {}", link.alias.to_string().bold(), link.canonical.to_string().bold()));
+    }
+    for address in &result.addresses {
+        lines.push(address_info(address));
+    }
+    if let Some(whois) = &result.whois {
+        lines.push(format!("registration of {}:\n{whois}", whois::registered_domain(&result.target).bold()));
+    }
+    if let Some(rdap) = &result.rdap {
+        lines.push(format!("registration of {}:\n{rdap}", whois::registered_domain(&result.target).bold()));
+    }
+    lines.join("\n")
+}
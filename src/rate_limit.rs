@@ -0,0 +1,130 @@
+use std::{collections::HashMap, sync::Mutex, thread::sleep, time::{Duration, Instant}};
+
+/// A token bucket per server: each server allows a burst of up to `burst` requests, refilled at the
+/// configured number of requests per minute, no matter how many threads share it.
+/// Requests beyond the burst queue up behind each other rather than failing.
+///
+/// Independently of the configured rate, a server can be paused when it says its quota is used up.
+pub struct RateLimiter {
+    /// tokens added per second, `None` when unlimited
+    rate: Option<f64>,
+    burst: f64,
+    servers: Mutex<HashMap<String, Bucket>>,
+}
+
+/// the longest a server can be paused for, whatever it asks for
+pub const MAX_PAUSE: Duration = Duration::from_secs(24 * 3600);
+
+struct Bucket {
+    /// may go negative: every request waiting for a token holds one in advance
+    tokens: f64,
+    updated: Instant,
+    paused_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: Option<u32>, burst: u32) -> Self {
+        let rate = per_minute
+            .filter(|n| *n > 0)
+            .map(|n| f64::from(n) / 60.0);
+        Self {
+            rate,
+            burst: f64::from(burst.max(1)),
+            servers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn buckets(&self) -> std::sync::MutexGuard<'_, HashMap<String, Bucket>> {
+        self.servers.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// takes the next token for `server`, returning how long the caller has to wait before using it
+    fn reserve(&self, server: &str) -> Duration {
+        let now = Instant::now();
+        let mut buckets = self.buckets();
+        let bucket = buckets.entry(server.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+            paused_until: None,
+        });
+        let paused = bucket.paused_until.map_or(Duration::ZERO, |until| until.saturating_duration_since(now));
+
+        let Some(rate) = self.rate else {
+            return paused;
+        };
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst) - 1.0;
+        bucket.updated = now;
+        let queued = if bucket.tokens >= 0.0 { Duration::ZERO } else { Duration::from_secs_f64(-bucket.tokens / rate) };
+        queued.max(paused)
+    }
+
+    /// blocks until a request to `server` is allowed
+    pub fn wait(&self, server: &str) {
+        let delay = self.reserve(server);
+        if !delay.is_zero() {
+            sleep(delay);
+        }
+    }
+
+    /// holds back every request to `server` for `duration` (at most [`MAX_PAUSE`]), e.g. because it said its quota is used up
+    pub fn pause(&self, server: &str, duration: Duration) {
+        let now = Instant::now();
+        let until = now.checked_add(duration.min(MAX_PAUSE)).unwrap_or(now);
+        let mut buckets = self.buckets();
+        let bucket = buckets.entry(server.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+            paused_until: None,
+        });
+        bucket.paused_until = Some(bucket.paused_until.map_or(until, |paused| paused.max(until)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_never_waits() {
+        let limiter = RateLimiter::new(None, 1);
+        for _ in 0..10 {
+            assert_eq!(limiter.reserve("8.8.8.8:53"), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn slots_are_spaced_per_server() {
+        let limiter = RateLimiter::new(Some(60), 1); // one per second
+        assert_eq!(limiter.reserve("a"), Duration::ZERO);
+        assert!(limiter.reserve("a") > Duration::from_millis(900));
+        // a different server has its own schedule
+        assert_eq!(limiter.reserve("b"), Duration::ZERO);
+    }
+
+    #[test]
+    fn burst_then_queue() {
+        let limiter = RateLimiter::new(Some(60), 3);
+        for _ in 0..3 {
+            assert_eq!(limiter.reserve("a"), Duration::ZERO);
+        }
+        let fourth = limiter.reserve("a");
+        assert!(fourth > Duration::from_millis(900) && fourth <= Duration::from_secs(1));
+        let fifth = limiter.reserve("a");
+        assert!(fifth > Duration::from_millis(1900) && fifth <= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn pause_holds_back_even_when_unlimited() {
+        let limiter = RateLimiter::new(None, 1);
+        limiter.pause("a", Duration::from_secs(30));
+        assert!(limiter.reserve("a") > Duration::from_secs(29));
+        assert_eq!(limiter.reserve("b"), Duration::ZERO);
+        // a shorter pause doesn't cut a longer one short
+        limiter.pause("a", Duration::from_secs(1));
+        assert!(limiter.reserve("a") > Duration::from_secs(29));
+        // however long a server asks for, it's held back for a day at most
+        limiter.pause("c", Duration::MAX);
+        assert!(limiter.reserve("c") <= MAX_PAUSE);
+    }
+}
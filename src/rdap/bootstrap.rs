@@ -0,0 +1,218 @@
+//! IANA's RDAP bootstrap registries (RFC 9224), which say which server to ask about which addresses,
+//! AS numbers and TLDs. Copies are bundled with inloc, the TLD one only as a partial seed. The current ones are
+//! fetched into the cache directory the first time RDAP is used, and again by `inloc rdap update`; once there,
+//! they take precedence over the bundled ones.
+
+use std::{fs, net::IpAddr, path::Path};
+
+use reqwest::blocking::Client;
+
+const IANA_URL: &str = "https://data.iana.org/rdap";
+
+/// the registries, with the copies bundled with inloc
+const REGISTRIES: [(&str, &str); 4] = [
+    ("ipv4", include_str!("bootstrap/ipv4.json")),
+    ("ipv6", include_str!("bootstrap/ipv6.json")),
+    ("asn", include_str!("bootstrap/asn.json")),
+    ("dns", include_str!("bootstrap/dns.json")),
+];
+
+/// A registry file: a list of services, each a list of entries (prefixes, AS ranges or TLDs) and the URLs serving them.
+#[derive(serde::Deserialize)]
+struct Registry {
+    services: Vec<(Vec<String>, Vec<String>)>,
+}
+impl Registry {
+    fn parse(text: &str) -> Result<Self, String> {
+        serde_json::from_str(text).map_err(|e| e.to_string())
+    }
+
+    /// every entry with the base URL to use for it, preferring HTTPS when a service has several
+    fn entries(self) -> impl Iterator<Item = (String, String)> {
+        self.services.into_iter().filter_map(|(entries, urls)| {
+            let url = urls.iter().find(|url| url.starts_with("https://")).or_else(|| urls.first())?.clone();
+            Some(entries.into_iter().map(move |entry| (entry, url.clone())))
+        }).flatten()
+    }
+}
+
+#[derive(Default)]
+pub struct Bootstrap {
+    networks: Vec<(IpAddr, u8, String)>,
+    asns: Vec<(u32, u32, String)>,
+    domains: Vec<(String, String)>,
+}
+impl Bootstrap {
+    /// the registries in `dir` where there are any, the bundled ones otherwise
+    pub fn load(dir: Option<&Path>) -> Result<Self, String> {
+        let mut bootstrap = Self::default();
+        for (name, bundled) in REGISTRIES {
+            let updated = dir.and_then(|dir| fs::read_to_string(dir.join(format!("{name}.json"))).ok());
+            let registry = match updated.as_deref().map(Registry::parse) {
+                Some(Ok(registry)) => registry,
+                Some(Err(e)) => {
+                    eprintln!("ignoring the downloaded RDAP {name} registry: {e}");
+                    Registry::parse(bundled)?
+                },
+                None => Registry::parse(bundled)?,
+            };
+            bootstrap.add(name, registry)?;
+        }
+        Ok(bootstrap)
+    }
+
+    /// like [`Bootstrap::load`], first fetching the registries into `dir` if they were never downloaded.
+    /// when that fails, the bundled ones are used.
+    pub fn load_or_fetch(dir: Option<&Path>, client: &Client) -> Result<Self, String> {
+        if let Some(dir) = dir {
+            fetch_missing(IANA_URL, dir, client);
+        }
+        Self::load(dir)
+    }
+
+    fn add(&mut self, name: &str, registry: Registry) -> Result<(), String> {
+        for (entry, url) in registry.entries() {
+            let invalid = || format!("invalid entry in the RDAP {name} registry: {entry}");
+            match name {
+                "ipv4" | "ipv6" => {
+                    let (network, prefix) = entry.split_once('/').ok_or_else(invalid)?;
+                    let network = network.parse().map_err(|_| invalid())?;
+                    let prefix = prefix.parse().map_err(|_| invalid())?;
+                    self.networks.push((network, prefix, url));
+                },
+                "asn" => {
+                    let (first, last) = entry.split_once('-').unwrap_or((&entry, &entry));
+                    let first = first.parse().map_err(|_| invalid())?;
+                    let last = last.parse().map_err(|_| invalid())?;
+                    self.asns.push((first, last, url));
+                },
+                _ => self.domains.push((entry.to_ascii_lowercase(), url)),
+            }
+        }
+        Ok(())
+    }
+
+    /// adds a single entry served by `url`
+    #[cfg(test)]
+    pub fn add_service(&mut self, name: &str, entry: &str, url: &str) {
+        let registry = Registry { services: vec![(vec![entry.to_string()], vec![url.to_string()])] };
+        self.add(name, registry).unwrap();
+    }
+
+    /// the server for the most specific network containing `ip`
+    pub fn server_for_ip(&self, ip: IpAddr) -> Option<&str> {
+        self.networks.iter()
+            .filter(|(network, prefix, _)| contains(*network, *prefix, ip))
+            .max_by_key(|(_, prefix, _)| *prefix)
+            .map(|(_, _, url)| url.as_str())
+    }
+
+    pub fn server_for_asn(&self, asn: u32) -> Option<&str> {
+        self.asns.iter()
+            .find(|(first, last, _)| (*first..=*last).contains(&asn))
+            .map(|(_, _, url)| url.as_str())
+    }
+
+    /// the server for the longest suffix of `domain` in the registry, usually its TLD
+    pub fn server_for_domain(&self, domain: &str) -> Option<&str> {
+        let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+        self.domains.iter()
+            .filter(|(suffix, _)| domain == *suffix || domain.ends_with(&format!(".{suffix}")))
+            .max_by_key(|(suffix, _)| suffix.len())
+            .map(|(_, url)| url.as_str())
+    }
+}
+
+fn contains(network: IpAddr, prefix: u8, ip: IpAddr) -> bool {
+    let (network, ip, bits) = match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => (u128::from(u32::from(network)), u128::from(u32::from(ip)), 32_u32),
+        (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+        _ => return false,
+    };
+    let shift = bits.saturating_sub(u32::from(prefix));
+    network.checked_shr(shift).unwrap_or(0) == ip.checked_shr(shift).unwrap_or(0)
+}
+
+/// downloads the current registries from IANA into `dir`
+pub fn update(dir: &Path, client: &Client) -> Result<(), String> {
+    update_from(IANA_URL, dir, client)
+}
+
+/// downloads the registries from `base_url` into `dir`, unless every one of them is there already
+fn fetch_missing(base_url: &str, dir: &Path, client: &Client) {
+    if REGISTRIES.iter().all(|(name, _)| dir.join(format!("{name}.json")).exists()) {
+        return;
+    }
+    eprintln!("fetching the RDAP bootstrap registries into {}", dir.display());
+    if let Err(e) = update_from(base_url, dir, client) {
+        eprintln!("error: {e}; using the bundled registries");
+    }
+}
+
+fn update_from(base_url: &str, dir: &Path, client: &Client) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("can't create {}: {e}", dir.display()))?;
+    for (name, _) in REGISTRIES {
+        let url = format!("{base_url}/{name}.json");
+        let text = client.get(&url).send()
+            .and_then(reqwest::blocking::Response::error_for_status)
+            .and_then(reqwest::blocking::Response::text)
+            .map_err(|e| format!("can't download {url}: {e}"))?;
+        // don't replace a working registry with something we can't read
+        Registry::parse(&text).map_err(|e| format!("{url}: {e}"))?;
+        let path = dir.join(format!("{name}.json"));
+        fs::write(&path, text).map_err(|e| format!("can't write {}: {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_registries() {
+        let bootstrap = Bootstrap::load(None).unwrap();
+        assert_eq!(bootstrap.server_for_ip("1.1.1.1".parse().unwrap()), Some("https://rdap.apnic.net/"));
+        assert_eq!(bootstrap.server_for_ip("93.184.215.14".parse().unwrap()), Some("https://rdap.db.ripe.net/"));
+        assert_eq!(bootstrap.server_for_ip("2a00:1450::1".parse().unwrap()), Some("https://rdap.db.ripe.net/"));
+        assert_eq!(bootstrap.server_for_asn(15133), Some("https://rdap.arin.net/registry/"));
+        assert_eq!(bootstrap.server_for_domain("www.example.com"), Some("https://rdap.verisign.com/com/v1/"));
+        assert_eq!(bootstrap.server_for_domain("example.invalid"), None);
+    }
+
+    #[test]
+    fn fetch_registries_once() {
+        use crate::test_http::{self, Response};
+
+        let dir = std::env::temp_dir().join(format!("inloc-test-{}-rdap", std::process::id()));
+        let empty = r#"{"services": []}"#;
+        let dns = r#"{"services": [[["de"], ["https://rdap.denic.de/"]]]}"#;
+        let (base_url, server) = test_http::serve(vec![Response::json(empty), Response::json(empty), Response::json(empty), Response::json(dns)]);
+        fetch_missing(&base_url, &dir, &Client::new());
+        let requests = server.join().unwrap();
+        // everything is there now, so nothing is asked for (and the stopped server isn't reached)
+        fetch_missing(&base_url, &dir, &Client::new());
+        let bootstrap = Bootstrap::load(Some(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(requests.iter().map(|request| request.request_line.as_str()).collect::<Vec<_>>(), [
+            "GET /ipv4.json HTTP/1.1", "GET /ipv6.json HTTP/1.1", "GET /asn.json HTTP/1.1", "GET /dns.json HTTP/1.1",
+        ]);
+        assert_eq!(bootstrap.unwrap().server_for_domain("www.example.de"), Some("https://rdap.denic.de/"));
+    }
+
+    #[test]
+    fn most_specific_entry_wins() {
+        let mut bootstrap = Bootstrap::default();
+        let ipv6 = r#"{"services": [[["2001::/16"], ["http://wide.example/"]], [["2001:db8::/32"], ["http://narrow.example/", "https://narrow.example/"]]]}"#;
+        bootstrap.add("ipv6", Registry::parse(ipv6).unwrap()).unwrap();
+        let dns = r#"{"services": [[["uk"], ["https://uk.example/"]], [["co.uk"], ["https://co-uk.example/"]]]}"#;
+        bootstrap.add("dns", Registry::parse(dns).unwrap()).unwrap();
+
+        assert_eq!(bootstrap.server_for_ip("2001:db8::1".parse().unwrap()), Some("https://narrow.example/"));
+        assert_eq!(bootstrap.server_for_ip("2001:db9::1".parse().unwrap()), Some("http://wide.example/"));
+        assert_eq!(bootstrap.server_for_ip("192.0.2.1".parse().unwrap()), None);
+        assert_eq!(bootstrap.server_for_domain("example.co.uk"), Some("https://co-uk.example/"));
+        assert_eq!(bootstrap.server_for_domain("example.uk"), Some("https://uk.example/"));
+    }
+}
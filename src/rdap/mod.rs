@@ -0,0 +1,271 @@
+//! An RDAP client (RFC 9082/9083): the structured, JSON successor of WHOIS. Which server to ask
+//! comes from IANA's bootstrap registries, see [`bootstrap`].
+
+use std::{net::IpAddr, path::PathBuf};
+
+use colored::Colorize;
+use reqwest::{blocking::Client, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::rate_limit::RateLimiter;
+
+pub mod bootstrap;
+
+use bootstrap::Bootstrap;
+
+/// Something RDAP can be asked about.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Query {
+    Ip(IpAddr),
+    Asn(u32),
+    Domain(String),
+}
+impl std::fmt::Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ip(ip) => write!(f, "{ip}"),
+            Self::Asn(asn) => write!(f, "AS{asn}"),
+            Self::Domain(domain) => write!(f, "{domain}"),
+        }
+    }
+}
+impl Query {
+    /// `AS15133` (or `as15133`), an address, or anything else as a domain
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim().trim_end_matches('.');
+        if let Some(asn) = text.strip_prefix("AS").or_else(|| text.strip_prefix("as")) {
+            if let Ok(asn) = asn.parse() {
+                return Ok(Self::Asn(asn));
+            }
+        }
+        if let Ok(ip) = text.parse() {
+            return Ok(Self::Ip(ip));
+        }
+        if text.is_empty() || text.contains(['/', ' ', ':']) {
+            return Err(format!("not an address, AS number or domain: {text}"));
+        }
+        Ok(Self::Domain(text.to_ascii_lowercase()))
+    }
+}
+
+/// where `inloc rdap update` puts the downloaded bootstrap registries
+pub fn bootstrap_dir() -> Option<PathBuf> {
+    Some(crate::config::xdg_dir("XDG_CACHE_HOME", ".cache")?.join("rdap"))
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RdapEvent {
+    /// e.g. `registration`, `last changed` or `expiration`
+    pub action: String,
+    pub date: String,
+}
+
+/// The fields we care about from an RDAP answer. Fields the server didn't have stay empty.
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RdapInfo {
+    /// the URL that answered
+    pub url: String,
+    /// the registry's identifier, e.g. `NET-8-8-8-0-2` or `AS15133`
+    pub handle: String,
+    /// the network, AS or domain name
+    pub name: String,
+    /// the network's prefixes, or its range when it has none
+    pub cidr: String,
+    pub country: String,
+    pub abuse_email: String,
+    pub events: Vec<RdapEvent>,
+}
+impl std::fmt::Display for RdapInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut result = vec![format!("rdap: {}", self.url.bold())];
+        for (label, value) in [
+            ("name", &self.name),
+            ("handle", &self.handle),
+            ("network", &self.cidr),
+            ("country", &self.country),
+            ("abuse contact", &self.abuse_email),
+        ] {
+            if !value.is_empty() {
+                result.push(format!("{label}: {}", value.bold()));
+            }
+        }
+        for event in &self.events {
+            result.push(format!("{}: {}", event.action, event.date.bold()));
+        }
+        write!(f, "\t{}", result.join("\n\t"))
+    }
+}
+
+/// the prefixes of a network, from the `cidr0_cidrs` extension every RIR supports
+fn cidrs(answer: &Value) -> String {
+    let Some(cidrs) = answer["cidr0_cidrs"].as_array() else {
+        return String::new();
+    };
+    let cidrs: Vec<String> = cidrs.iter()
+        .filter_map(|cidr| {
+            let prefix = cidr["v4prefix"].as_str().or_else(|| cidr["v6prefix"].as_str())?;
+            Some(format!("{prefix}/{}", cidr["length"].as_u64()?))
+        })
+        .collect();
+    cidrs.join(", ")
+}
+
+/// the email address of the first entity with the `abuse` role, looking into nested entities too
+/// (registrars keep theirs inside their own entity)
+fn abuse_email(entities: &Value) -> Option<String> {
+    entities.as_array()?.iter().find_map(|entity| {
+        let is_abuse = entity["roles"].as_array().is_some_and(|roles| roles.iter().any(|role| role == "abuse"));
+        let email = || {
+            // ["vcard", [["version", {}, "text", "4.0"], ["email", {}, "text", "abuse@example.net"], ...]]
+            entity["vcardArray"][1].as_array()?.iter()
+                .find(|property| property[0] == "email")
+                .and_then(|property| property[3].as_str())
+                .map(str::to_string)
+        };
+        is_abuse.then(email).flatten().or_else(|| abuse_email(&entity["entities"]))
+    })
+}
+
+fn parse(url: String, answer: &Value) -> RdapInfo {
+    let text = |key: &str| answer[key].as_str().unwrap_or_default().to_string();
+    let mut cidr = cidrs(answer);
+    if cidr.is_empty() && answer["startAddress"].is_string() {
+        cidr = format!("{} - {}", text("startAddress"), text("endAddress"));
+    }
+    let name = match text("ldhName") {
+        name if name.is_empty() => text("name"),
+        name => name,
+    };
+    let events = answer["events"].as_array().map(|events| {
+        events.iter()
+            .filter_map(|event| Some(RdapEvent {
+                action: event["eventAction"].as_str()?.to_string(),
+                date: event["eventDate"].as_str()?.to_string(),
+            }))
+            .collect()
+    });
+    RdapInfo {
+        url,
+        handle: text("handle"),
+        name,
+        cidr,
+        country: text("country"),
+        abuse_email: abuse_email(&answer["entities"]).unwrap_or_default(),
+        events: events.unwrap_or_default(),
+    }
+}
+
+pub struct Rdap {
+    bootstrap: Bootstrap,
+    client: Client,
+}
+impl Rdap {
+    pub fn new(bootstrap: Bootstrap) -> Self {
+        Self {
+            bootstrap,
+            client: Client::new(),
+        }
+    }
+
+    pub fn lookup(&self, query: &Query, limiter: &RateLimiter) -> Result<RdapInfo, String> {
+        let (base, path) = match query {
+            Query::Ip(ip) => (self.bootstrap.server_for_ip(*ip), format!("ip/{ip}")),
+            Query::Asn(asn) => (self.bootstrap.server_for_asn(*asn), format!("autnum/{asn}")),
+            Query::Domain(domain) => (self.bootstrap.server_for_domain(domain), format!("domain/{domain}")),
+        };
+        let base = base.ok_or_else(|| format!("no RDAP server is known for {query}; `inloc rdap update` fetches the full registries"))?;
+        let url = format!("{}/{path}", base.trim_end_matches('/'));
+
+        limiter.wait(&crate::ip_locator::host_of(base));
+        let response = self.client.get(&url).header("Accept", "application/rdap+json").send()
+            .map_err(|e| format!("error asking {url}: {e}"))?;
+        match response.status() {
+            StatusCode::NOT_FOUND => return Err(format!("{url} has no record of {query}")),
+            status if !status.is_success() => return Err(format!("{url} answered HTTP {status}")),
+            _ => {},
+        }
+        let answer: Value = serde_json::from_reader(response).map_err(|_| format!("invalid response from {url}"))?;
+        Ok(parse(url, &answer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_http::{self, Response};
+
+    mod util {
+        use super::*;
+
+        /// a client sending everything to `base_url`
+        pub fn client(base_url: &str) -> Rdap {
+            let mut bootstrap = Bootstrap::default();
+            bootstrap.add_service("ipv4", "0.0.0.0/0", base_url);
+            bootstrap.add_service("asn", "1-4199999999", base_url);
+            bootstrap.add_service("dns", "com", base_url);
+            Rdap::new(bootstrap)
+        }
+    }
+
+    #[test]
+    fn ip_network() {
+        let body = r#"{"objectClassName":"ip network","handle":"1.1.1.0 - 1.1.1.255","startAddress":"1.1.1.0","endAddress":"1.1.1.255",
+            "name":"APNIC-LABS","country":"AU","cidr0_cidrs":[{"v4prefix":"1.1.1.0","length":24}],
+            "entities":[{"roles":["registrant"],"handle":"ORG-ARAD1-AP"},{"roles":["abuse"],"vcardArray":["vcard",[["version",{},"text","4.0"],["fn",{},"text","IRT-APNICRANDNET-AU"],["email",{},"text","helpdesk@apnic.net"]]]}],
+            "events":[{"eventAction":"registration","eventDate":"2011-08-10T23:12:35Z"},{"eventAction":"last changed","eventDate":"2023-04-26T22:57:58Z"}]}"#;
+        let (base_url, server) = test_http::serve(vec![Response::json(body)]);
+
+        let info = util::client(&base_url).lookup(&Query::Ip("1.1.1.1".parse().unwrap()), &RateLimiter::new(None, 1)).unwrap();
+        assert_eq!(info, RdapInfo {
+            url: format!("{base_url}/ip/1.1.1.1"),
+            handle: "1.1.1.0 - 1.1.1.255".to_string(),
+            name: "APNIC-LABS".to_string(),
+            cidr: "1.1.1.0/24".to_string(),
+            country: "AU".to_string(),
+            abuse_email: "helpdesk@apnic.net".to_string(),
+            events: vec![
+                RdapEvent { action: "registration".to_string(), date: "2011-08-10T23:12:35Z".to_string() },
+                RdapEvent { action: "last changed".to_string(), date: "2023-04-26T22:57:58Z".to_string() },
+            ],
+        });
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].request_line, "GET /ip/1.1.1.1 HTTP/1.1");
+        assert_eq!(requests[0].header("accept"), Some("application/rdap+json"));
+    }
+
+    #[test]
+    fn domain_with_registrar_abuse_contact() {
+        let body = r#"{"objectClassName":"domain","handle":"2336799_DOMAIN_COM-VRSN","ldhName":"EXAMPLE.COM",
+            "entities":[{"roles":["registrar"],"entities":[{"roles":["abuse"],"vcardArray":["vcard",[["email",{},"text","abuse@registrar.example"]]]}]}],
+            "events":[{"eventAction":"registration","eventDate":"1995-08-14T04:00:00Z"},{"eventAction":"expiration","eventDate":"2025-08-13T04:00:00Z"}]}"#;
+        let (base_url, server) = test_http::serve(vec![Response::json(body)]);
+
+        let info = util::client(&base_url).lookup(&Query::Domain("example.com".to_string()), &RateLimiter::new(None, 1)).unwrap();
+        assert_eq!(info.name, "EXAMPLE.COM");
+        assert_eq!(info.abuse_email, "abuse@registrar.example");
+        assert_eq!(info.events[1], RdapEvent { action: "expiration".to_string(), date: "2025-08-13T04:00:00Z".to_string() });
+        assert_eq!(server.join().unwrap()[0].request_line, "GET /domain/example.com HTTP/1.1");
+    }
+
+    #[test]
+    fn autnum_not_found() {
+        let (base_url, server) = test_http::serve(vec![Response { status: 404, ..Response::json("{}") }]);
+        let res = util::client(&base_url).lookup(&Query::Asn(64512), &RateLimiter::new(None, 1));
+        assert_eq!(res, Err(format!("{base_url}/autnum/64512 has no record of AS64512")));
+        server.join().unwrap();
+
+        let nowhere = Rdap::new(Bootstrap::default()).lookup(&Query::Asn(15133), &RateLimiter::new(None, 1));
+        assert_eq!(nowhere, Err("no RDAP server is known for AS15133; `inloc rdap update` fetches the full registries".to_string()));
+    }
+
+    #[test]
+    fn parse_queries() {
+        assert_eq!(Query::parse("AS15133"), Ok(Query::Asn(15133)));
+        assert_eq!(Query::parse("2001:db8::1"), Ok(Query::Ip("2001:db8::1".parse().unwrap())));
+        assert_eq!(Query::parse("Example.COM."), Ok(Query::Domain("example.com".to_string())));
+        assert!(Query::parse("192.0.2.0/24").is_err());
+    }
+}
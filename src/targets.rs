@@ -0,0 +1,152 @@
+use std::{io::{self, BufRead}, net::IpAddr};
+
+/// Something the user asked us to look up, already normalized.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Target {
+    /// a hostname that still has to be resolved
+    Host(String),
+    /// a single address, ready to be geolocated
+    Ip(IpAddr),
+    /// a network, stored as its (masked) network address and prefix length
+    Cidr(IpAddr, u8),
+}
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Host(host) => write!(f, "{host}"),
+            Self::Ip(addr) => write!(f, "{addr}"),
+            Self::Cidr(addr, prefix) => write!(f, "{addr}/{prefix}"),
+        }
+    }
+}
+
+/// Works out what kind of target `entry` is.
+/// Accepts bare hostnames and addresses, CIDR blocks, `host:port`, `[v6]:port` and full URLs
+/// such as `https://example.com:8443/x`, from which only the host part is kept.
+pub fn parse_target(entry: &str) -> Result<Target, String> {
+    let entry = entry.trim();
+    if entry.is_empty() {
+        return Err("empty target".to_string());
+    }
+
+    if let Some((addr, prefix)) = entry.split_once('/') {
+        if let (Ok(addr), Ok(prefix)) = (addr.parse::<IpAddr>(), prefix.parse::<u8>()) {
+            return cidr(addr, prefix).ok_or_else(|| format!("invalid prefix length in {entry}"));
+        }
+    }
+
+    // drop the scheme, then everything from the path onwards, then any credentials
+    let rest = entry.split_once("://").map_or(entry, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+
+    if let Ok(addr) = host_port.parse::<IpAddr>() {
+        return Ok(Target::Ip(addr));
+    }
+
+    let host = if let Some(bracketed) = host_port.strip_prefix('[') {
+        // [v6] or [v6]:port
+        let Some((inner, _)) = bracketed.split_once(']') else {
+            return Err(format!("unterminated IPv6 literal in {entry}"));
+        };
+        inner
+    } else {
+        match host_port.rsplit_once(':') {
+            Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+            _ => host_port,
+        }
+    };
+
+    if let Ok(addr) = host.parse::<IpAddr>() {
+        return Ok(Target::Ip(addr));
+    }
+
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let valid = !host.is_empty() && host.split('.').all(|label| {
+        !label.is_empty() && label.len() <= 63 && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    });
+    if !valid {
+        return Err(format!("not a valid hostname, address or URL: {entry}"));
+    }
+    Ok(Target::Host(host))
+}
+
+fn cidr(addr: IpAddr, prefix: u8) -> Option<Target> {
+    let network = match addr {
+        IpAddr::V4(v4) if prefix <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::from((u32::from(v4) & mask).to_be_bytes())
+        },
+        IpAddr::V6(v6) if prefix <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::from((u128::from(v6) & mask).to_be_bytes())
+        },
+        _ => return None,
+    };
+    Some(Target::Cidr(network, prefix))
+}
+
+/// Reads one target per line. Blank lines and `#` comments (whole-line or trailing) are skipped;
+/// entries that can't be understood are reported on stderr and skipped as well.
+pub fn read_targets(reader: impl BufRead) -> Result<Vec<Target>, io::Error> {
+    let mut targets = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let entry = line.split('#').next().unwrap_or_default().trim();
+        if entry.is_empty() {
+            continue;
+        }
+        match parse_target(entry) {
+            Ok(target) => targets.push(target),
+            Err(e) => eprintln!("line {}: {e}", number + 1),
+        }
+    }
+    Ok(targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn parse_plain_entries() {
+        assert_eq!(parse_target("Example.COM."), Ok(Target::Host("example.com".to_string())));
+        assert_eq!(parse_target("93.184.215.14"), Ok(Target::Ip(IpAddr::V4(Ipv4Addr::new(93, 184, 215, 14)))));
+        assert_eq!(parse_target("2001:db8::1"), Ok(Target::Ip("2001:db8::1".parse().unwrap())));
+    }
+
+    #[test]
+    fn parse_urls_strip_scheme_path_and_port() {
+        assert_eq!(parse_target("https://example.com:8443/x?y=1"), Ok(Target::Host("example.com".to_string())));
+        assert_eq!(parse_target("http://user:pw@example.com/"), Ok(Target::Host("example.com".to_string())));
+        assert_eq!(parse_target("example.com:80"), Ok(Target::Host("example.com".to_string())));
+        assert_eq!(parse_target("http://[2001:db8::1]:8080/"), Ok(Target::Ip("2001:db8::1".parse().unwrap())));
+        assert_eq!(parse_target("10.1.2.3:22"), Ok(Target::Ip(IpAddr::V4(Ipv4Addr::new(10, 1, 2, 3)))));
+    }
+
+    #[test]
+    fn parse_cidr_masks_host_bits() {
+        assert_eq!(parse_target("192.0.2.77/24"), Ok(Target::Cidr(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 0)), 24)));
+        assert_eq!(parse_target("2001:db8::ff/32"), Ok(Target::Cidr(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0)), 32)));
+        assert_eq!(parse_target("0.0.0.0/0"), Ok(Target::Cidr(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)));
+        assert!(parse_target("192.0.2.0/33").is_err());
+    }
+
+    #[test]
+    fn parse_bad_entries() {
+        assert!(parse_target("").is_err());
+        assert!(parse_target("not a host").is_err());
+        assert!(parse_target("http://[::1").is_err());
+    }
+
+    #[test]
+    fn read_skips_comments_and_blanks() {
+        let input = "# inventory\n\nexample.com  # web\n 1.1.1.1\n;;;\n";
+        let targets = read_targets(input.as_bytes()).unwrap();
+        assert_eq!(targets, vec![
+            Target::Host("example.com".to_string()),
+            Target::Ip(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))),
+        ]);
+    }
+}
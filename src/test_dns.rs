@@ -0,0 +1,57 @@
+//! A tiny local DNS server for tests, answering queries over UDP from canned records.
+
+use std::{io::Cursor, net::UdpSocket, thread::{self, JoinHandle}};
+
+use crate::dns_components::dns_question::DnsQuestion;
+
+/// The records to answer a question with, as `(type, rdata)`, or `None` for NXDOMAIN.
+pub type Answers = Option<Vec<(u16, Vec<u8>)>>;
+
+/// Answers `count` queries using `answer`, then stops.
+/// Returns the server address and a handle yielding the questions that were asked, as `name/type`.
+pub fn serve(count: usize, answer: impl Fn(&DnsQuestion) -> Answers + Send + 'static) -> (String, JoinHandle<Vec<String>>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").expect("failed to bind test DNS server");
+    let address = socket.local_addr().unwrap().to_string();
+
+    let handle = thread::spawn(move || {
+        let mut asked = Vec::new();
+        for _ in 0..count {
+            let mut buffer = [0_u8; 512];
+            let (length, peer) = socket.recv_from(&mut buffer).unwrap();
+            let mut cursor = Cursor::new(&buffer[..length]);
+            cursor.set_position(12);
+            let question = DnsQuestion::parse(&mut cursor).unwrap();
+            asked.push(format!("{}/{}", question.qname, question.qtype));
+
+            let answers = answer(&question);
+            let mut reply = buffer[..2].to_vec(); // same id
+            // a recursive response, NXDOMAIN when there's nothing to answer with
+            reply.extend([0x81, if answers.is_some() { 0x80 } else { 0x83 }]);
+            let answers = answers.unwrap_or_default();
+            reply.extend(1_u16.to_be_bytes());
+            reply.extend(u16::try_from(answers.len()).unwrap().to_be_bytes());
+            reply.extend([0, 0, 0, 0]);
+            reply.extend(question.to_bytes());
+            for (rtype, rdata) in answers {
+                // the owner name points back at the question
+                reply.extend([0xc0, 0x0c]);
+                reply.extend(rtype.to_be_bytes());
+                reply.extend(1_u16.to_be_bytes());
+                reply.extend(300_u32.to_be_bytes());
+                reply.extend(u16::try_from(rdata.len()).unwrap().to_be_bytes());
+                reply.extend(rdata);
+            }
+            socket.send_to(&reply, peer).unwrap();
+        }
+        asked
+    });
+
+    (address, handle)
+}
+
+/// TXT rdata holding `text` as a single character-string
+pub fn txt(text: &str) -> Vec<u8> {
+    let mut rdata = vec![u8::try_from(text.len()).unwrap()];
+    rdata.extend(text.as_bytes());
+    rdata
+}
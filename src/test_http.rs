@@ -0,0 +1,99 @@
+//! A tiny local HTTP server for tests, standing in for the real web APIs.
+
+use std::{io::{BufRead, BufReader, Read, Write}, net::TcpListener, thread::{self, JoinHandle}};
+
+/// A canned response, served once per expected request.
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+impl Response {
+    pub fn json(body: &str) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.to_string(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// A request as the stand-in received it.
+#[derive(Debug)]
+pub struct Request {
+    /// e.g. `GET /json/1.1.1.1?fields=1 HTTP/1.1`
+    pub request_line: String,
+    /// names are lowercased
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Serves `responses` in order, one per connection, then stops.
+/// Returns the base URL (`http://127.0.0.1:port`) and a handle yielding the requests that were received.
+pub fn serve(responses: Vec<Response>) -> (String, JoinHandle<Vec<Request>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind test server");
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = thread::spawn(move || {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (stream, _) = listener.accept().expect("failed to accept test connection");
+            let mut reader = BufReader::new(stream);
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+                }
+            }
+            let length = headers.iter()
+                .find(|(name, _)| name == "content-length")
+                .and_then(|(_, value)| value.parse().ok())
+                .unwrap_or(0);
+            let mut body = vec![0_u8; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = reader.into_inner();
+            let mut reply = format!("HTTP/1.1 {} Stub\r\nContent-Length: {}\r\nConnection: close\r\n", response.status, response.body.len());
+            for (name, value) in &response.headers {
+                reply.push_str(&format!("{name}: {value}\r\n"));
+            }
+            reply.push_str("\r\n");
+            reply.push_str(&response.body);
+            stream.write_all(reply.as_bytes()).unwrap();
+
+            requests.push(Request {
+                request_line: request_line.trim_end().to_string(),
+                headers,
+                body: String::from_utf8_lossy(&body).into_owned(),
+            });
+        }
+        requests
+    });
+
+    (base_url, handle)
+}
+
+/// a base URL nothing listens on, so that every request to it fails to connect
+pub fn unreachable() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind test server");
+    format!("http://{}", listener.local_addr().unwrap())
+}
@@ -0,0 +1,446 @@
+//! Zone transfers over TCP. An AXFR (RFC 5936) hands over a whole zone as a stream of messages,
+//! starting and ending with the zone's SOA record. An IXFR (RFC 1995) hands over only what changed
+//! since a version the client already has, unless the server would rather send the whole zone.
+
+use std::{collections::{HashMap, HashSet}, io};
+
+use crate::dns_client::TcpConnection;
+use crate::dns_components::{dns_header, dns_message::DnsMessage, dns_name::DnsName, dns_rdata::{DnsRdata, Soa}, dns_rr::DnsResourceRecord, tsig::Key};
+use crate::rate_limit::RateLimiter;
+
+pub const AXFR: u16 = 252;
+const SOA: u16 = 6;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn serial(record: &DnsResourceRecord) -> Option<u32> {
+    match &record.rdata {
+        DnsRdata::SoaRecord(soa) => Some(soa.serial),
+        _ => None,
+    }
+}
+
+/// whether serial `a` is newer than `b`, in serial number arithmetic (RFC 1982)
+fn newer(a: u32, b: u32) -> bool {
+    a != b && a.wrapping_sub(b) < 1 << 31
+}
+
+/// what makes a record the same as another, whatever their TTLs
+fn record_key(record: &DnsResourceRecord) -> (DnsName, u16, u16, Vec<u8>) {
+    (record.name.to_lowercase(), record.rtype, record.class, record.rdata.to_bytes())
+}
+
+/// The records of an AXFR response so far. The SOA record closing the transfer isn't kept, as it's
+/// the same as the one opening it.
+#[derive(Default)]
+struct Axfr {
+    records: Vec<DnsResourceRecord>,
+    done: bool,
+}
+impl Axfr {
+    /// adds the next record, returning whether it closed the transfer
+    fn add_record(&mut self, record: &DnsResourceRecord) -> Result<bool, io::Error> {
+        if self.done {
+            return Err(invalid("records after the SOA record closing the transfer"));
+        }
+        if record.rtype == SOA && !self.records.is_empty() {
+            self.done = true;
+        } else if self.records.is_empty() && record.rtype != SOA {
+            return Err(invalid("the transfer doesn't start with an SOA record"));
+        } else {
+            self.records.push(record.clone());
+        }
+        Ok(self.done)
+    }
+
+    /// adds the answers of the next message, returning whether that was the last one
+    fn add(&mut self, msg: &DnsMessage) -> Result<bool, io::Error> {
+        if msg.rcode() != 0 {
+            return Err(dns_header::get_error(msg.rcode()));
+        }
+        for record in msg.answers() {
+            self.add_record(record)?;
+        }
+        Ok(self.done)
+    }
+}
+
+/// The changes from one version of a zone to the next: the records removed, starting with the old
+/// SOA record, and the records added, starting with the new one.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diff {
+    pub removed: Vec<DnsResourceRecord>,
+    pub added: Vec<DnsResourceRecord>,
+}
+impl Diff {
+    /// the changes that turn the records of `old` into those of `new`
+    pub fn between(old: &[DnsResourceRecord], new: &[DnsResourceRecord]) -> Self {
+        let missing_from = |records: &[DnsResourceRecord], others: &[DnsResourceRecord]| -> Vec<DnsResourceRecord> {
+            let others: HashSet<_> = others.iter().map(record_key).collect();
+            records.iter().filter(|record| !others.contains(&record_key(record))).cloned().collect()
+        };
+        Self {
+            removed: missing_from(old, new),
+            added: missing_from(new, old),
+        }
+    }
+
+    pub fn apply(&self, records: &mut Vec<DnsResourceRecord>) {
+        // each removed record takes away one copy of it, the first
+        let mut removed: HashMap<_, usize> = HashMap::new();
+        for record in &self.removed {
+            *removed.entry(record_key(record)).or_default() += 1;
+        }
+        records.retain(|record| match removed.get_mut(&record_key(record)) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            },
+            _ => true,
+        });
+        records.extend(self.added.iter().cloned());
+    }
+}
+impl std::fmt::Display for Diff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let version = |records: &[DnsResourceRecord]| records.iter().find_map(serial).map_or_else(|| "?".to_string(), |serial| serial.to_string());
+        let (from, to) = (version(&self.removed), version(&self.added));
+        write!(f, "; serial {from} -> {to}")?;
+        for record in &self.removed {
+            write!(f, "\n- {record}")?;
+        }
+        for record in &self.added {
+            write!(f, "\n+ {record}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What an IXFR got.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Ixfr {
+    /// the version asked about is the latest
+    UpToDate,
+    /// the changes since the version asked about, oldest first
+    Incremental(Vec<Diff>),
+    /// the server sent the whole zone instead, as AXFR would
+    Full(Vec<DnsResourceRecord>),
+}
+
+/// The records of an IXFR response so far (RFC 1995 section 4). After the SOA record of the newest
+/// version come either the records of a full transfer, or a difference sequence for each version:
+/// the old SOA record, the records removed, the new SOA record and the records added.
+/// The newest SOA record closes the response.
+#[derive(Default)]
+struct IxfrCollector {
+    /// the serial of the version asked about
+    known: Option<u32>,
+    newest: Option<DnsResourceRecord>,
+    full: Option<Axfr>,
+    diffs: Vec<Diff>,
+    /// whether the records are being added to the last diff, rather than removed
+    adding: bool,
+    done: bool,
+}
+impl IxfrCollector {
+    fn add_record(&mut self, record: &DnsResourceRecord) -> Result<(), io::Error> {
+        if self.done {
+            return Err(invalid("records after the SOA record closing the transfer"));
+        }
+        if let Some(full) = &mut self.full {
+            self.done = full.add_record(record)?;
+            return Ok(());
+        }
+        let Some(newest) = &self.newest else {
+            if record.rtype != SOA {
+                return Err(invalid("the transfer doesn't start with an SOA record"));
+            }
+            // when there's nothing newer than the version asked about, its SOA record is all there is (RFC 1995 section 4)
+            if serial(record).zip(self.known).is_some_and(|(newest, known)| !newer(newest, known)) {
+                self.done = true;
+            }
+            self.newest = Some(record.clone());
+            return Ok(());
+        };
+
+        let is_soa = record.rtype == SOA;
+        match self.diffs.last_mut() {
+            // the second record tells a full transfer from an incremental one
+            None if !is_soa => {
+                let mut full = Axfr::default();
+                full.add_record(newest)?;
+                full.add_record(record)?;
+                self.full = Some(full);
+            },
+            Some(_) if is_soa && self.adding && serial(record) == serial(newest) => self.done = true,
+            None => self.start_diff(record),
+            Some(_) if is_soa && self.adding => self.start_diff(record),
+            Some(diff) if is_soa => {
+                diff.added.push(record.clone());
+                self.adding = true;
+            },
+            Some(diff) if self.adding => diff.added.push(record.clone()),
+            Some(diff) => diff.removed.push(record.clone()),
+        }
+        Ok(())
+    }
+
+    fn start_diff(&mut self, old_soa: &DnsResourceRecord) {
+        self.diffs.push(Diff { removed: vec![old_soa.clone()], added: Vec::new() });
+        self.adding = false;
+    }
+
+    /// adds the answers of the next message, returning whether that was the last one
+    fn add(&mut self, msg: &DnsMessage) -> Result<bool, io::Error> {
+        if msg.rcode() != 0 {
+            return Err(dns_header::get_error(msg.rcode()));
+        }
+        for record in msg.answers() {
+            self.add_record(record)?;
+        }
+        Ok(self.done)
+    }
+
+    fn finish(self) -> Ixfr {
+        match self.full {
+            Some(full) => Ixfr::Full(full.records),
+            None if self.diffs.is_empty() => Ixfr::UpToDate,
+            None => Ixfr::Incremental(self.diffs),
+        }
+    }
+}
+
+/// sends `request` to `server` over TCP, signed with `key` if there is one, handing every message
+/// of the response to `add` until it says that was the last
+fn transfer(server: &str, mut request: DnsMessage, key: Option<&Key>, limiter: &RateLimiter, mut add: impl FnMut(&DnsMessage) -> Result<bool, io::Error>) -> Result<(), io::Error> {
+    let mut verifier = key.map(|key| key.sign(&mut request)).transpose()?;
+    limiter.wait(server);
+    let mut connection = TcpConnection::open(server)?;
+    connection.send(&request.to_bytes())?;
+    loop {
+        let data = connection.receive()?;
+        if let Some(verifier) = &mut verifier {
+            verifier.verify(&data)?;
+        }
+        if add(&DnsMessage::parse_any(&data)?)? {
+            break;
+        }
+    }
+    verifier.map_or(Ok(()), |verifier| verifier.finish())
+}
+
+/// every record of `zone`, as `server` sends them, starting with the SOA record
+pub fn axfr(server: &str, zone: &DnsName, key: Option<&Key>, limiter: &RateLimiter) -> Result<Vec<DnsResourceRecord>, io::Error> {
+    let mut collected = Axfr::default();
+    transfer(server, DnsMessage::query(&zone.to_string(), AXFR), key, limiter, |msg| collected.add(msg))?;
+    Ok(collected.records)
+}
+
+/// an SOA record standing for the version of `zone` with `serial`, when there's no copy to take it
+/// from. servers only look at the serial (RFC 1995 section 3).
+pub fn known_soa(zone: &DnsName, serial: u32) -> DnsResourceRecord {
+    let soa = Soa {
+        mname: DnsName { labels: Vec::new() },
+        rname: DnsName { labels: Vec::new() },
+        serial,
+        refresh: 0,
+        retry: 0,
+        expire: 0,
+        minimum: 0,
+    };
+    let rdata_raw = soa.to_bytes();
+    DnsResourceRecord {
+        name: zone.clone(),
+        rtype: SOA,
+        class: 1,
+        ttl: 0,
+        rdlength: u16::try_from(rdata_raw.len()).unwrap_or(u16::MAX),
+        rdata: DnsRdata::SoaRecord(soa),
+        rdata_raw,
+    }
+}
+
+/// the changes to `zone` since the version with the SOA record `known`. servers that don't do IXFR
+/// are asked for the whole zone instead.
+pub fn ixfr(server: &str, zone: &DnsName, known: &DnsResourceRecord, key: Option<&Key>, limiter: &RateLimiter) -> Result<Ixfr, io::Error> {
+    let mut collected = IxfrCollector { known: serial(known), ..Default::default() };
+    let request = DnsMessage::query_ixfr(&zone.to_string(), known.clone());
+    match transfer(server, request, key, limiter, |msg| collected.add(msg)) {
+        Ok(()) => Ok(collected.finish()),
+        // NOTIMP and FORMERR, from servers that don't know IXFR
+        Err(e) if matches!(e.kind(), io::ErrorKind::Unsupported | io::ErrorKind::InvalidInput) => axfr(server, zone, key, limiter).map(Ixfr::Full),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::dns_components::zone_file;
+
+    mod util {
+        use std::{io::{Read, Write}, net::TcpListener, thread::{self, JoinHandle}};
+
+        use super::*;
+
+        pub fn zone(text: &str) -> Vec<DnsResourceRecord> {
+            zone_file::parse(text, &DnsName::from_string("example"), Path::new(".")).unwrap()
+        }
+
+        /// a response with `records` as its answers, and `rcode`
+        pub fn message(rcode: u8, records: &[DnsResourceRecord]) -> Vec<u8> {
+            let mut msg = vec![0xab, 0xba, 0x84, 0x80 | rcode, 0, 0];
+            msg.extend(u16::try_from(records.len()).unwrap().to_be_bytes());
+            msg.extend([0, 0, 0, 0]);
+            for record in records {
+                msg.extend(record.to_bytes());
+            }
+            msg
+        }
+
+        /// a server taking one request over TCP and answering it with `messages`.
+        /// the handle yields the request it got.
+        pub fn serve(messages: Vec<Vec<u8>>) -> (String, JoinHandle<Vec<u8>>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap().to_string();
+            let handle = thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut length = [0_u8; 2];
+                stream.read_exact(&mut length).unwrap();
+                let mut request = vec![0_u8; usize::from(u16::from_be_bytes(length))];
+                stream.read_exact(&mut request).unwrap();
+                for message in messages {
+                    stream.write_all(&u16::try_from(message.len()).unwrap().to_be_bytes()).unwrap();
+                    stream.write_all(&message).unwrap();
+                }
+                request
+            });
+            (address, handle)
+        }
+    }
+
+    const ZONE: &str = "$TTL 300\n@ SOA ns h 7 2 3 4 5\n@ NS ns\nns A 192.0.2.53\nwww AAAA 2001:db8::80\n";
+
+    #[test]
+    fn multi_message_transfer() {
+        let records = util::zone(ZONE);
+        let messages = vec![
+            util::message(0, &records[..2]),
+            util::message(0, &records[2..]),
+            util::message(0, &records[..1]),
+        ];
+        let (server, handle) = util::serve(messages);
+        let transferred = axfr(&server, &DnsName::from_string("example"), None, &RateLimiter::new(None, 1)).unwrap();
+        assert_eq!(transferred, records);
+
+        let request = DnsMessage::parse_any(&handle.join().unwrap()).unwrap();
+        assert_eq!(request, DnsMessage::query("example", AXFR));
+    }
+
+    #[test]
+    fn broken_transfers() {
+        let records = util::zone(ZONE);
+        let add_all = |messages: &[Vec<u8>]| -> Result<bool, io::Error> {
+            let mut transfer = Axfr::default();
+            let mut done = false;
+            for message in messages {
+                done = transfer.add(&DnsMessage::parse_any(message).unwrap())?;
+            }
+            Ok(done)
+        };
+        assert!(add_all(&[util::message(0, &records[1..])]).is_err());
+        assert_eq!(add_all(&[util::message(5, &[])]).unwrap_err().to_string(), "the server refused to perform the request");
+        let mut trailing = records.clone();
+        trailing.extend([records[0].clone(), records[1].clone()]);
+        assert!(add_all(&[util::message(0, &trailing)]).is_err());
+        // the server stopped before the closing SOA record
+        assert!(!add_all(&[util::message(0, &records)]).unwrap());
+    }
+
+    #[test]
+    fn incremental_transfer() {
+        let v7 = util::zone(ZONE);
+        let v8 = util::zone("$TTL 300\n@ SOA ns h 8 2 3 4 5\nwww AAAA 2001:db8::81\n");
+        let v9 = util::zone("$TTL 300\n@ SOA ns h 9 2 3 4 5\nmail A 192.0.2.25\n");
+        // 7 -> 8 changes www's address, 8 -> 9 adds mail
+        let messages = vec![
+            util::message(0, &[v9[0].clone(), v7[0].clone(), v7[3].clone(), v8[0].clone()]),
+            util::message(0, &[v8[1].clone(), v8[0].clone(), v9[0].clone(), v9[1].clone(), v9[0].clone()]),
+        ];
+        let (server, handle) = util::serve(messages);
+        let zone = DnsName::from_string("example");
+        let changes = ixfr(&server, &zone, &v7[0], None, &RateLimiter::new(None, 1)).unwrap();
+        let request = DnsMessage::parse_any(&handle.join().unwrap()).unwrap();
+        assert_eq!(request, DnsMessage::query_ixfr("example", v7[0].clone()));
+
+        let Ixfr::Incremental(diffs) = changes else { panic!("expected diffs, got {changes:?}") };
+        assert_eq!(diffs, [
+            Diff { removed: vec![v7[0].clone(), v7[3].clone()], added: v8.clone() },
+            Diff { removed: vec![v8[0].clone()], added: v9.clone() },
+        ]);
+        assert_eq!(diffs[0].to_string(), format!("; serial 7 -> 8\n- {}\n- {}\n+ {}\n+ {}", v7[0], v7[3], v8[0], v8[1]));
+
+        let mut records = v7.clone();
+        for diff in &diffs {
+            diff.apply(&mut records);
+        }
+        assert_eq!(records, [v7[1].clone(), v7[2].clone(), v8[1].clone(), v9[0].clone(), v9[1].clone()]);
+    }
+
+    #[test]
+    fn other_ixfr_responses() {
+        let records = util::zone(ZONE);
+        let collect = |messages: &[Vec<u8>]| -> Result<Ixfr, io::Error> {
+            let mut collected = IxfrCollector { known: Some(6), ..Default::default() };
+            for message in messages {
+                if collected.add(&DnsMessage::parse_any(message).unwrap())? {
+                    return Ok(collected.finish());
+                }
+            }
+            Err(invalid("the transfer didn't finish"))
+        };
+        let v6 = util::zone("$TTL 300\n@ SOA ns h 6 2 3 4 5\n");
+        assert_eq!(collect(&[util::message(0, &v6)]).unwrap(), Ixfr::UpToDate);
+        // the newest SOA record alone is no answer when it's newer than the version asked about
+        assert!(collect(&[util::message(0, &records[..1])]).is_err());
+
+        // a server may send the whole zone instead, as AXFR would
+        let mut full = records.clone();
+        full.push(records[0].clone());
+        assert_eq!(collect(&[util::message(0, &full[..2]), util::message(0, &full[2..])]).unwrap(), Ixfr::Full(records.clone()));
+        // even when the first message holds nothing but the newest SOA record
+        assert_eq!(collect(&[util::message(0, &full[..1]), util::message(0, &full[1..])]).unwrap(), Ixfr::Full(records.clone()));
+        let changes = [records[0].clone(), v6[0].clone(), records[0].clone(), records[2].clone(), records[0].clone()];
+        let Ixfr::Incremental(diffs) = collect(&[util::message(0, &changes[..1]), util::message(0, &changes[1..])]).unwrap() else {
+            panic!("expected diffs");
+        };
+        assert_eq!(diffs, [Diff { removed: vec![v6[0].clone()], added: vec![records[0].clone(), records[2].clone()] }]);
+
+        // NOTIMP, which has ixfr() fall back to AXFR
+        assert_eq!(collect(&[util::message(4, &[])]).unwrap_err().kind(), io::ErrorKind::Unsupported);
+        assert!(collect(&[util::message(0, &records[1..])]).is_err());
+    }
+
+    #[test]
+    fn diff_between_versions() {
+        let old = util::zone(ZONE);
+        let new = util::zone("$TTL 60\n@ SOA ns h 8 2 3 4 5\n@ NS ns\nns A 192.0.2.54\nwww AAAA 2001:db8::80\n");
+        let diff = Diff::between(&old, &new);
+        // TTLs don't matter
+        assert_eq!(diff, Diff { removed: vec![old[0].clone(), old[2].clone()], added: vec![new[0].clone(), new[2].clone()] });
+        let mut records = old.clone();
+        diff.apply(&mut records);
+        assert_eq!(records, [old[1].clone(), old[3].clone(), new[0].clone(), new[2].clone()]);
+        // nor does the case of names, and a record removed once only goes once
+        let upper = util::zone("$TTL 60\nWWW AAAA 2001:db8::80\n");
+        let mut records = vec![old[3].clone(), old[3].clone()];
+        Diff { removed: upper.clone(), added: Vec::new() }.apply(&mut records);
+        assert_eq!(records, [old[3].clone()]);
+        assert_eq!(Diff::between(&upper, &old[3..]), Diff { removed: Vec::new(), added: Vec::new() });
+        assert_eq!(known_soa(&DnsName::from_string("example"), 7).rdata.to_string(), ". . 7 0 0 0 0");
+    }
+}
@@ -0,0 +1,161 @@
+//! Dynamic updates (RFC 2136): records added to or deleted from a zone on its primary server,
+//! optionally only if some names or RRsets exist or don't.
+
+use std::io;
+
+use crate::dns_client;
+use crate::dns_components::{dns_message::DnsMessage, dns_name::DnsName, dns_rdata::DnsRdata, dns_rr::{self, DnsResourceRecord}, tsig::Key, zone_file};
+use crate::rate_limit::RateLimiter;
+
+const ANY: u16 = 255;
+const NONE: u16 = 254;
+/// the TTL of added records that don't give one
+const DEFAULT_TTL: u32 = 3600;
+
+/// a record with no rdata, the way prerequisites and deletions of whole RRsets are written
+fn empty_record(name: &DnsName, rtype: u16, class: u16) -> DnsResourceRecord {
+    DnsResourceRecord {
+        name: name.clone(),
+        rtype,
+        class,
+        ttl: 0,
+        rdlength: 0,
+        rdata: DnsRdata::UnknownRecord(Vec::new()),
+        rdata_raw: Vec::new(),
+    }
+}
+
+/// `NAME [TYPE]`, with the name relative to `zone`. without a type, the name stands for all its RRsets.
+fn name_and_type(text: &str, zone: &DnsName) -> Result<(DnsName, u16), String> {
+    match text.split_whitespace().collect::<Vec<&str>>().as_slice() {
+        [name] => Ok((zone_file::name(name, zone)?, ANY)),
+        [name, rtype] => Ok((zone_file::name(name, zone)?, dns_rr::type_code(rtype).ok_or_else(|| format!("unknown record type {rtype}"))?)),
+        _ => Err(format!("expected a name and optionally a type, got `{text}`")),
+    }
+}
+
+/// What has to be true of the zone for an update to be made (RFC 2136 section 2.4).
+#[derive(Debug, PartialEq, Eq)]
+pub enum Prerequisite {
+    /// the RRset exists, or with type ANY, the name does
+    Exists(DnsName, u16),
+    /// the RRset doesn't exist, or with type ANY, the name doesn't
+    Absent(DnsName, u16),
+}
+impl Prerequisite {
+    pub fn exists(text: &str, zone: &DnsName) -> Result<Self, String> {
+        name_and_type(text, zone).map(|(name, rtype)| Self::Exists(name, rtype))
+    }
+
+    pub fn absent(text: &str, zone: &DnsName) -> Result<Self, String> {
+        name_and_type(text, zone).map(|(name, rtype)| Self::Absent(name, rtype))
+    }
+
+    fn to_record(&self) -> DnsResourceRecord {
+        match self {
+            Self::Exists(name, rtype) => empty_record(name, *rtype, ANY),
+            Self::Absent(name, rtype) => empty_record(name, *rtype, NONE),
+        }
+    }
+}
+
+/// One change to the zone (RFC 2136 section 2.5).
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    Add(DnsResourceRecord),
+    /// deletes an RRset, or with type ANY, every RRset of the name
+    DeleteRrset(DnsName, u16),
+    /// deletes one record, whatever its TTL
+    DeleteRecord(DnsResourceRecord),
+}
+impl Change {
+    pub fn add(text: &str, zone: &DnsName) -> Result<Self, String> {
+        zone_file::parse_record(text, zone, DEFAULT_TTL).map(Self::Add)
+    }
+
+    pub fn delete_rrset(text: &str, zone: &DnsName) -> Result<Self, String> {
+        name_and_type(text, zone).map(|(name, rtype)| Self::DeleteRrset(name, rtype))
+    }
+
+    pub fn delete_record(text: &str, zone: &DnsName) -> Result<Self, String> {
+        zone_file::parse_record(text, zone, DEFAULT_TTL).map(Self::DeleteRecord)
+    }
+
+    fn to_record(&self) -> DnsResourceRecord {
+        match self {
+            Self::Add(record) => record.clone(),
+            Self::DeleteRrset(name, rtype) => empty_record(name, *rtype, ANY),
+            Self::DeleteRecord(record) => DnsResourceRecord { class: NONE, ttl: 0, ..record.clone() },
+        }
+    }
+}
+
+/// the UPDATE message making `changes` to `zone` if `prerequisites` hold
+fn message(zone: &DnsName, prerequisites: &[Prerequisite], changes: &[Change]) -> DnsMessage {
+    let mut msg = DnsMessage::update(&zone.to_string());
+    for prerequisite in prerequisites {
+        msg.push_prerequisite(prerequisite.to_record());
+    }
+    for change in changes {
+        msg.push_update(change.to_record());
+    }
+    msg
+}
+
+/// sends the update to `server`, signed with `key` if there is one, and returns the RCODE of the
+/// response: 0 when the changes were made, or why they weren't, like NXRRSET for an unmet prerequisite
+pub fn update(server: &str, zone: &DnsName, prerequisites: &[Prerequisite], changes: &[Change], key: Option<&Key>, limiter: &RateLimiter) -> Result<u8, io::Error> {
+    let mut request = message(zone, prerequisites, changes);
+    let mut verifier = key.map(|key| key.sign(&mut request)).transpose()?;
+    let response = dns_client::exchange(server, &request.to_bytes(), limiter)?;
+    if let Some(verifier) = &mut verifier {
+        verifier.verify(&response)?;
+        verifier.finish()?;
+    }
+    Ok(DnsMessage::parse_any(&response)?.rcode())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dns;
+
+    #[test]
+    fn update_message() {
+        let zone = DnsName::from_string("example");
+        let prerequisites = [Prerequisite::absent("www", &zone).unwrap(), Prerequisite::exists("@ NS", &zone).unwrap()];
+        let changes = [
+            Change::add("www 300 A 192.0.2.1", &zone).unwrap(),
+            Change::add("mail.example. MX 10 mx", &zone).unwrap(),
+            Change::delete_rrset("old AAAA", &zone).unwrap(),
+            Change::delete_record("txt TXT \"v=1\"", &zone).unwrap(),
+        ];
+        let bytes = message(&zone, &prerequisites, &changes).to_bytes();
+        // opcode UPDATE, one zone, two prerequisites and four updates
+        assert_eq!(bytes[2..12], [5 << 3, 0, 0, 1, 0, 2, 0, 4, 0, 0]);
+
+        let msg = DnsMessage::parse_any(&bytes).unwrap();
+        let prerequisites: Vec<String> = msg.answers().iter().map(DnsResourceRecord::to_string).collect();
+        assert_eq!(prerequisites, ["www.example. 0 CLASS254 ANY \\# 0", "example. 0 CLASS255 NS \\# 0"]);
+        let updates: Vec<String> = msg.authorities().iter().map(DnsResourceRecord::to_string).collect();
+        assert_eq!(updates, [
+            "www.example. 300 IN A 192.0.2.1",
+            "mail.example. 3600 IN MX 10 mx.example.",
+            "old.example. 0 CLASS255 AAAA \\# 0",
+            "txt.example. 0 CLASS254 TXT \"v=1\"",
+        ]);
+
+        assert_eq!(Change::add("www A 192.0.2.1\nwww A 192.0.2.2", &zone).unwrap_err(), "expected a single record, got `www A 192.0.2.1\nwww A 192.0.2.2`");
+        assert_eq!(Change::delete_rrset("www NOPE", &zone).unwrap_err(), "unknown record type NOPE");
+    }
+
+    #[test]
+    fn send_update() {
+        let zone = DnsName::from_string("example");
+        // the stand-in server answers NXDOMAIN for the zone, as a real one would when the name prerequisite isn't met
+        let (server, handle) = test_dns::serve(1, |_| None);
+        let rcode = update(&server, &zone, &[Prerequisite::exists("www", &zone).unwrap()], &[Change::delete_rrset("www", &zone).unwrap()], None, &RateLimiter::new(None, 1));
+        assert_eq!(handle.join().unwrap(), ["example/6"]);
+        assert_eq!(rcode.unwrap(), 3);
+    }
+}
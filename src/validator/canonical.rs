@@ -0,0 +1,62 @@
+//! The canonical form of an RRset, which is what an RRSIG actually signs (RFC 4034 sections 3.1.8.1 and 6).
+
+use crate::dns_components::{dns_rdata::{DnsRdata, Soa, Srv}, dns_rr::DnsResourceRecord, dnssec::Rrsig};
+
+/// the rdata in canonical form: uncompressed, with the names in the types listed by RFC 4034 section 6.2
+/// lowercased (less NSEC, as RFC 6840 section 5.1 took it off the list)
+fn rdata(rdata: &DnsRdata) -> Vec<u8> {
+    let lowered = match rdata {
+        DnsRdata::NsRecord(name) => DnsRdata::NsRecord(name.to_lowercase()),
+        DnsRdata::CnameRecord(name) => DnsRdata::CnameRecord(name.to_lowercase()),
+        DnsRdata::PtrRecord(name) => DnsRdata::PtrRecord(name.to_lowercase()),
+        DnsRdata::MxRecord(preference, exchange) => DnsRdata::MxRecord(*preference, exchange.to_lowercase()),
+        DnsRdata::SoaRecord(soa) => DnsRdata::SoaRecord(Soa {
+            mname: soa.mname.to_lowercase(),
+            rname: soa.rname.to_lowercase(),
+            ..soa.clone()
+        }),
+        DnsRdata::SrvRecord(srv) => DnsRdata::SrvRecord(Srv {
+            target: srv.target.to_lowercase(),
+            ..srv.clone()
+        }),
+        DnsRdata::RrsigRecord(rrsig) => DnsRdata::RrsigRecord(Rrsig {
+            signer: rrsig.signer.to_lowercase(),
+            ..rrsig.clone()
+        }),
+        other => return other.to_bytes(),
+    };
+    lowered.to_bytes()
+}
+
+/// the data `rrsig` signs for `records`, which must all have the same owner, type and class:
+/// the RRSIG's own fields followed by the records in canonical form and order.
+/// records expanded from a wildcard are put back under the wildcard name they came from.
+pub fn signed_data(rrsig: &Rrsig, records: &[&DnsResourceRecord]) -> Vec<u8> {
+    let mut data = Rrsig {
+        signer: rrsig.signer.to_lowercase(),
+        ..rrsig.clone()
+    }.fields_to_bytes();
+    let Some(first) = records.first() else {
+        return data;
+    };
+
+    let mut owner = first.name.to_lowercase();
+    let labels = usize::from(rrsig.labels);
+    if labels < owner.labels.len() {
+        owner = owner.ancestor(labels).child(b"*");
+    }
+    let owner = owner.to_bytes();
+
+    let mut rdatas: Vec<Vec<u8>> = records.iter().map(|record| rdata(&record.rdata)).collect();
+    rdatas.sort();
+    rdatas.dedup();
+    for rdata in rdatas {
+        data.extend(&owner);
+        data.extend(first.rtype.to_be_bytes());
+        data.extend(first.class.to_be_bytes());
+        data.extend(rrsig.original_ttl.to_be_bytes());
+        data.extend(u16::try_from(rdata.len()).unwrap_or(u16::MAX).to_be_bytes());
+        data.extend(rdata);
+    }
+    data
+}
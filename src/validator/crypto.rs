@@ -0,0 +1,120 @@
+//! The cryptography DNSSEC needs, on top of `ring`: signature verification for the algorithms we support,
+//! DS digests and NSEC3 hashes.
+
+use ring::{digest, signature};
+
+use crate::dns_components::{dns_name::DnsName, dnssec::Dnskey};
+
+pub const RSASHA256: u8 = 8;
+pub const ECDSAP256SHA256: u8 = 13;
+pub const ECDSAP384SHA384: u8 = 14;
+pub const ED25519: u8 = 15;
+
+/// whether signatures made with `algorithm` can be checked. zones signed only with other algorithms
+/// are treated as unsigned (RFC 4035 section 5.2).
+pub fn is_supported(algorithm: u8) -> bool {
+    matches!(algorithm, RSASHA256 | ECDSAP256SHA256 | ECDSAP384SHA384 | ED25519)
+}
+
+/// splits an RSA key as DNSKEY records hold it (RFC 3110 section 2) into its exponent and modulus
+fn rsa_components(key: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (exponent_length, rest) = match key {
+        [0, high, low, rest @ ..] => (usize::from(u16::from_be_bytes([*high, *low])), rest),
+        [length, rest @ ..] => (usize::from(*length), rest),
+        [] => return None,
+    };
+    (exponent_length > 0 && rest.len() > exponent_length).then(|| rest.split_at(exponent_length))
+}
+
+/// checks `signed` against `sig` using a DNSKEY's public key
+pub fn verify(algorithm: u8, public_key: &[u8], signed: &[u8], sig: &[u8]) -> Result<(), String> {
+    let result = match algorithm {
+        RSASHA256 => {
+            let (e, n) = rsa_components(public_key).ok_or("invalid RSA key")?;
+            // 1024 bit keys are still around, mostly as zone signing keys
+            signature::RsaPublicKeyComponents { n, e }.verify(&signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY, signed, sig)
+        },
+        ECDSAP256SHA256 | ECDSAP384SHA384 => {
+            let scheme = if algorithm == ECDSAP256SHA256 { &signature::ECDSA_P256_SHA256_FIXED } else { &signature::ECDSA_P384_SHA384_FIXED };
+            // the key is the curve point without the uncompressed-point marker ring expects (RFC 6605 section 4)
+            let mut point = vec![4];
+            point.extend(public_key);
+            signature::UnparsedPublicKey::new(scheme, point).verify(signed, sig)
+        },
+        ED25519 => signature::UnparsedPublicKey::new(&signature::ED25519, public_key).verify(signed, sig),
+        _ => return Err(format!("unsupported algorithm {algorithm}")),
+    };
+    result.map_err(|_| "signature doesn't match".to_string())
+}
+
+/// the digest a DS record holds for `key` at `owner` (RFC 4034 section 5.1.4); `None` for unsupported digest types
+pub fn ds_digest(digest_type: u8, owner: &DnsName, key: &Dnskey) -> Option<Vec<u8>> {
+    let algorithm = match digest_type {
+        1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        2 => &digest::SHA256,
+        4 => &digest::SHA384,
+        _ => return None,
+    };
+    let mut context = digest::Context::new(algorithm);
+    context.update(&owner.to_lowercase().to_bytes());
+    context.update(&key.to_bytes());
+    Some(context.finish().as_ref().to_vec())
+}
+
+/// the hashed owner name NSEC3 uses for `name` (RFC 5155 section 5): SHA-1, applied `iterations` more times
+pub fn nsec3_hash(name: &DnsName, salt: &[u8], iterations: u16) -> Vec<u8> {
+    let hash = |data: &[u8]| {
+        let mut context = digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY);
+        context.update(data);
+        context.update(salt);
+        context.finish().as_ref().to_vec()
+    };
+    let mut hashed = hash(&name.to_lowercase().to_bytes());
+    for _ in 0..iterations {
+        hashed = hash(&hashed);
+    }
+    hashed
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::{rand::SystemRandom, signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair}};
+
+    use super::*;
+    use crate::dns_components::encoding;
+
+    #[test]
+    fn ecdsa_and_ed25519() {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let sig = pair.sign(&rng, b"signed data").unwrap();
+        let key = &pair.public_key().as_ref()[1..];
+        assert_eq!(verify(ECDSAP256SHA256, key, b"signed data", sig.as_ref()), Ok(()));
+        assert!(verify(ECDSAP256SHA256, key, b"other data", sig.as_ref()).is_err());
+
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let sig = pair.sign(b"signed data");
+        assert_eq!(verify(ED25519, pair.public_key().as_ref(), b"signed data", sig.as_ref()), Ok(()));
+        assert!(verify(ECDSAP384SHA384, pair.public_key().as_ref(), b"signed data", sig.as_ref()).is_err());
+    }
+
+    #[test]
+    fn rsa_key_components() {
+        let (e, n) = rsa_components(&[3, 1, 0, 1, 0xc0, 0xff, 0xee]).unwrap();
+        assert_eq!((e, n), ([1, 0, 1].as_slice(), [0xc0, 0xff, 0xee].as_slice()));
+        let (e, _) = rsa_components(&[0, 0, 1, 3, 0xaa]).unwrap();
+        assert_eq!(e, [3]);
+        assert_eq!(rsa_components(&[3, 1, 0]), None);
+    }
+
+    #[test]
+    fn nsec3_hashes() {
+        // RFC 5155 appendix A
+        let salt = [0xaa, 0xbb, 0xcc, 0xdd];
+        let hash = |name| encoding::base32hex(&nsec3_hash(&DnsName::from_string(name), &salt, 12));
+        assert_eq!(hash("example"), "0P9MHAVEQVM6T7VBL5LOP2U3T2RP3TOM");
+        assert_eq!(hash("a.example"), "35MTHGPGCU1QG68FAB165KLNSNK3DPVL");
+    }
+}
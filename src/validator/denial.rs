@@ -0,0 +1,377 @@
+//! Authenticated denial of existence: what the NSEC (RFC 4035 section 5.4) or NSEC3 (RFC 5155 section 8)
+//! records of a response prove about a name. The records are taken to be validated already.
+
+use std::cmp::Ordering;
+
+use crate::dns_components::{dns_name::DnsName, dns_rdata::DnsRdata, dns_rr::DnsResourceRecord, dnssec::{Nsec, Nsec3}, encoding};
+
+use super::crypto;
+
+const NS: u16 = 2;
+const SOA: u16 = 6;
+const CNAME: u16 = 5;
+pub const DS: u16 = 43;
+
+/// NSEC3 records hashed more often than this are not worth checking (RFC 9276 section 3.2)
+const MAX_ITERATIONS: u16 = 150;
+
+/// What a set of records proves.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Proof {
+    Proven,
+    /// proven only as far as an opt-out range or NSEC3 with too many iterations goes, which is no proof at all
+    Insecure,
+    NotProven,
+}
+
+fn nsecs(records: &[DnsResourceRecord]) -> Vec<(&DnsName, &Nsec)> {
+    records.iter()
+        .filter_map(|record| match &record.rdata {
+            DnsRdata::NsecRecord(nsec) => Some((&record.name, nsec)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// whether a record with `types` at `owner` is the parent side of a zone cut. the parent can't deny anything
+/// in the child zone, neither names below the cut nor types other than DS at it (RFC 6840 section 4.1),
+/// so such records don't count towards proofs about `qname` and `qtype`.
+fn ancestor_delegation(owner: &DnsName, types: &[u16], qname: &DnsName, qtype: Option<u16>) -> bool {
+    let delegation = types.contains(&NS) && !types.contains(&SOA);
+    delegation && qname.is_subdomain_of(owner) && (!same(owner, qname) || qtype != Some(DS))
+}
+
+/// the NSEC records that can take part in a proof about `qname` and `qtype`
+fn usable_nsecs<'a>(qname: &DnsName, qtype: Option<u16>, records: &'a [DnsResourceRecord]) -> Vec<(&'a DnsName, &'a Nsec)> {
+    nsecs(records).into_iter()
+        .filter(|(owner, nsec)| !ancestor_delegation(owner, &nsec.types, qname, qtype))
+        .collect()
+}
+
+/// whether `name` falls strictly between `owner` and `next`; the last NSEC of a zone wraps around to its apex
+fn covers(owner: &DnsName, next: &DnsName, name: &DnsName) -> bool {
+    let after_owner = owner.canonical_cmp(name) == Ordering::Less;
+    let before_next = name.canonical_cmp(next) == Ordering::Less;
+    if owner.canonical_cmp(next) == Ordering::Less { after_owner && before_next } else { after_owner || before_next }
+}
+
+fn same(a: &DnsName, b: &DnsName) -> bool {
+    a.canonical_cmp(b) == Ordering::Equal
+}
+
+/// how many labels, counted from the right, `a` and `b` have in common
+fn common_labels(a: &DnsName, b: &DnsName) -> usize {
+    a.labels.iter().rev().zip(b.labels.iter().rev())
+        .take_while(|(a, b)| a.label.eq_ignore_ascii_case(&b.label))
+        .count()
+}
+
+/// The NSEC3 records of one zone, with their owner names decoded to hashes.
+struct Nsec3Set<'a> {
+    records: Vec<(Vec<u8>, &'a Nsec3)>,
+    zone: DnsName,
+}
+impl<'a> Nsec3Set<'a> {
+    fn new(records: &'a [DnsResourceRecord]) -> Option<Self> {
+        let mut zone = None;
+        let mut set = Vec::new();
+        for record in records {
+            let DnsRdata::Nsec3Record(nsec3) = &record.rdata else {
+                continue;
+            };
+            let hash = encoding::decode_base32hex(&record.name.labels.first()?.to_string())?;
+            zone.get_or_insert(record.name.parent()?);
+            set.push((hash, nsec3));
+        }
+        Some(Self {
+            records: set,
+            zone: zone?,
+        })
+    }
+
+    /// whether the hashes can be checked at all
+    fn usable(&self) -> bool {
+        self.records.iter().all(|(_, nsec3)| nsec3.hash_algorithm == 1 && nsec3.iterations <= MAX_ITERATIONS)
+    }
+
+    fn hash(&self, name: &DnsName) -> Vec<u8> {
+        let (_, first) = self.records[0];
+        crypto::nsec3_hash(name, &first.salt, first.iterations)
+    }
+
+    fn matching(&self, name: &DnsName) -> Option<&'a Nsec3> {
+        let hash = self.hash(name);
+        self.records.iter().find(|(owner, _)| *owner == hash).map(|(_, nsec3)| *nsec3)
+    }
+
+    fn covering(&self, name: &DnsName) -> Option<&'a Nsec3> {
+        let hash = self.hash(name);
+        self.records.iter()
+            .find(|(owner, nsec3)| {
+                let next = &nsec3.next_hashed;
+                if owner < next { *owner < hash && hash < *next } else { *owner < hash || hash < *next }
+            })
+            .map(|(_, nsec3)| *nsec3)
+    }
+
+    /// the closest encloser proof (RFC 5155 section 8.3): the longest existing ancestor of `name`, and the
+    /// record covering the next closer name below it. `None` for the record when `name` itself exists, and
+    /// no proof at all when the closest encloser is a delegation.
+    fn closest_encloser(&self, name: &DnsName) -> Option<(DnsName, Option<&'a Nsec3>)> {
+        if !name.is_subdomain_of(&self.zone) {
+            return None;
+        }
+        for count in (self.zone.labels.len()..=name.labels.len()).rev() {
+            let candidate = name.ancestor(count);
+            let Some(encloser) = self.matching(&candidate) else {
+                continue;
+            };
+            if count == name.labels.len() {
+                return Some((candidate, None));
+            }
+            if ancestor_delegation(&candidate, &encloser.types, name, None) {
+                return None;
+            }
+            let next_closer = self.covering(&name.ancestor(count + 1))?;
+            return Some((candidate, Some(next_closer)));
+        }
+        None
+    }
+}
+
+/// the wildcard that could have answered for `qname`, at the closest encloser the NSEC covering `qname` shows
+fn nsec_wildcard(qname: &DnsName, nsecs: &[(&DnsName, &Nsec)]) -> Option<DnsName> {
+    let (owner, nsec) = nsecs.iter().find(|(owner, nsec)| covers(owner, &nsec.next, qname))?;
+    let encloser = common_labels(qname, owner).max(common_labels(qname, &nsec.next));
+    Some(qname.ancestor(encloser).child(b"*"))
+}
+
+fn opt_out(nsec3: &Nsec3) -> bool {
+    nsec3.flags & 1 == 1
+}
+
+/// that `qname` doesn't exist: nothing at it, and no wildcard that could have answered instead
+pub fn nxdomain(qname: &DnsName, records: &[DnsResourceRecord]) -> Proof {
+    let nsecs = usable_nsecs(qname, None, records);
+    if !nsecs.is_empty() {
+        let Some(wildcard) = nsec_wildcard(qname, &nsecs) else {
+            return Proof::NotProven;
+        };
+        let no_wildcard = nsecs.iter().any(|(owner, nsec)| covers(owner, &nsec.next, &wildcard));
+        return if no_wildcard { Proof::Proven } else { Proof::NotProven };
+    }
+
+    let Some(set) = Nsec3Set::new(records) else {
+        return Proof::NotProven;
+    };
+    if !set.usable() {
+        return Proof::Insecure;
+    }
+    let Some((encloser, Some(next_closer))) = set.closest_encloser(qname) else {
+        return Proof::NotProven;
+    };
+    if set.covering(&encloser.child(b"*")).is_none() {
+        return Proof::NotProven;
+    }
+    if opt_out(next_closer) { Proof::Insecure } else { Proof::Proven }
+}
+
+/// that `qname` exists but has no records of type `qtype` (nor a CNAME that would have been followed),
+/// or that it doesn't exist and the wildcard that would have answered instead has no such records
+pub fn nodata(qname: &DnsName, qtype: u16, records: &[DnsResourceRecord]) -> Proof {
+    // a DS record lives on the parent side of a zone cut, so the child's apex can't deny it
+    let lacks = |types: &[u16]| {
+        let child_apex = qtype == DS && types.contains(&SOA) && !qname.is_root();
+        !(types.contains(&qtype) || types.contains(&CNAME) || child_apex)
+    };
+
+    let nsecs = usable_nsecs(qname, Some(qtype), records);
+    if !nsecs.is_empty() {
+        let at_name = nsecs.iter().any(|(owner, nsec)| same(owner, qname) && lacks(&nsec.types));
+        // an empty non-terminal: nothing at the name itself, but names below it
+        let empty_non_terminal = nsecs.iter().any(|(owner, nsec)| covers(owner, &nsec.next, qname) && nsec.next.is_subdomain_of(qname));
+        // a wildcard without the type (RFC 4035 section 3.1.3.4)
+        let wildcard = nsec_wildcard(qname, &nsecs)
+            .is_some_and(|wildcard| nsecs.iter().any(|(owner, nsec)| same(owner, &wildcard) && lacks(&nsec.types)));
+        return if at_name || empty_non_terminal || wildcard { Proof::Proven } else { Proof::NotProven };
+    }
+
+    let Some(set) = Nsec3Set::new(records) else {
+        return Proof::NotProven;
+    };
+    if !set.usable() {
+        return Proof::Insecure;
+    }
+    if let Some(nsec3) = set.matching(qname) {
+        let usable = !ancestor_delegation(qname, &nsec3.types, qname, Some(qtype));
+        return if usable && lacks(&nsec3.types) { Proof::Proven } else { Proof::NotProven };
+    }
+    match set.closest_encloser(qname) {
+        // no DS for a delegation in an opt-out range (RFC 5155 section 8.6)
+        Some((_, Some(next_closer))) if qtype == DS && opt_out(next_closer) => Proof::Insecure,
+        // a wildcard without the type (RFC 5155 section 8.7)
+        Some((encloser, Some(next_closer))) if set.matching(&encloser.child(b"*")).is_some_and(|nsec3| lacks(&nsec3.types)) => {
+            if opt_out(next_closer) { Proof::Insecure } else { Proof::Proven }
+        },
+        _ => Proof::NotProven,
+    }
+}
+
+/// that `name` is a delegation without a DS record, making the zone below it unsigned
+pub fn insecure_delegation(name: &DnsName, records: &[DnsResourceRecord]) -> bool {
+    let delegation = |types: &[u16]| types.contains(&NS) && !types.contains(&DS) && !types.contains(&SOA);
+
+    let nsecs = nsecs(records);
+    if !nsecs.is_empty() {
+        return nsecs.iter().any(|(owner, nsec)| same(owner, name) && delegation(&nsec.types));
+    }
+    let Some(set) = Nsec3Set::new(records) else {
+        return false;
+    };
+    if !set.usable() {
+        return true;
+    }
+    match set.matching(name) {
+        Some(nsec3) => delegation(&nsec3.types),
+        None => matches!(set.closest_encloser(name), Some((_, Some(next_closer))) if opt_out(next_closer)),
+    }
+}
+
+/// that an answer expanded from a wildcard was right to be: `qname` doesn't exist below the `labels`
+/// rightmost labels the wildcard was at (RFC 4035 section 5.3.4, RFC 5155 section 8.8)
+pub fn no_closer_match(qname: &DnsName, labels: usize, records: &[DnsResourceRecord]) -> bool {
+    let nsecs = nsecs(records);
+    if !nsecs.is_empty() {
+        return nsecs.iter().any(|(owner, nsec)| covers(owner, &nsec.next, qname));
+    }
+    Nsec3Set::new(records).is_some_and(|set| set.covering(&qname.ancestor(labels + 1)).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod util {
+        use super::*;
+
+        pub fn record(owner: &str, rdata: DnsRdata) -> DnsResourceRecord {
+            DnsResourceRecord {
+                name: DnsName::from_string(owner),
+                rtype: 0,
+                class: 1,
+                ttl: 3600,
+                rdlength: 0,
+                rdata,
+                rdata_raw: Vec::new(),
+            }
+        }
+
+        pub fn nsec(owner: &str, next: &str, types: &[u16]) -> DnsResourceRecord {
+            record(owner, DnsRdata::NsecRecord(Nsec { next: DnsName::from_string(next), types: types.to_vec() }))
+        }
+
+        /// an NSEC3 record at the hash of `owner` in `zone`, pointing to the hash of `next`
+        pub fn nsec3(zone: &str, owner: &str, next: &str, flags: u8, types: &[u16]) -> DnsResourceRecord {
+            let hash = |name| crypto::nsec3_hash(&DnsName::from_string(name), &[0xaa], 1);
+            let rdata = Nsec3 { hash_algorithm: 1, flags, iterations: 1, salt: vec![0xaa], next_hashed: hash(next), types: types.to_vec() };
+            record(&format!("{}.{zone}", encoding::base32hex(&hash(owner))), DnsRdata::Nsec3Record(rdata))
+        }
+    }
+
+    #[test]
+    fn nsec_proofs() {
+        let name = DnsName::from_string;
+        let records = vec![
+            util::nsec("example", "a.example", &[NS, SOA, 46, 47, 48]),
+            util::nsec("a.example", "x.y.example", &[NS, DS, 46, 47]),
+            util::nsec("x.y.example", "example", &[1, 46, 47]),
+        ];
+        assert_eq!(nxdomain(&name("b.example"), &records), Proof::Proven);
+        assert_eq!(nxdomain(&name("a.example"), &records), Proof::NotProven);
+        assert_eq!(nodata(&name("x.y.example"), 28, &records), Proof::Proven);
+        assert_eq!(nodata(&name("x.y.example"), 1, &records), Proof::NotProven);
+        // y.example only exists because x.y.example does
+        assert_eq!(nodata(&name("y.example"), 1, &records), Proof::Proven);
+        // the apex can't prove there's no DS above it
+        assert_eq!(nodata(&name("example"), DS, &records), Proof::NotProven);
+        assert!(!insecure_delegation(&name("a.example"), &records));
+        assert!(no_closer_match(&name("z.example"), 1, &records));
+
+        let insecure = vec![util::nsec("b.example", "c.example", &[NS, 46, 47])];
+        assert!(insecure_delegation(&name("b.example"), &insecure));
+    }
+
+    #[test]
+    fn nsec_parent_side_of_delegation() {
+        let name = DnsName::from_string;
+        // signed by example, at the cut to the sub.example zone
+        let records = vec![util::nsec("sub.example", "z.example", &[NS, 46, 47])];
+        assert_eq!(nxdomain(&name("a.sub.example"), &records), Proof::NotProven);
+        assert_eq!(nodata(&name("a.sub.example"), 1, &records), Proof::NotProven);
+        assert_eq!(nodata(&name("sub.example"), 1, &records), Proof::NotProven);
+        // the parent does speak for the DS record
+        assert_eq!(nodata(&name("sub.example"), DS, &records), Proof::Proven);
+        assert!(insecure_delegation(&name("sub.example"), &records));
+        // names that aren't below the cut can still be denied
+        assert_eq!(nodata(&name("x.example"), 1, &[util::nsec("sub.example", "z.example", &[NS, 46, 47]), util::nsec("x.example", "sub.example", &[16, 46, 47])]), Proof::Proven);
+    }
+
+    #[test]
+    fn nsec_wildcard_nodata() {
+        let name = DnsName::from_string;
+        // *.example only has an A record, and the NSEC at it also covers foo.example
+        let records = vec![
+            util::nsec("example", "*.example", &[NS, SOA, 46, 47, 48]),
+            util::nsec("*.example", "www.example", &[1, 46, 47]),
+        ];
+        assert_eq!(nodata(&name("foo.example"), 28, &records), Proof::Proven);
+        assert_eq!(nodata(&name("foo.example"), 1, &records), Proof::NotProven);
+        // without the NSEC at the wildcard, foo.example could still have been synthesized from it
+        assert_eq!(nodata(&name("foo.example"), 28, &records[..1]), Proof::NotProven);
+    }
+
+    #[test]
+    fn nsec3_proofs() {
+        let name = DnsName::from_string;
+        // a zone with only its apex, whose single NSEC3 covers every other hash
+        let records = vec![util::nsec3("example", "example", "example", 0, &[NS, SOA, 46, 48, 51])];
+        assert_eq!(nxdomain(&name("nope.example"), &records), Proof::Proven);
+        assert_eq!(nodata(&name("example"), 1, &records), Proof::Proven);
+        assert_eq!(nodata(&name("example"), NS, &records), Proof::NotProven);
+        assert_eq!(nodata(&name("nope.example"), DS, &records), Proof::NotProven);
+        assert!(no_closer_match(&name("a.b.example"), 1, &records));
+
+        let opt_out = vec![util::nsec3("example", "example", "example", 1, &[NS, SOA, 46, 48, 51])];
+        assert_eq!(nodata(&name("unsigned.example"), DS, &opt_out), Proof::Insecure);
+        assert!(insecure_delegation(&name("unsigned.example"), &opt_out));
+        assert_eq!(nxdomain(&name("nope.example"), &opt_out), Proof::Insecure);
+    }
+
+    #[test]
+    fn nsec3_parent_side_of_delegation() {
+        let name = DnsName::from_string;
+        let records = vec![
+            util::nsec3("example", "example", "sub.example", 0, &[NS, SOA, 46, 48, 51]),
+            util::nsec3("example", "sub.example", "example", 0, &[NS]),
+        ];
+        assert_eq!(nxdomain(&name("a.sub.example"), &records), Proof::NotProven);
+        assert_eq!(nodata(&name("sub.example"), 1, &records), Proof::NotProven);
+        assert_eq!(nodata(&name("sub.example"), DS, &records), Proof::Proven);
+        assert!(insecure_delegation(&name("sub.example"), &records));
+        // other names in the parent zone are still denied
+        assert_eq!(nxdomain(&name("nope.example"), &records), Proof::Proven);
+    }
+
+    #[test]
+    fn nsec3_wildcard_nodata() {
+        let name = DnsName::from_string;
+        // the apex and *.example, whose two NSEC3 records cover every other hash between them
+        let records = vec![
+            util::nsec3("example", "example", "*.example", 0, &[NS, SOA, 46, 48, 51]),
+            util::nsec3("example", "*.example", "example", 0, &[1, 46]),
+        ];
+        assert_eq!(nodata(&name("foo.example"), 28, &records), Proof::Proven);
+        assert_eq!(nodata(&name("foo.example"), 1, &records), Proof::NotProven);
+        assert_eq!(nodata(&name("foo.example"), 28, &records[..1]), Proof::NotProven);
+    }
+}
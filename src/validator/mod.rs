@@ -0,0 +1,534 @@
+//! DNSSEC validation (RFC 4035 section 5): builds the chain of trust from the root trust anchor down
+//! through DS and DNSKEY records to the RRSIGs over an answer, and checks denials of existence.
+
+mod canonical;
+mod crypto;
+mod denial;
+
+use std::{cell::RefCell, collections::HashMap, io, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::dns_client;
+use crate::dns_components::{dns_name::DnsName, dns_rdata::DnsRdata, dns_rr::{self, DnsResourceRecord}, dnssec::{Dnskey, Ds, Rrsig}, encoding};
+use crate::rate_limit::RateLimiter;
+
+use denial::Proof;
+
+const NXDOMAIN: u8 = 3;
+const RRSIG: u16 = 46;
+const NSEC: u16 = 47;
+const DNSKEY: u16 = 48;
+const NSEC3: u16 = 50;
+
+/// the DNSKEY flag saying a key may sign records of its zone (RFC 4034 section 2.1.1)
+const ZONE_KEY: u16 = 0x0100;
+
+/// The root zone's key signing keys, KSK-2017 and KSK-2024, as published at
+/// <https://data.iana.org/root-anchors/root-anchors.xml>: key tag, algorithm, digest type, digest.
+const ROOT_ANCHORS: [(u16, u8, u8, &str); 2] = [
+    (20326, 8, 2, "E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D"),
+    (38696, 8, 2, "683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16"),
+];
+
+/// The four outcomes of validation (RFC 4033 section 5).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// signed, with an unbroken chain of trust from the root
+    Secure,
+    /// proven to be in an unsigned zone
+    Insecure,
+    /// should have been signed, but the signatures or proofs are missing or wrong
+    Bogus(String),
+    /// the records needed to tell couldn't be fetched
+    Indeterminate(String),
+}
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Secure => write!(f, "Secure"),
+            Self::Insecure => write!(f, "Insecure"),
+            Self::Bogus(reason) => write!(f, "Bogus ({reason})"),
+            Self::Indeterminate(reason) => write!(f, "Indeterminate ({reason})"),
+        }
+    }
+}
+
+/// The parts of a response validation looks at.
+pub struct Response {
+    pub rcode: u8,
+    pub answers: Vec<DnsResourceRecord>,
+    pub authorities: Vec<DnsResourceRecord>,
+}
+
+/// Where the records to validate come from.
+pub trait Resolver {
+    /// the records of type `qtype` at `name`, along with their signatures and any denial of existence
+    fn query(&self, name: &DnsName, qtype: u16) -> Result<Response, io::Error>;
+}
+
+/// Asks a DNS server, which doesn't have to validate itself.
+pub struct Upstream<'a> {
+    pub server: &'a str,
+    pub limiter: &'a RateLimiter,
+}
+impl Resolver for Upstream<'_> {
+    fn query(&self, name: &DnsName, qtype: u16) -> Result<Response, io::Error> {
+        let msg = dns_client::query_dnssec(self.server, &name.to_string(), qtype, self.limiter)?;
+        Ok(Response {
+            rcode: msg.rcode(),
+            answers: msg.answers().to_vec(),
+            authorities: msg.authorities().to_vec(),
+        })
+    }
+}
+
+/// The answer to a query, with the status of every record, and of the denial when there were none.
+pub struct Validated {
+    pub records: Vec<(DnsResourceRecord, Status)>,
+    pub denial: Option<Status>,
+}
+
+/// The records of one type at one name, and the signatures over them.
+struct RrSet<'a> {
+    records: Vec<&'a DnsResourceRecord>,
+    sigs: Vec<&'a Rrsig>,
+}
+impl RrSet<'_> {
+    fn owner(&self) -> &DnsName {
+        &self.records[0].name
+    }
+
+    fn rtype(&self) -> u16 {
+        self.records[0].rtype
+    }
+
+    /// the signer of the first signature that could be from the zone the records are in
+    fn signer(&self) -> Option<&DnsName> {
+        self.sigs.iter().map(|sig| &sig.signer).find(|signer| self.owner().is_subdomain_of(signer))
+    }
+}
+
+/// groups `records` into RRsets, in the order they first appear, with the RRSIGs attached to the sets they cover
+fn rrsets(records: &[DnsResourceRecord]) -> Vec<RrSet<'_>> {
+    let mut sets: Vec<RrSet> = Vec::new();
+    let mut index = HashMap::new();
+    for record in records.iter().filter(|record| record.rtype != RRSIG) {
+        let key = (record.name.to_lowercase(), record.rtype);
+        let i = *index.entry(key).or_insert_with(|| {
+            sets.push(RrSet { records: Vec::new(), sigs: Vec::new() });
+            sets.len() - 1
+        });
+        sets[i].records.push(record);
+    }
+    for record in records {
+        if let DnsRdata::RrsigRecord(sig) = &record.rdata {
+            if let Some(&i) = index.get(&(record.name.to_lowercase(), sig.type_covered)) {
+                sets[i].sigs.push(sig);
+            }
+        }
+    }
+    sets
+}
+
+fn same(a: &DnsName, b: &DnsName) -> bool {
+    a.is_subdomain_of(b) && b.is_subdomain_of(a)
+}
+
+fn indeterminate(e: &io::Error) -> Status {
+    Status::Indeterminate(e.to_string())
+}
+
+/// Validates answers, remembering the keys of every zone it has established trust in.
+pub struct Validator<R> {
+    resolver: R,
+    anchors: Vec<Ds>,
+    /// seconds since the epoch, modulo 2^32 the way RRSIG times are (RFC 4034 section 3.1.5)
+    now: u32,
+    zones: RefCell<HashMap<DnsName, Result<Vec<Dnskey>, Status>>>,
+}
+impl<R: Resolver> Validator<R> {
+    /// a validator trusting the root zone's published key signing keys
+    pub fn new(resolver: R) -> Self {
+        let anchors = ROOT_ANCHORS.iter()
+            .filter_map(|(key_tag, algorithm, digest_type, digest)| Some(Ds {
+                key_tag: *key_tag,
+                algorithm: *algorithm,
+                digest_type: *digest_type,
+                digest: encoding::decode_hex(digest)?,
+            }))
+            .collect();
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        Self::with_anchors(resolver, anchors, u32::try_from(seconds % (1 << 32)).unwrap_or_default())
+    }
+
+    fn with_anchors(resolver: R, anchors: Vec<Ds>, now: u32) -> Self {
+        Self {
+            resolver,
+            anchors,
+            now,
+            zones: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// asks for the records of type `qtype` at `name` and validates them
+    pub fn validate(&self, name: &DnsName, qtype: u16) -> Result<Validated, io::Error> {
+        let response = self.resolver.query(name, qtype)?;
+        let mut records = Vec::new();
+        for set in rrsets(&response.answers) {
+            let status = self.rrset_status(&set, &response.authorities);
+            records.extend(set.records.iter().map(|record| ((*record).clone(), status.clone())));
+        }
+        let denial = records.is_empty().then(|| self.denial_status(name, qtype, &response));
+        Ok(Validated { records, denial })
+    }
+
+    fn rrset_status(&self, set: &RrSet, authorities: &[DnsResourceRecord]) -> Status {
+        let Some(signer) = set.signer() else {
+            return self.unsigned_status(set.owner());
+        };
+        let keys = match self.zone_keys(signer) {
+            Ok(keys) => keys,
+            Err(status) => return status,
+        };
+        match self.verify(set, &keys, signer) {
+            Ok(None) => Status::Secure,
+            // an answer synthesized from a wildcard must come with proof that there was nothing closer
+            Ok(Some(labels)) => match self.verified_denial(authorities) {
+                Ok(proof) if denial::no_closer_match(set.owner(), labels, &proof) => Status::Secure,
+                Ok(_) => Status::Bogus(format!("no proof that the wildcard was the closest match for {}", set.owner())),
+                Err(status) => status,
+            },
+            Err(reason) => Status::Bogus(reason),
+        }
+    }
+
+    /// checks the signatures `zone` made over `set` with any of its `keys`. when the set was expanded from
+    /// a wildcard, returns how many labels the wildcard's parent has.
+    fn verify(&self, set: &RrSet, keys: &[Dnskey], zone: &DnsName) -> Result<Option<usize>, String> {
+        // serial number arithmetic, as the times wrap around in 2106
+        let not_after = |a: u32, b: u32| b.wrapping_sub(a) < 1 << 31;
+        let owner_labels = set.owner().labels.len() - usize::from(set.owner().labels.first().is_some_and(|label| label.label == b"*"));
+
+        let mut error = format!("no signature by {zone} over the {} records of {}", dns_rr::type_name(set.rtype()), set.owner());
+        for sig in set.sigs.iter().filter(|sig| same(&sig.signer, zone)) {
+            if !not_after(sig.inception, self.now) || !not_after(self.now, sig.expiration) {
+                error = format!("the signature over {} isn't valid now", set.owner());
+                continue;
+            }
+            let labels = usize::from(sig.labels);
+            if labels > owner_labels {
+                error = format!("the signature over {} has too many labels", set.owner());
+                continue;
+            }
+            let signed = canonical::signed_data(sig, &set.records);
+            let usable = keys.iter().filter(|key| {
+                key.key_tag() == sig.key_tag && key.algorithm == sig.algorithm && key.protocol == 3 && key.flags & ZONE_KEY != 0
+            });
+            for key in usable {
+                match crypto::verify(key.algorithm, &key.public_key, &signed, &sig.signature) {
+                    Ok(()) => return Ok((labels < owner_labels).then_some(labels)),
+                    Err(e) => error = format!("{e} for the records of {}", set.owner()),
+                }
+            }
+        }
+        Err(error)
+    }
+
+    /// the validated DNSKEY records of `zone`, or the status of anything in it when they can't be had
+    fn zone_keys(&self, zone: &DnsName) -> Result<Vec<Dnskey>, Status> {
+        let key = zone.to_lowercase();
+        if let Some(keys) = self.zones.borrow().get(&key) {
+            return keys.clone();
+        }
+        // a zone whose proof of being unsigned is signed by itself would otherwise never be settled
+        self.zones.borrow_mut().insert(key.clone(), Err(Status::Bogus(format!("the keys of {zone} depend on themselves"))));
+        let keys = self.find_zone_keys(zone);
+        self.zones.borrow_mut().insert(key, keys.clone());
+        keys
+    }
+
+    fn find_zone_keys(&self, zone: &DnsName) -> Result<Vec<Dnskey>, Status> {
+        let ds = if zone.is_root() { self.anchors.clone() } else { self.delegation(zone)? };
+        // a zone signed only with algorithms we don't know is as good as unsigned (RFC 4035 section 5.2)
+        let ds: Vec<Ds> = ds.into_iter()
+            .filter(|ds| crypto::is_supported(ds.algorithm) && matches!(ds.digest_type, 1 | 2 | 4))
+            .collect();
+        if ds.is_empty() {
+            return Err(Status::Insecure);
+        }
+
+        let response = self.resolver.query(zone, DNSKEY).map_err(|e| indeterminate(&e))?;
+        let sets = rrsets(&response.answers);
+        let Some(set) = sets.iter().find(|set| set.rtype() == DNSKEY && same(set.owner(), zone)) else {
+            return Err(Status::Bogus(format!("{zone} has DS records but no DNSKEY records")));
+        };
+        let keys: Vec<Dnskey> = set.records.iter()
+            .filter_map(|record| match &record.rdata {
+                DnsRdata::DnskeyRecord(key) => Some(key.clone()),
+                _ => None,
+            })
+            .collect();
+        let trusted: Vec<Dnskey> = keys.iter()
+            .filter(|key| ds.iter().any(|ds| {
+                ds.key_tag == key.key_tag() && ds.algorithm == key.algorithm
+                    && crypto::ds_digest(ds.digest_type, zone, key).is_some_and(|digest| digest == ds.digest)
+            }))
+            .cloned()
+            .collect();
+        if trusted.is_empty() {
+            return Err(Status::Bogus(format!("no DNSKEY of {zone} matches its DS records")));
+        }
+        self.verify(set, &trusted, zone).map_err(Status::Bogus)?;
+        Ok(keys)
+    }
+
+    /// the validated DS records for `zone` from its parent
+    fn delegation(&self, zone: &DnsName) -> Result<Vec<Ds>, Status> {
+        let response = self.resolver.query(zone, denial::DS).map_err(|e| indeterminate(&e))?;
+        let sets = rrsets(&response.answers);
+        let Some(set) = sets.iter().find(|set| set.rtype() == denial::DS && same(set.owner(), zone)) else {
+            return Err(self.unsigned_status(zone));
+        };
+        // the DS records are the parent's, so the zone can't vouch for them itself
+        let Some(signer) = set.signer().filter(|signer| !same(signer, zone)) else {
+            return Err(self.unsigned_status(zone));
+        };
+        let keys = self.zone_keys(signer)?;
+        self.verify(set, &keys, signer).map_err(Status::Bogus)?;
+        Ok(set.records.iter()
+            .filter_map(|record| match &record.rdata {
+                DnsRdata::DsRecord(ds) => Some(ds.clone()),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// the NSEC and NSEC3 records among `authorities`, once their signatures check out. unsigned ones are left out.
+    fn verified_denial(&self, authorities: &[DnsResourceRecord]) -> Result<Vec<DnsResourceRecord>, Status> {
+        let mut verified = Vec::new();
+        for set in rrsets(authorities).iter().filter(|set| matches!(set.rtype(), NSEC | NSEC3)) {
+            let Some(signer) = set.signer() else {
+                continue;
+            };
+            let keys = self.zone_keys(signer)?;
+            self.verify(set, &keys, signer).map_err(Status::Bogus)?;
+            verified.extend(set.records.iter().map(|record| (*record).clone()));
+        }
+        Ok(verified)
+    }
+
+    /// the status of an empty answer for `qtype` at `name`
+    fn denial_status(&self, name: &DnsName, qtype: u16, response: &Response) -> Status {
+        let proof = match self.verified_denial(&response.authorities) {
+            Ok(proof) if proof.is_empty() => return self.unsigned_status(name),
+            Ok(proof) => proof,
+            Err(status) => return status,
+        };
+        let (proven, claim) = if response.rcode == NXDOMAIN {
+            (denial::nxdomain(name, &proof), "doesn't exist")
+        } else {
+            (denial::nodata(name, qtype, &proof), "has no such records")
+        };
+        match proven {
+            Proof::Proven => Status::Secure,
+            Proof::Insecure => Status::Insecure,
+            Proof::NotProven => Status::Bogus(format!("no proof that {name} {claim}")),
+        }
+    }
+
+    /// the status of unsigned records at `name`: insecure if some zone above it is proven to be unsigned,
+    /// bogus if they should have been signed
+    fn unsigned_status(&self, name: &DnsName) -> Status {
+        let mut current = name.clone();
+        loop {
+            let response = match self.resolver.query(&current, denial::DS) {
+                Ok(response) => response,
+                Err(e) => return indeterminate(&e),
+            };
+            if response.answers.iter().any(|record| record.rtype == denial::DS) {
+                return Status::Bogus(format!("{name} is in the signed zone {current}, but isn't signed"));
+            }
+            match self.verified_denial(&response.authorities) {
+                Ok(proof) if denial::insecure_delegation(&current, &proof) => return Status::Insecure,
+                Ok(_) => {},
+                Err(status) => return status,
+            }
+            let Some(parent) = current.parent() else {
+                return Status::Bogus(format!("{name} isn't signed, and no zone above it is proven to be unsigned"));
+            };
+            current = parent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    use super::*;
+    use crate::dns_components::dnssec::{tests::util::root_ksk, Nsec};
+
+    mod util {
+        use super::*;
+
+        pub const NOW: u32 = 1_760_000_000;
+
+        pub fn record(name: &str, rtype: u16, rdata: DnsRdata) -> DnsResourceRecord {
+            DnsResourceRecord {
+                name: DnsName::from_string(name),
+                rtype,
+                class: 1,
+                ttl: 3600,
+                rdlength: 0,
+                rdata,
+                rdata_raw: Vec::new(),
+            }
+        }
+
+        pub fn a(name: &str, ip: [u8; 4]) -> DnsResourceRecord {
+            record(name, 1, DnsRdata::ARecord(Ipv4Addr::from(ip)))
+        }
+
+        pub fn nsec(name: &str, next: &str, types: &[u16]) -> DnsResourceRecord {
+            record(name, NSEC, DnsRdata::NsecRecord(Nsec { next: DnsName::from_string(next), types: types.to_vec() }))
+        }
+
+        /// a zone with an Ed25519 key signing key
+        pub struct Zone {
+            pub name: &'static str,
+            pair: Ed25519KeyPair,
+        }
+        impl Zone {
+            pub fn new(name: &'static str) -> Self {
+                let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+                Self { name, pair: Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap() }
+            }
+
+            pub fn key(&self) -> Dnskey {
+                Dnskey { flags: 257, protocol: 3, algorithm: crypto::ED25519, public_key: self.pair.public_key().as_ref().to_vec() }
+            }
+
+            pub fn ds(&self) -> Ds {
+                let key = self.key();
+                let digest = crypto::ds_digest(2, &DnsName::from_string(self.name), &key).unwrap();
+                Ds { key_tag: key.key_tag(), algorithm: key.algorithm, digest_type: 2, digest }
+            }
+
+            /// `records` followed by this zone's signature over them
+            pub fn sign(&self, mut records: Vec<DnsResourceRecord>) -> Vec<DnsResourceRecord> {
+                let first = &records[0];
+                let rrsig = Rrsig {
+                    type_covered: first.rtype,
+                    algorithm: crypto::ED25519,
+                    // the wildcard label isn't counted
+                    labels: u8::try_from(first.name.labels.iter().filter(|label| label.label != b"*").count()).unwrap(),
+                    original_ttl: first.ttl,
+                    expiration: NOW + 86400,
+                    inception: NOW - 86400,
+                    key_tag: self.key().key_tag(),
+                    signer: DnsName::from_string(self.name),
+                    signature: Vec::new(),
+                };
+                let refs: Vec<&DnsResourceRecord> = records.iter().collect();
+                let signature = self.pair.sign(&canonical::signed_data(&rrsig, &refs)).as_ref().to_vec();
+                let owner = first.name.to_string();
+                records.push(record(&owner, RRSIG, DnsRdata::RrsigRecord(Rrsig { signature, ..rrsig })));
+                records
+            }
+        }
+
+        /// canned responses by name and type, empty for anything else
+        #[derive(Default)]
+        pub struct Canned {
+            pub responses: HashMap<(String, u16), Response>,
+        }
+        impl Canned {
+            pub fn add(&mut self, name: &str, qtype: u16, rcode: u8, answers: Vec<DnsResourceRecord>, authorities: Vec<DnsResourceRecord>) {
+                self.responses.insert((name.to_string(), qtype), Response { rcode, answers, authorities });
+            }
+        }
+        impl Resolver for Canned {
+            fn query(&self, name: &DnsName, qtype: u16) -> Result<Response, io::Error> {
+                if name.to_string() == "broken.example" {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+                }
+                let response = self.responses.get(&(name.to_string(), qtype));
+                Ok(response.map_or_else(|| Response { rcode: 0, answers: Vec::new(), authorities: Vec::new() }, |response| Response {
+                    rcode: response.rcode,
+                    answers: response.answers.clone(),
+                    authorities: response.authorities.clone(),
+                }))
+            }
+        }
+
+        /// a root zone delegating to the signed `example.` and the unsigned `insecure.`
+        pub fn hierarchy() -> (Canned, Ds) {
+            let root = Zone::new("");
+            let example = Zone::new("example");
+            let mut canned = Canned::default();
+            canned.add("", DNSKEY, 0, root.sign(vec![record("", DNSKEY, DnsRdata::DnskeyRecord(root.key()))]), Vec::new());
+            canned.add("example", 43, 0, root.sign(vec![record("example", 43, DnsRdata::DsRecord(example.ds()))]), Vec::new());
+            canned.add("example", DNSKEY, 0, example.sign(vec![record("example", DNSKEY, DnsRdata::DnskeyRecord(example.key()))]), Vec::new());
+            canned.add("insecure", 43, 0, Vec::new(), root.sign(vec![nsec("insecure", "", &[2, RRSIG, NSEC])]));
+
+            canned.add("www.example", 1, 0, example.sign(vec![a("www.example", [192, 0, 2, 1])]), Vec::new());
+            let mut tampered = example.sign(vec![a("tampered.example", [192, 0, 2, 1])]);
+            tampered[0] = a("tampered.example", [192, 0, 2, 66]);
+            canned.add("tampered.example", 1, 0, tampered, Vec::new());
+            canned.add("host.insecure", 1, 0, vec![a("host.insecure", [192, 0, 2, 2])], Vec::new());
+            canned.add("nope.example", 1, NXDOMAIN, Vec::new(), example.sign(vec![nsec("example", "www.example", &[2, 6, RRSIG, NSEC, DNSKEY])]));
+            // a wildcard answer, signed with the labels of *.example
+            let mut wildcard = example.sign(vec![a("*.example", [192, 0, 2, 3])]);
+            for record in &mut wildcard {
+                record.name = DnsName::from_string("any.example");
+            }
+            canned.add("any.example", 1, 0, wildcard.clone(), Vec::new());
+            canned.add("proven.example", 1, 0, wildcard.into_iter().map(|mut record| {
+                record.name = DnsName::from_string("proven.example");
+                record
+            }).collect(), example.sign(vec![nsec("nope.example", "www.example", &[1, RRSIG, NSEC])]));
+            (canned, root.ds())
+        }
+    }
+
+    fn status(validator: &Validator<util::Canned>, name: &str, qtype: u16) -> Status {
+        let validated = validator.validate(&DnsName::from_string(name), qtype).unwrap();
+        validated.records.into_iter().map(|(_, status)| status).next().or(validated.denial).unwrap()
+    }
+
+    #[test]
+    fn chain_of_trust() {
+        let (canned, anchor) = util::hierarchy();
+        let validator = Validator::with_anchors(canned, vec![anchor], util::NOW);
+        assert_eq!(status(&validator, "www.example", 1), Status::Secure);
+        assert!(matches!(status(&validator, "tampered.example", 1), Status::Bogus(_)));
+        assert_eq!(status(&validator, "host.insecure", 1), Status::Insecure);
+        assert_eq!(status(&validator, "nope.example", 1), Status::Secure);
+        assert!(matches!(status(&validator, "www.example", 28), Status::Bogus(_)));
+        // without an NSEC showing there's no any.example, the wildcard can't be trusted
+        assert!(matches!(status(&validator, "any.example", 1), Status::Bogus(_)));
+        assert_eq!(status(&validator, "proven.example", 1), Status::Secure);
+
+        let (canned, anchor) = util::hierarchy();
+        let late = Validator::with_anchors(canned, vec![anchor], util::NOW + 2 * 86400);
+        assert!(matches!(status(&late, "www.example", 1), Status::Bogus(_)));
+    }
+
+    #[test]
+    fn unreachable_chain() {
+        let mut canned = util::Canned::default();
+        canned.add("www.broken.example", 1, 0, vec![util::a("www.broken.example", [192, 0, 2, 1])], Vec::new());
+        let validator = Validator::with_anchors(canned, Vec::new(), util::NOW);
+        assert!(matches!(status(&validator, "www.broken.example", 1), Status::Indeterminate(_)));
+    }
+
+    #[test]
+    fn root_anchors() {
+        let validator = Validator::new(util::Canned::default());
+        let ksk = root_ksk();
+        let anchor = validator.anchors.iter().find(|ds| ds.key_tag == ksk.key_tag()).unwrap();
+        assert_eq!(crypto::ds_digest(anchor.digest_type, &DnsName::from_string("."), &ksk), Some(anchor.digest.clone()));
+        assert_eq!(validator.anchors.len(), 2);
+    }
+}
@@ -0,0 +1,279 @@
+//! A WHOIS client (RFC 3912): a query line goes out over TCP port 43 and free-form text comes back.
+//! Every query starts at IANA and follows referrals to the registry (RIR or TLD registry) and from there
+//! to the registrar, picking the interesting fields out of each answer on the way.
+
+use std::{collections::HashSet, io::{self, Read, Write}, net::{TcpStream, ToSocketAddrs}, time::Duration};
+
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::rate_limit::RateLimiter;
+
+pub const IANA: &str = "whois.iana.org:43";
+
+/// how many referrals are followed after asking IANA (registry, then registrar)
+const MAX_REFERRALS: usize = 3;
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The fields we care about from a WHOIS answer. Fields the servers didn't have stay empty.
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WhoisInfo {
+    /// the most specific server that answered
+    pub server: String,
+    /// the registrant or the organization holding the network
+    pub org: String,
+    /// e.g. `1.1.1.0 - 1.1.1.255`
+    pub netrange: String,
+    pub abuse_contact: String,
+    pub created: String,
+    pub expires: String,
+}
+impl std::fmt::Display for WhoisInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut result = vec![format!("whois server: {}", self.server.bold())];
+        for (label, value) in [
+            ("registrant/organization", &self.org),
+            ("network", &self.netrange),
+            ("abuse contact", &self.abuse_contact),
+            ("created", &self.created),
+            ("expires", &self.expires),
+        ] {
+            if !value.is_empty() {
+                result.push(format!("{label}: {}", value.bold()));
+            }
+        }
+        write!(f, "\t{}", result.join("\n\t"))
+    }
+}
+impl WhoisInfo {
+    /// takes every field `other` has, since later servers know more than the ones that referred to them
+    fn update(&mut self, other: Self) {
+        for (field, value) in [
+            (&mut self.org, other.org),
+            (&mut self.netrange, other.netrange),
+            (&mut self.abuse_contact, other.abuse_contact),
+            (&mut self.created, other.created),
+            (&mut self.expires, other.expires),
+        ] {
+            if !value.is_empty() {
+                *field = value;
+            }
+        }
+    }
+}
+
+/// the `key: value` lines of an answer, with the keys lowercased. comments (`%`, `#`) are skipped.
+fn fields(answer: &str) -> Vec<(String, String)> {
+    answer.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with(['%', '#']))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .filter(|(key, value)| !key.is_empty() && !value.is_empty())
+        .collect()
+}
+
+/// the first value found for any of `keys`, in the order the keys are given
+fn first_of(fields: &[(String, String)], keys: &[&str]) -> String {
+    keys.iter()
+        .find_map(|key| fields.iter().find(|(field, _)| field == key))
+        .map(|(_, value)| value.clone())
+        .unwrap_or_default()
+}
+
+/// picks the interesting fields out of an answer. ARIN, the RIPE-style registries and gTLD registries
+/// all name them differently.
+fn parse(answer: &str) -> WhoisInfo {
+    let fields = fields(answer);
+    let mut abuse_contact = first_of(&fields, &["orgabuseemail", "abuse-mailbox", "registrar abuse contact email"]);
+    if abuse_contact.is_empty() {
+        // RIPE and APNIC put it in a comment: % Abuse contact for '1.1.1.0 - 1.1.1.255' is 'abuse@apnic.net'
+        abuse_contact = answer.lines()
+            .find_map(|line| line.strip_prefix("% Abuse contact for ")?.split(" is ").nth(1))
+            .map(|contact| contact.trim().trim_matches('\'').to_string())
+            .unwrap_or_default();
+    }
+    WhoisInfo {
+        server: String::new(),
+        org: first_of(&fields, &["orgname", "org-name", "registrant organization", "organisation", "owner", "netname"]),
+        netrange: first_of(&fields, &["netrange", "inetnum", "inet6num", "cidr"]),
+        abuse_contact,
+        created: first_of(&fields, &["regdate", "creation date", "created"]),
+        expires: first_of(&fields, &["registry expiry date", "registrar registration expiration date", "expires", "paid-till"]),
+    }
+}
+
+/// where an answer points to for more details, as `host:port`
+fn referral(answer: &str) -> Option<String> {
+    let fields = fields(answer);
+    let server = first_of(&fields, &["refer", "whois", "referralserver", "registrar whois server"]);
+    if server.is_empty() {
+        return None;
+    }
+    // ARIN writes `whois://whois.ripe.net`, and sometimes refers to rwhois servers we can't talk to
+    let server = match server.split_once("://") {
+        Some(("whois", rest)) => rest,
+        Some(_) => return None,
+        None => server.as_str(),
+    };
+    let server = server.trim_end_matches('/');
+    Some(if server.contains(':') { server.to_string() } else { format!("{server}:43") })
+}
+
+/// second-level labels that country codes commonly register names under, as in `co.uk` or `com.au`
+const SECOND_LEVEL_SUFFIXES: &[&str] = &[
+    "ac", "co", "com", "edu", "gob", "gov", "govt", "gv", "ltd", "me", "mil", "ne", "net", "nhs", "or", "org", "plc", "sch",
+];
+
+/// the part of `host` that's registered, e.g. `example.co.uk` for `www.example.co.uk`.
+/// a guess: well-known second-level labels under a country code are taken to be public suffixes.
+pub fn registered_domain(host: &str) -> &str {
+    let labels: Vec<&str> = host.trim_end_matches('.').split('.').collect();
+    let keep = match labels.as_slice() {
+        [.., second, tld] if tld.len() == 2 && labels.len() > 2 && SECOND_LEVEL_SUFFIXES.contains(&second.to_ascii_lowercase().as_str()) => 3,
+        _ => 2,
+    };
+    let skip: usize = labels.iter().take(labels.len().saturating_sub(keep)).map(|label| label.len() + 1).sum();
+    &host[skip.min(host.len())..]
+}
+
+/// sends `query` to `server` and reads the whole answer
+fn ask(server: &str, query: &str, limiter: &RateLimiter) -> Result<String, io::Error> {
+    let address = server.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("can't resolve {server}")))?;
+    limiter.wait(server);
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.write_all(format!("{query}\r\n").as_bytes())?;
+
+    let mut answer = Vec::new();
+    stream.read_to_end(&mut answer)?;
+    // most servers answer in ASCII or UTF-8, some in Latin-1
+    Ok(String::from_utf8_lossy(&answer).into_owned())
+}
+
+/// Looks things up starting from a root server, normally [`IANA`].
+pub struct Whois {
+    root: String,
+}
+impl Whois {
+    pub fn new(root: &str) -> Self {
+        Self {
+            root: root.to_string(),
+        }
+    }
+
+    /// looks up a domain or an address, following referrals as far as they go.
+    /// only failing to reach the root server is an error; after that, whatever was found so far is returned.
+    pub fn lookup(&self, query: &str, limiter: &RateLimiter) -> Result<WhoisInfo, io::Error> {
+        let mut info = WhoisInfo::default();
+        let mut server = self.root.clone();
+        let mut visited = HashSet::from([server.clone()]);
+        let mut answer = ask(&server, query, limiter)?;
+        let mut referrals = 0;
+        loop {
+            info.update(parse(&answer));
+            info.server = server.trim_end_matches(":43").to_string();
+
+            if referrals == MAX_REFERRALS {
+                break;
+            }
+            let Some(next) = referral(&answer).filter(|next| visited.insert(next.clone())) else {
+                break;
+            };
+            match ask(&next, query, limiter) {
+                Ok(next_answer) => (server, answer) = (next, next_answer),
+                Err(_) => break,
+            }
+            referrals += 1;
+        }
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod util {
+        use std::{io::BufRead, net::TcpListener, thread::{self, JoinHandle}};
+
+        use super::*;
+
+        /// a WHOIS server answering one connection per answer, in order. the handle yields the queries it got.
+        pub fn serve(answers: Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap().to_string();
+            let handle = thread::spawn(move || {
+                let mut queries = Vec::new();
+                for answer in answers {
+                    let (stream, _) = listener.accept().unwrap();
+                    let mut reader = io::BufReader::new(stream);
+                    let mut query = String::new();
+                    reader.read_line(&mut query).unwrap();
+                    queries.push(query.trim_end().to_string());
+                    reader.into_inner().write_all(answer.as_bytes()).unwrap();
+                }
+                queries
+            });
+            (address, handle)
+        }
+    }
+
+    const APNIC_ANSWER: &str = "% Abuse contact for '1.1.1.0 - 1.1.1.255' is 'helpdesk@apnic.net'\n\n\
+        inetnum:        1.1.1.0 - 1.1.1.255\nnetname:        APNIC-LABS\ndescr:          APNIC and Cloudflare DNS Resolver project\n\
+        country:        AU\nlast-modified:  2023-04-26T22:57:58Z\n\norganisation:   ORG-ARAD1-AP\norg-name:       Research and Development\n";
+
+    #[test]
+    fn follows_referral() {
+        let (apnic, apnic_handle) = util::serve(vec![APNIC_ANSWER.to_string()]);
+        let iana_answer = format!("% IANA WHOIS server\n\nrefer:        {apnic}\n\ninetnum:      1.0.0.0 - 1.255.255.255\norganisation: APNIC\n");
+        let (iana, iana_handle) = util::serve(vec![iana_answer]);
+
+        let info = Whois::new(&iana).lookup("1.1.1.1", &RateLimiter::new(None, 1)).unwrap();
+        assert_eq!(info, WhoisInfo {
+            server: apnic,
+            org: "Research and Development".to_string(),
+            netrange: "1.1.1.0 - 1.1.1.255".to_string(),
+            abuse_contact: "helpdesk@apnic.net".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(iana_handle.join().unwrap(), vec!["1.1.1.1"]);
+        assert_eq!(apnic_handle.join().unwrap(), vec!["1.1.1.1"]);
+    }
+
+    #[test]
+    fn arin_and_gtld_fields() {
+        let arin = "NetRange:       8.8.8.0 - 8.8.8.255\nCIDR:           8.8.8.0/24\nOrgName:        Google LLC\nRegDate:        2023-12-28\n\
+            OrgAbuseEmail:  network-abuse@google.com\nReferralServer: rwhois://rwhois.example.net:4321\n";
+        let info = parse(arin);
+        assert_eq!(info.org, "Google LLC");
+        assert_eq!(info.netrange, "8.8.8.0 - 8.8.8.255");
+        assert_eq!(info.created, "2023-12-28");
+        assert_eq!(info.abuse_contact, "network-abuse@google.com");
+        assert_eq!(referral(arin), None);
+
+        let verisign = "   Domain Name: EXAMPLE.COM\n   Registrar WHOIS Server: whois.iana.org\n   Creation Date: 1995-08-14T04:00:00Z\n   Registry Expiry Date: 2025-08-13T04:00:00Z\n";
+        let info = parse(verisign);
+        assert_eq!(info.created, "1995-08-14T04:00:00Z");
+        assert_eq!(info.expires, "2025-08-13T04:00:00Z");
+        assert_eq!(referral(verisign).as_deref(), Some("whois.iana.org:43"));
+        assert_eq!(referral("ReferralServer: whois://whois.ripe.net/").as_deref(), Some("whois.ripe.net:43"));
+    }
+
+    #[test]
+    fn registered_domains() {
+        assert_eq!(registered_domain("www.example.com"), "example.com");
+        assert_eq!(registered_domain("example.com"), "example.com");
+        assert_eq!(registered_domain("a.b.example.co.uk"), "example.co.uk");
+        assert_eq!(registered_domain("www.example.de"), "example.de");
+        assert_eq!(registered_domain("www.sap.de"), "sap.de");
+        assert_eq!(registered_domain("www.bbc.de"), "bbc.de");
+        assert_eq!(registered_domain("mail.gmx.de"), "gmx.de");
+        assert_eq!(registered_domain("www.abc.net.au"), "abc.net.au");
+        assert_eq!(registered_domain("bbc.co.uk"), "bbc.co.uk");
+        assert_eq!(registered_domain("localhost"), "localhost");
+    }
+}
@@ -0,0 +1,234 @@
+//! Compares what a zone's name servers answer with what its zone file says: every name and type in the
+//! file is asked for, and the answers are checked record by record.
+
+use std::{cmp::Ordering, collections::HashMap, net::{IpAddr, SocketAddr}};
+
+use crate::bulk;
+use crate::dns_client;
+use crate::dns_components::{dns_header, dns_name::DnsName, dns_rr::{self, DnsResourceRecord}};
+use crate::rate_limit::RateLimiter;
+
+const NS: u16 = 2;
+const SOA: u16 = 6;
+const NXDOMAIN: u8 = 3;
+
+/// signatures and denial records change whenever a zone is re-signed, so the file can't be expected to match
+const SKIPPED_TYPES: [u16; 3] = [46, 47, 50];
+
+/// One way the live zone differs from the file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Difference {
+    /// in the file, but not served
+    Missing(DnsResourceRecord),
+    /// served, but not in the file
+    Extra(DnsResourceRecord),
+    /// a record with a different value, when that's the only one missing from its RRset
+    Differs { expected: DnsResourceRecord, found: DnsResourceRecord },
+    /// the same record, with a different TTL
+    Ttl { expected: DnsResourceRecord, found: u32 },
+    /// the server couldn't be asked
+    Failed { name: DnsName, rtype: u16, error: String },
+}
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(record) => write!(f, "missing  {record}"),
+            Self::Extra(record) => write!(f, "extra    {record}"),
+            Self::Differs { expected, found } => write!(
+                f,
+                "differs  {}. {}: {} in the file, {} live",
+                expected.name,
+                dns_rr::type_name(expected.rtype),
+                expected.rdata,
+                found.rdata,
+            ),
+            Self::Ttl { expected, found } => write!(
+                f,
+                "ttl      {}. {} {}: {} in the file, {found} live",
+                expected.name,
+                dns_rr::type_name(expected.rtype),
+                expected.rdata,
+                expected.ttl,
+            ),
+            Self::Failed { name, rtype, error } => write!(f, "failed   {name}. {}: {error}", dns_rr::type_name(*rtype)),
+        }
+    }
+}
+
+fn same_name(a: &DnsName, b: &DnsName) -> bool {
+    a.canonical_cmp(b) == Ordering::Equal
+}
+
+/// compares one RRset from the file with the records served for it
+pub fn compare(expected: &[DnsResourceRecord], found: &[DnsResourceRecord]) -> Vec<Difference> {
+    let same = |a: &DnsResourceRecord, b: &DnsResourceRecord| a.rdata.to_bytes() == b.rdata.to_bytes();
+    let missing: Vec<&DnsResourceRecord> = expected.iter().filter(|e| !found.iter().any(|f| same(e, f))).collect();
+    let extra: Vec<&DnsResourceRecord> = found.iter().filter(|f| !expected.iter().any(|e| same(e, f))).collect();
+
+    let mut differences = Vec::new();
+    if let ([expected], [found]) = (missing.as_slice(), extra.as_slice()) {
+        differences.push(Difference::Differs { expected: (*expected).clone(), found: (*found).clone() });
+    } else {
+        differences.extend(missing.into_iter().cloned().map(Difference::Missing));
+        differences.extend(extra.into_iter().cloned().map(Difference::Extra));
+    }
+    for record in expected {
+        if let Some(live) = found.iter().find(|f| same(record, f) && f.ttl != record.ttl) {
+            differences.push(Difference::Ttl { expected: record.clone(), found: live.ttl });
+        }
+    }
+    differences
+}
+
+/// the records of the file grouped into RRsets, in the order they first appear
+fn rrsets(records: &[DnsResourceRecord]) -> Vec<Vec<DnsResourceRecord>> {
+    let mut sets: Vec<Vec<DnsResourceRecord>> = Vec::new();
+    // where each (lowercased name, type) went in `sets`
+    let mut index: HashMap<(DnsName, u16), usize> = HashMap::new();
+    for record in records.iter().filter(|record| !SKIPPED_TYPES.contains(&record.rtype)) {
+        let i = *index.entry((record.name.to_lowercase(), record.rtype)).or_insert_with(|| {
+            sets.push(Vec::new());
+            sets.len() - 1
+        });
+        sets[i].push(record.clone());
+    }
+    sets
+}
+
+/// asks `server` for one RRset of the file and compares the answer
+fn check_rrset(expected: &[DnsResourceRecord], server: &str, limiter: &RateLimiter) -> Vec<Difference> {
+    let (name, rtype) = (&expected[0].name, expected[0].rtype);
+    let msg = match dns_client::query_dnssec(server, &name.to_string(), rtype, limiter) {
+        Ok(msg) if msg.rcode() == 0 || msg.rcode() == NXDOMAIN => msg,
+        Ok(msg) => return vec![Difference::Failed { name: name.clone(), rtype, error: dns_header::get_error(msg.rcode()).to_string() }],
+        Err(e) => return vec![Difference::Failed { name: name.clone(), rtype, error: e.to_string() }],
+    };
+    let matching = |records: &[DnsResourceRecord]| -> Vec<DnsResourceRecord> {
+        records.iter().filter(|record| record.rtype == rtype && same_name(&record.name, name)).cloned().collect()
+    };
+    // the NS records of a delegation come back as a referral rather than an answer
+    let mut found = matching(msg.answers());
+    if found.is_empty() {
+        found = matching(msg.authorities());
+    }
+    compare(expected, &found)
+}
+
+/// The outcome of checking a zone against one server.
+pub struct Report {
+    /// how many RRsets were asked for
+    pub checked: usize,
+    pub differences: Vec<Difference>,
+}
+
+/// asks `server` for every RRset in `records`, `jobs` at a time
+pub fn check(records: &[DnsResourceRecord], server: &str, limiter: &RateLimiter, jobs: usize, progress: bool) -> Report {
+    let sets = rrsets(records);
+    let mut differences = Vec::new();
+    bulk::run_ordered(&sets, jobs, progress, |set| check_rrset(set, server, limiter), |found| differences.extend(found));
+    Report {
+        checked: sets.len(),
+        differences,
+    }
+}
+
+/// the name servers the file lists for its apex, to ask when no servers are given
+pub fn name_servers(records: &[DnsResourceRecord]) -> Vec<String> {
+    let Some(apex) = records.iter().find(|record| record.rtype == SOA).map(|soa| &soa.name) else {
+        return Vec::new();
+    };
+    records.iter()
+        .filter(|record| record.rtype == NS && same_name(&record.name, apex))
+        .map(|record| server_address(&record.rdata.to_string()))
+        .collect()
+}
+
+/// `server` with the DNS port added when it has none, e.g. `ns1.example.net:53` or `[2001:db8::53]:53`
+pub fn server_address(server: &str) -> String {
+    let server = server.trim_end_matches('.');
+    if server.parse::<SocketAddr>().is_ok() {
+        return server.to_string();
+    }
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return SocketAddr::new(ip, 53).to_string();
+    }
+    if server.contains(':') { server.to_string() } else { format!("{server}:53") }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::dns_components::zone_file;
+    use crate::test_dns;
+
+    mod util {
+        use super::*;
+
+        pub fn zone(text: &str) -> Vec<DnsResourceRecord> {
+            zone_file::parse(text, &DnsName::from_string("example"), Path::new(".")).unwrap()
+        }
+
+        pub fn rdata(rtype: &str, rdata: &str) -> Vec<u8> {
+            zone(&format!("$TTL 1\n@ {rtype} {rdata}"))[0].rdata.to_bytes()
+        }
+    }
+
+    #[test]
+    fn compare_rrsets() {
+        let expected = util::zone("$TTL 300\n@ NS a\n@ NS b\n");
+        assert_eq!(compare(&expected, &expected), []);
+        let found = util::zone("$TTL 300\n@ NS a\n@ NS c\n");
+        assert_eq!(compare(&expected, &found), [Difference::Differs { expected: expected[1].clone(), found: found[1].clone() }]);
+        let found = util::zone("$TTL 60\n@ NS a\n@ NS b\n@ NS c\n");
+        let differences: Vec<String> = compare(&expected, &found).iter().map(Difference::to_string).collect();
+        assert_eq!(differences, [
+            "extra    example. 60 IN NS c.example.",
+            "ttl      example. NS a.example.: 300 in the file, 60 live",
+            "ttl      example. NS b.example.: 300 in the file, 60 live",
+        ]);
+        assert_eq!(compare(&expected, &[]), [Difference::Missing(expected[0].clone()), Difference::Missing(expected[1].clone())]);
+    }
+
+    #[test]
+    fn group_rrsets() {
+        let records = util::zone("$TTL 300\nwww A 192.0.2.1\n@ NS a\nWWW A 192.0.2.2\nwww AAAA ::1\n@ NS b\n");
+        let sets = rrsets(&records);
+        assert_eq!(sets, [
+            vec![records[0].clone(), records[2].clone()],
+            vec![records[1].clone(), records[4].clone()],
+            vec![records[3].clone()],
+        ]);
+    }
+
+    #[test]
+    fn check_zone() {
+        let records = util::zone("$TTL 300\n@ SOA ns h 1 2 3 4 5\n@ NS ns.example.net.\nwww A 192.0.2.1\nmail 3600 A 192.0.2.2\ngone A 192.0.2.3\n@ RRSIG A 13 1 300 1 0 1 example. AAAA\n");
+        let (server, handle) = test_dns::serve(5, |question| match question.qname.to_string().as_str() {
+            "example" if question.qtype == SOA => Some(vec![(SOA, util::rdata("SOA", "ns h 1 2 3 4 5"))]),
+            "example" => Some(vec![(NS, DnsName::from_string("ns.example.net").to_bytes())]),
+            "www.example" => Some(vec![(1, vec![192, 0, 2, 1])]),
+            "mail.example" => Some(vec![(1, vec![192, 0, 2, 9])]),
+            _ => None,
+        });
+        let report = check(&records, &server, &RateLimiter::new(None, 1), 1, false);
+        assert_eq!(handle.join().unwrap(), ["example/6", "example/2", "www.example/1", "mail.example/1", "gone.example/1"]);
+
+        assert_eq!(report.checked, 5);
+        let differences: Vec<String> = report.differences.iter().map(Difference::to_string).collect();
+        assert_eq!(differences, [
+            "differs  mail.example. A: 192.0.2.2 in the file, 192.0.2.9 live",
+            "missing  gone.example. 300 IN A 192.0.2.3",
+        ]);
+        assert_eq!(name_servers(&records), ["ns.example.net:53"]);
+    }
+
+    #[test]
+    fn server_addresses() {
+        assert_eq!(server_address("ns1.example.net."), "ns1.example.net:53");
+        assert_eq!(server_address("192.0.2.53"), "192.0.2.53:53");
+        assert_eq!(server_address("2001:db8::53"), "[2001:db8::53]:53");
+        assert_eq!(server_address("127.0.0.1:5353"), "127.0.0.1:5353");
+    }
+}